@@ -0,0 +1,209 @@
+use std::io::{BufRead, Seek};
+use std::sync::Arc;
+use std::time::Duration;
+
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, DynamicImage, Frames, ImageFormat};
+
+// Frames of an animated GIF, APNG or WebP, composed on the full canvas
+pub struct Animation {
+    pub frames: Vec<Arc<DynamicImage>>,
+    pub delays: Vec<Duration>,
+}
+
+impl Animation {
+    // Frame shown at the shared frame index, short animations hold their last frame
+    pub fn frame_index(&self, frame: usize) -> usize {
+        frame.min(self.frames.len() - 1)
+    }
+
+    // Still GIFs are decoded as a single frame
+    pub fn is_animated(&self) -> bool {
+        self.frames.len() > 1
+    }
+}
+
+// Browsers show frames with a delay of 10 ms or less at 100 ms, many files rely on it
+fn frame_delay(delay: image::Delay) -> Duration {
+    let delay = Duration::from(delay);
+    if delay <= Duration::from_millis(10) {
+        Duration::from_millis(100)
+    } else {
+        delay
+    }
+}
+
+// Decode every frame of a GIF, APNG or animated WebP, None for other files (and still PNG or WebP)
+pub fn decode_from<R: BufRead + Seek>(reader: R, format: ImageFormat) -> Option<Animation> {
+    let frames = match format {
+        ImageFormat::Gif => GifDecoder::new(reader).ok()?.into_frames(),
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(reader).ok()?;
+            if !decoder.is_apng().ok()? {
+                return None;
+            }
+            decoder.apng().ok()?.into_frames()
+        }
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(reader).ok()?;
+            if !decoder.has_animation() {
+                return None;
+            }
+            decoder.into_frames()
+        }
+        _ => return None,
+    };
+    collect(frames)
+}
+
+fn collect(frames: Frames) -> Option<Animation> {
+    let mut animation = Animation {
+        frames: Vec::new(),
+        delays: Vec::new(),
+    };
+    for frame in frames {
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(e) => {
+                println!("Failed to decode animation frame: {e}");
+                break;
+            }
+        };
+        animation.delays.push(frame_delay(frame.delay()));
+        animation
+            .frames
+            .push(Arc::new(DynamicImage::ImageRgba8(frame.into_buffer())));
+    }
+    if animation.frames.is_empty() {
+        None
+    } else {
+        Some(animation)
+    }
+}
+
+// Shared playback position of every animated cell and sequence
+#[derive(Debug, Default)]
+pub struct Playback {
+    pub playing: bool,
+    pub frame: usize,
+    elapsed: Duration,
+}
+
+impl Playback {
+    // Move forward by `delta` given the delay of each frame of the timeline.
+    // Returns true when the frame changed.
+    pub fn advance(&mut self, delta: Duration, delays: &[Duration]) -> bool {
+        if !self.playing || delays.is_empty() {
+            return false;
+        }
+        let start = self.frame;
+        self.frame %= delays.len();
+        self.elapsed += delta;
+        // Don't try to catch up more than one loop after a long stall
+        let mut steps = 0;
+        while self.elapsed >= delays[self.frame] && steps < delays.len() {
+            self.elapsed -= delays[self.frame];
+            self.frame = (self.frame + 1) % delays.len();
+            steps += 1;
+        }
+        if steps == delays.len() {
+            self.elapsed = Duration::ZERO;
+        }
+        self.frame != start
+    }
+
+    // Jump to a frame, e.g. from the timeline
+    pub fn seek(&mut self, frame: usize) {
+        self.frame = frame;
+        self.elapsed = Duration::ZERO;
+    }
+
+    // Step forward or backward by `offset` frames, wrapping around
+    pub fn step(&mut self, offset: isize, frame_count: usize) {
+        if frame_count == 0 {
+            return;
+        }
+        self.frame = (self.frame as isize + offset).rem_euclid(frame_count as isize) as usize;
+        self.elapsed = Duration::ZERO;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::codecs::gif::GifEncoder;
+    use image::{Delay, Frame, Rgba, RgbaImage};
+
+    use super::*;
+
+    fn gif(colors: &[u8], delay_ms: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut bytes);
+            let frames = colors.iter().map(|c| {
+                Frame::from_parts(
+                    RgbaImage::from_pixel(4, 2, Rgba([*c, 0, 0, 255])),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(delay_ms, 1),
+                )
+            });
+            encoder.encode_frames(frames).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn gif_frames_are_decoded() {
+        let animation = decode_from(Cursor::new(gif(&[0, 120, 250], 40)), ImageFormat::Gif).unwrap();
+        assert_eq!(animation.frames.len(), 3);
+        assert_eq!(animation.delays, vec![Duration::from_millis(40); 3]);
+        assert_eq!(animation.frames[1].width(), 4);
+        assert_eq!(animation.frames[2].to_rgba8().get_pixel(0, 0).0[0], 250);
+        assert_eq!(animation.frame_index(7), 2);
+        assert!(animation.is_animated());
+    }
+
+    #[test]
+    fn still_images_are_not_animations() {
+        let still = decode_from(Cursor::new(gif(&[10], 40)), ImageFormat::Gif).unwrap();
+        assert!(!still.is_animated());
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::new(2, 2))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        assert!(decode_from(Cursor::new(png), ImageFormat::Png).is_none());
+    }
+
+    #[test]
+    fn zero_delay_uses_browser_default() {
+        let animation = decode_from(Cursor::new(gif(&[0, 250], 0)), ImageFormat::Gif).unwrap();
+        assert_eq!(animation.delays[0], Duration::from_millis(100));
+    }
+
+    #[test]
+    fn playback_follows_frame_delays() {
+        let delays = [Duration::from_millis(100), Duration::from_millis(50)];
+        let mut playback = Playback::default();
+        assert!(!playback.advance(Duration::from_millis(500), &delays));
+
+        playback.playing = true;
+        assert!(!playback.advance(Duration::from_millis(60), &delays));
+        assert!(playback.advance(Duration::from_millis(60), &delays));
+        assert_eq!(playback.frame, 1);
+        assert!(playback.advance(Duration::from_millis(30), &delays));
+        assert_eq!(playback.frame, 0);
+    }
+
+    #[test]
+    fn stepping_wraps() {
+        let mut playback = Playback::default();
+        playback.step(-1, 5);
+        assert_eq!(playback.frame, 4);
+        playback.step(2, 5);
+        assert_eq!(playback.frame, 1);
+    }
+}
@@ -0,0 +1,389 @@
+use std::io::{BufRead, Seek};
+use std::sync::Arc;
+
+use image::{ImageDecoder, ImageFormat, ImageReader, Rgba32FImage};
+use moxcms::{ColorProfile, Layout, ProfileText, ToneReprCurve, TransformF32Executor, TransformOptions};
+
+// Color spaces that can be assigned to an image. Stored values are converted to linear sRGB
+// (the working space, textures are displayed as linear data) before the display transform.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorSpace {
+    Srgb,
+    LinearSrgb,
+    DisplayP3,
+    AdobeRgb,
+    Rec2020,
+    LinearRec2020,
+}
+
+// CIE xy chromaticities of the red, green and blue primaries, every space uses the D65 white point
+const SRGB_PRIMARIES: [[f64; 2]; 3] = [[0.64, 0.33], [0.30, 0.60], [0.15, 0.06]];
+const DISPLAY_P3_PRIMARIES: [[f64; 2]; 3] = [[0.680, 0.320], [0.265, 0.690], [0.150, 0.060]];
+const ADOBE_RGB_PRIMARIES: [[f64; 2]; 3] = [[0.64, 0.33], [0.21, 0.71], [0.15, 0.06]];
+const REC2020_PRIMARIES: [[f64; 2]; 3] = [[0.708, 0.292], [0.170, 0.797], [0.131, 0.046]];
+const D65: [f64; 2] = [0.3127, 0.3290];
+
+// Transfer functions of the known color spaces, numbered like in the display shader
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transfer {
+    Linear,
+    Srgb,
+    AdobeRgb, // pure gamma 563/256
+    Bt1886,   // gamma 2.4 display curve of BT.709 and BT.2020
+}
+
+impl Transfer {
+    // Stored value -> linear light, for positive values
+    fn decode(self, value: f32) -> f32 {
+        match self {
+            Transfer::Linear => value,
+            Transfer::Srgb => crate::display::srgb_to_linear(value),
+            Transfer::AdobeRgb => value.powf(563. / 256.),
+            Transfer::Bt1886 => value.powf(2.4),
+        }
+    }
+}
+
+impl ColorSpace {
+    pub const ALL: [ColorSpace; 6] = [
+        ColorSpace::Srgb,
+        ColorSpace::LinearSrgb,
+        ColorSpace::DisplayP3,
+        ColorSpace::AdobeRgb,
+        ColorSpace::Rec2020,
+        ColorSpace::LinearRec2020,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "sRGB",
+            ColorSpace::LinearSrgb => "Linear sRGB",
+            ColorSpace::DisplayP3 => "Display P3",
+            ColorSpace::AdobeRgb => "Adobe RGB",
+            ColorSpace::Rec2020 => "Rec. 2020",
+            ColorSpace::LinearRec2020 => "Linear Rec. 2020",
+        }
+    }
+
+    // Integer formats are sRGB encoded, float formats (EXR, HDR) store linear values
+    pub fn default_for(linear: bool) -> ColorSpace {
+        if linear {
+            ColorSpace::LinearSrgb
+        } else {
+            ColorSpace::Srgb
+        }
+    }
+
+    fn primaries(self) -> [[f64; 2]; 3] {
+        match self {
+            ColorSpace::Srgb | ColorSpace::LinearSrgb => SRGB_PRIMARIES,
+            ColorSpace::DisplayP3 => DISPLAY_P3_PRIMARIES,
+            ColorSpace::AdobeRgb => ADOBE_RGB_PRIMARIES,
+            ColorSpace::Rec2020 | ColorSpace::LinearRec2020 => REC2020_PRIMARIES,
+        }
+    }
+
+    pub fn transfer(self) -> Transfer {
+        match self {
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => Transfer::Srgb,
+            ColorSpace::LinearSrgb | ColorSpace::LinearRec2020 => Transfer::Linear,
+            ColorSpace::AdobeRgb => Transfer::AdobeRgb,
+            ColorSpace::Rec2020 => Transfer::Bt1886,
+        }
+    }
+
+    // Transfer function: stored value -> linear light. Negative values (out of gamut) are mirrored.
+    fn decode(self, value: f32) -> f32 {
+        self.transfer().decode(value.abs()).copysign(value)
+    }
+
+    // Linear RGB of this space -> linear sRGB
+    pub fn to_srgb_matrix(self) -> [[f32; 3]; 3] {
+        let to_xyz = rgb_to_xyz(self.primaries());
+        let from_xyz = invert(rgb_to_xyz(SRGB_PRIMARIES));
+        multiply(from_xyz, to_xyz).map(|row| row.map(|v| v as f32))
+    }
+
+    // Stored values -> linear sRGB
+    pub fn to_working_space(self, rgb: [f32; 3]) -> [f32; 3] {
+        apply(&self.to_srgb_matrix(), rgb.map(|v| self.decode(v)))
+    }
+}
+
+fn apply(matrix: &[[f32; 3]; 3], rgb: [f32; 3]) -> [f32; 3] {
+    matrix.map(|row| row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2])
+}
+
+// Matrix from linear RGB to CIE XYZ given the primaries, with the D65 white at Y = 1
+fn rgb_to_xyz(primaries: [[f64; 2]; 3]) -> [[f64; 3]; 3] {
+    let xyz = |[x, y]: [f64; 2]| [x / y, 1., (1. - x - y) / y];
+    let [r, g, b] = primaries.map(xyz);
+    let columns = [[r[0], g[0], b[0]], [r[1], g[1], b[1]], [r[2], g[2], b[2]]];
+    // Scale each primary so that R = G = B = 1 gives the white point
+    let white = xyz(D65);
+    let inverse = invert(columns);
+    let scale = inverse.map(|row| row[0] * white[0] + row[1] * white[1] + row[2] * white[2]);
+    columns.map(|row| [row[0] * scale[0], row[1] * scale[1], row[2] * scale[2]])
+}
+
+fn invert(m: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let determinant = m[0][0] * cofactor(1, 2, 1, 2) - m[0][1] * cofactor(1, 2, 0, 2) + m[0][2] * cofactor(1, 2, 0, 1);
+    let adjugate = [
+        [cofactor(1, 2, 1, 2), -cofactor(0, 2, 1, 2), cofactor(0, 1, 1, 2)],
+        [-cofactor(1, 2, 0, 2), cofactor(0, 2, 0, 2), -cofactor(0, 1, 0, 2)],
+        [cofactor(1, 2, 0, 1), -cofactor(0, 2, 0, 1), cofactor(0, 1, 0, 1)],
+    ];
+    adjugate.map(|row| row.map(|v| v / determinant))
+}
+
+fn multiply(a: [[f64; 3]; 3], b: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut product = [[0.; 3]; 3];
+    for (i, row) in product.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    product
+}
+
+// Color space of the stored values of an image: a known one, or an embedded ICC profile
+#[derive(Clone)]
+pub enum ImageSpace {
+    Known(ColorSpace),
+    Icc {
+        name: String,
+        transform: Arc<TransformF32Executor>, // profile -> linear Rec. 2020, see convert_icc
+    },
+}
+
+impl ImageSpace {
+    pub fn name(&self) -> &str {
+        match self {
+            ImageSpace::Known(space) => space.name(),
+            ImageSpace::Icc { name, .. } => name,
+        }
+    }
+
+    // Stored values are shown as is: sRGB integers, linear floats
+    pub fn is_native(&self, linear: bool) -> bool {
+        matches!(self, ImageSpace::Known(space) if *space == ColorSpace::default_for(linear))
+    }
+
+    // Convert the RGB stored values (integers normalized to [0, 1]) to linear sRGB, alpha is kept
+    pub fn to_working_space(&self, rgba: &mut Rgba32FImage) {
+        match self {
+            ImageSpace::Known(space) => {
+                let matrix = space.to_srgb_matrix();
+                for pixel in rgba.pixels_mut() {
+                    let [r, g, b, a] = pixel.0;
+                    let [r, g, b] = apply(&matrix, [r, g, b].map(|v| space.decode(v)));
+                    pixel.0 = [r, g, b, a];
+                }
+            }
+            ImageSpace::Icc { transform, .. } => {
+                let source: Vec<f32> = rgba.pixels().flat_map(|p| [p.0[0], p.0[1], p.0[2]]).collect();
+                let Some(converted) = convert_icc(transform, &source) else {
+                    return;
+                };
+                for (pixel, rgb) in rgba.pixels_mut().zip(converted.chunks_exact(3)) {
+                    pixel.0[..3].copy_from_slice(rgb);
+                }
+            }
+        }
+    }
+
+    // Conversion of an ICC profile sampled on a size x size x size grid of stored values, red varying
+    // fastest, for the display shader to interpolate. None for the known spaces, converted with their matrix.
+    pub fn lut(&self, size: usize) -> Option<Vec<[f32; 3]>> {
+        let ImageSpace::Icc { transform, .. } = self else {
+            return None;
+        };
+        let step = 1. / (size - 1).max(1) as f32;
+        let source: Vec<f32> = (0..size * size * size)
+            .flat_map(|index| [index % size, index / size % size, index / (size * size)].map(|i| i as f32 * step))
+            .collect();
+        let converted = convert_icc(transform, &source)?;
+        Some(converted.chunks_exact(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect())
+    }
+}
+
+// RGB triplets of stored values -> linear sRGB. The transform only clips colors out of the wide
+// Rec. 2020 gamut, the matrix to sRGB keeps the others as negative values like the known spaces.
+fn convert_icc(transform: &Arc<TransformF32Executor>, source: &[f32]) -> Option<Vec<f32>> {
+    let mut converted = vec![0.; source.len()];
+    if let Err(e) = transform.transform(source, &mut converted) {
+        println!("Failed to convert colors with the ICC profile: {e}");
+        return None;
+    }
+    let matrix = ColorSpace::LinearRec2020.to_srgb_matrix();
+    for rgb in converted.chunks_exact_mut(3) {
+        rgb.copy_from_slice(&apply(&matrix, [rgb[0], rgb[1], rgb[2]]));
+    }
+    Some(converted)
+}
+
+// ICC profile embedded in an image read with a fresh decoder, for the images that aren't decoded
+// by the image crate (layers and animations)
+pub fn embedded<R: BufRead + Seek>(reader: R, format: ImageFormat) -> Option<Vec<u8>> {
+    let mut decoder = ImageReader::with_format(reader, format).into_decoder().ok()?;
+    decoder.icc_profile().ok()?
+}
+
+// The known color space matching the profile, so the usual sRGB profiles are shown as is,
+// otherwise the profile itself. None for profiles that aren't RGB.
+pub fn from_icc(icc: &[u8]) -> Option<ImageSpace> {
+    let profile = ColorProfile::new_from_slice(icc).ok()?;
+    let transform = profile
+        .create_transform_f32(
+            Layout::Rgb,
+            &working_profile(),
+            Layout::Rgb,
+            TransformOptions::default(),
+        )
+        .ok()?;
+
+    // Compare the conversions of a few colors, profiles only store 16-bit (or coarser) curves
+    const STEPS: [f32; 5] = [0., 0.2, 0.5, 0.8, 1.];
+    let samples: Vec<[f32; 3]> = STEPS
+        .iter()
+        .flat_map(|r| STEPS.iter().flat_map(move |g| STEPS.iter().map(move |b| [*r, *g, *b])))
+        .collect();
+    let source: Vec<f32> = samples.iter().flatten().copied().collect();
+    let converted = convert_icc(&transform, &source)?;
+    let known = ColorSpace::ALL.into_iter().find(|space| {
+        samples.iter().zip(converted.chunks_exact(3)).all(|(sample, rgb)| {
+            let expected = space.to_working_space(*sample);
+            (0..3).all(|c| (expected[c] - rgb[c]).abs() < 0.01)
+        })
+    });
+    if let Some(space) = known {
+        return Some(ImageSpace::Known(space));
+    }
+    Some(ImageSpace::Icc {
+        name: description(&profile).unwrap_or_else(|| "ICC profile".to_string()),
+        transform,
+    })
+}
+
+// Linear Rec. 2020 as an ICC profile
+fn working_profile() -> ColorProfile {
+    let mut profile = ColorProfile::new_bt2020();
+    let linear = ToneReprCurve::Parametric(vec![1.]);
+    profile.red_trc = Some(linear.clone());
+    profile.green_trc = Some(linear.clone());
+    profile.blue_trc = Some(linear);
+    profile.cicp = None;
+    profile
+}
+
+fn description(profile: &ColorProfile) -> Option<String> {
+    let text = match profile.description.as_ref()? {
+        ProfileText::PlainString(text) => text.clone(),
+        ProfileText::Localizable(texts) => texts.first()?.value.clone(),
+        ProfileText::Description(description) => description.ascii_string.clone(),
+    };
+    let text = text.trim_matches(['\0', ' ']).to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    fn approx(a: [f32; 3], b: [f32; 3]) -> bool {
+        (0..3).all(|c| (a[c] - b[c]).abs() < 1e-3)
+    }
+
+    #[test]
+    fn conversions_to_linear_srgb() {
+        let half = crate::display::srgb_to_linear(0.5);
+        assert!(approx(ColorSpace::Srgb.to_working_space([0.5, 0., 1.]), [half, 0., 1.]));
+        assert!(approx(
+            ColorSpace::LinearSrgb.to_working_space([2., 0.5, 0.]),
+            [2., 0.5, 0.]
+        ));
+        // White stays white, saturated wide gamut colors fall out of the sRGB gamut
+        for space in ColorSpace::ALL {
+            assert!(approx(space.to_working_space([1., 1., 1.]), [1., 1., 1.]));
+        }
+        let red = ColorSpace::DisplayP3.to_working_space([1., 0., 0.]);
+        assert!(approx(red, [1.2249, -0.0420, -0.0197]));
+        let green = ColorSpace::LinearRec2020.to_working_space([0., 1., 0.]);
+        assert!(approx(green, [-0.5876, 1.1329, -0.1006]));
+        // Rec. 2020 values are decoded with the BT.1886 gamma 2.4
+        let gray = 0.5_f32.powf(2.4);
+        assert!(approx(ColorSpace::Rec2020.to_working_space([0.5; 3]), [gray; 3]));
+    }
+
+    #[test]
+    fn known_profiles_are_recognized() {
+        let known = |profile: ColorProfile| match from_icc(&profile.encode().unwrap()) {
+            Some(ImageSpace::Known(space)) => Some(space),
+            _ => None,
+        };
+        assert_eq!(known(ColorProfile::new_srgb()), Some(ColorSpace::Srgb));
+        assert_eq!(known(ColorProfile::new_display_p3()), Some(ColorSpace::DisplayP3));
+        assert_eq!(known(ColorProfile::new_adobe_rgb()), Some(ColorSpace::AdobeRgb));
+        assert!(from_icc(b"not a profile").is_none());
+    }
+
+    #[test]
+    fn icc_profiles_keep_colors_out_of_the_srgb_gamut() {
+        // Display P3 with a gamma 1.8, converted with the profile instead of the matrix
+        let mut profile = ColorProfile::new_display_p3();
+        let gamma = ToneReprCurve::Parametric(vec![1.8]);
+        profile.red_trc = Some(gamma.clone());
+        profile.green_trc = Some(gamma.clone());
+        profile.blue_trc = Some(gamma);
+        profile.cicp = None;
+        let space = from_icc(&profile.encode().unwrap()).unwrap();
+        assert!(matches!(space, ImageSpace::Icc { .. }));
+
+        let mut rgba = Rgba32FImage::from_pixel(1, 1, Rgba([1., 0., 0., 1.]));
+        space.to_working_space(&mut rgba);
+        let [r, g, b, _] = rgba.get_pixel(0, 0).0;
+        assert!([r, g, b]
+            .iter()
+            .zip([1.2249, -0.0420, -0.0197])
+            .all(|(a, b)| (a - b).abs() < 0.01));
+    }
+
+    #[test]
+    fn native_spaces_are_left_as_is() {
+        assert!(ImageSpace::Known(ColorSpace::Srgb).is_native(false));
+        assert!(!ImageSpace::Known(ColorSpace::Srgb).is_native(true));
+        assert!(ImageSpace::Known(ColorSpace::LinearSrgb).is_native(true));
+
+        let mut rgba = Rgba32FImage::from_pixel(1, 1, Rgba([1., 0., 0., 0.5]));
+        ImageSpace::Known(ColorSpace::DisplayP3).to_working_space(&mut rgba);
+        let [r, g, b, a] = rgba.get_pixel(0, 0).0;
+        assert!(approx([r, g, b], [1.2249, -0.0420, -0.0197]));
+        assert_eq!(a, 0.5);
+    }
+
+    #[test]
+    fn icc_profiles_are_sampled_for_the_shader() {
+        assert!(ImageSpace::Known(ColorSpace::DisplayP3).lut(2).is_none());
+
+        // A gamma 1.8 profile isn't one of the known spaces
+        let mut profile = ColorProfile::new_srgb();
+        let gamma = ToneReprCurve::Parametric(vec![1.8]);
+        profile.red_trc = Some(gamma.clone());
+        profile.green_trc = Some(gamma.clone());
+        profile.blue_trc = Some(gamma);
+        profile.cicp = None;
+        let space = from_icc(&profile.encode().unwrap()).unwrap();
+        assert!(matches!(space, ImageSpace::Icc { .. }));
+
+        let lut = space.lut(3).unwrap();
+        assert_eq!(lut.len(), 27);
+        assert!(approx(lut[0], [0., 0., 0.]));
+        assert!(approx(lut[26], [1., 1., 1.]));
+        // Red varies fastest, the middle of the red axis is 0.5^1.8
+        let half = 0.5_f32.powf(1.8);
+        assert!(approx(lut[1], [half, 0., 0.]));
+    }
+}
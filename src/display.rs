@@ -0,0 +1,1078 @@
+use std::sync::Arc;
+
+use image::{ColorType, DynamicImage, Rgba32FImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::colorspace::ImageSpace;
+
+// Tone-mapping operator used to bring HDR values in the displayable range.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ToneMapping {
+    Clamp,
+    Reinhard,
+    Aces,
+    Filmic,
+}
+
+// Display transform: value * 2^exposure + offset, then tone-mapping, then gamma.
+// Applied on linear values, the texture is then displayed as linear data.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct ToneSettings {
+    pub exposure: f32,
+    pub offset: f32,
+    pub gamma: f32,
+    pub tonemapping: ToneMapping,
+}
+
+impl Default for ToneSettings {
+    fn default() -> Self {
+        ToneSettings {
+            exposure: 0.,
+            offset: 0.,
+            gamma: 1.,
+            tonemapping: ToneMapping::Clamp,
+        }
+    }
+}
+
+impl ToneSettings {
+    // Identity settings let the raw texture be uploaded untouched (values above 1.0 included)
+    pub fn is_identity(&self) -> bool {
+        *self == ToneSettings::default()
+    }
+
+    pub fn apply(&self, value: f32) -> f32 {
+        let exposed = value * self.exposure.exp2() + self.offset;
+        let mapped = match self.tonemapping {
+            ToneMapping::Clamp => exposed,
+            ToneMapping::Reinhard => {
+                let v = exposed.max(0.);
+                v / (1. + v)
+            }
+            // Narkowicz fit of the ACES filmic curve
+            ToneMapping::Aces => {
+                let v = exposed.max(0.);
+                (v * (2.51 * v + 0.03)) / (v * (2.43 * v + 0.59) + 0.14)
+            }
+            // Hable (Uncharted 2) curve with its usual white point and exposure bias
+            ToneMapping::Filmic => {
+                let white = 11.2;
+                hable(2. * exposed.max(0.)) / hable(white)
+            }
+        };
+        mapped.clamp(0., 1.).powf(1. / self.gamma.max(0.01))
+    }
+}
+
+fn hable(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1. / 2.4) - 0.055
+    }
+}
+
+// Float formats (EXR, HDR) store linear values, integer formats are sRGB encoded
+pub fn is_linear(image: &DynamicImage) -> bool {
+    matches!(image.color(), ColorType::Rgb32F | ColorType::Rgba32F)
+}
+
+// Convert the decoded image to linear RGBA values.
+// `levels` first stretches the stored values (integers normalized to [0, 1], floats as is) to [0, 1].
+pub fn to_linear_rgba(image: &DynamicImage, levels: Option<(f32, f32)>) -> Rgba32FImage {
+    let mut rgba = image.to_rgba32f();
+    let linear = is_linear(image);
+    if linear && levels.is_none() {
+        return rgba;
+    }
+    for pixel in rgba.pixels_mut() {
+        for channel in &mut pixel.0[..3] {
+            if let Some((low, high)) = levels {
+                *channel = (*channel - low) / (high - low).max(f32::EPSILON);
+            }
+            if !linear {
+                *channel = srgb_to_linear(*channel);
+            }
+        }
+    }
+    rgba
+}
+
+// Same as to_linear_rgba, with the stored values converted from `color_space` to linear sRGB
+// instead of being decoded from sRGB (integers) or used as is (floats)
+pub fn to_working_rgba(
+    image: &DynamicImage,
+    levels: Option<(f32, f32)>,
+    color_space: Option<&ImageSpace>,
+) -> Rgba32FImage {
+    let Some(color_space) = color_space else {
+        return to_linear_rgba(image, levels);
+    };
+    let mut rgba = image.to_rgba32f();
+    if let Some((low, high)) = levels {
+        for pixel in rgba.pixels_mut() {
+            for channel in &mut pixel.0[..3] {
+                *channel = (*channel - low) / (high - low).max(f32::EPSILON);
+            }
+        }
+    }
+    color_space.to_working_space(&mut rgba);
+    rgba
+}
+
+// Largest stored value: 255 or 65535 for integer images, 1 for float images (stored as is)
+pub fn stored_unit(image: &DynamicImage) -> f32 {
+    match image.color() {
+        ColorType::L8 | ColorType::La8 | ColorType::Rgb8 | ColorType::Rgba8 => 255.,
+        ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => 65535.,
+        _ => 1.,
+    }
+}
+
+// Range of the RGB stored values used to stretch an image (integers normalized to [0, 1], floats as is).
+// `percentile` ignores this percentage of the values at each end, 0 gives the min and max.
+// Non finite values are ignored, large images are subsampled.
+pub fn level_range(image: &DynamicImage, percentile: f32) -> Option<(f32, f32)> {
+    const MAX_SAMPLES: usize = 1 << 20;
+    let rgba = image.to_rgba32f();
+    let stride = (rgba.width() as usize * rgba.height() as usize / MAX_SAMPLES).max(1);
+    let mut values: Vec<f32> = rgba
+        .pixels()
+        .step_by(stride)
+        .flat_map(|p| [p.0[0], p.0[1], p.0[2]])
+        .filter(|v| v.is_finite())
+        .collect();
+    if values.is_empty() {
+        return None;
+    }
+    let last = values.len() - 1;
+    let skipped = ((percentile.clamp(0., 49.) / 100.) * last as f32).round() as usize;
+    let low = *values.select_nth_unstable_by(skipped, f32::total_cmp).1;
+    let high = *values.select_nth_unstable_by(last - skipped, f32::total_cmp).1;
+    Some((low, high))
+}
+
+// Channels shown in every cell: all of them, a single one as grayscale, or a custom RGB mapping.
+// Channel indices are 0: R, 1: G, 2: B, 3: A.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ChannelView {
+    #[default]
+    Rgb,
+    Single(usize),
+    Luma,
+    Swizzle([usize; 3]),
+}
+
+const CHANNEL_NAMES: [char; 4] = ['R', 'G', 'B', 'A'];
+
+impl ChannelView {
+    // Order used when cycling with the keyboard
+    pub const CYCLE: [ChannelView; 6] = [
+        ChannelView::Rgb,
+        ChannelView::Single(0),
+        ChannelView::Single(1),
+        ChannelView::Single(2),
+        ChannelView::Single(3),
+        ChannelView::Luma,
+    ];
+
+    pub fn name(&self) -> String {
+        match self {
+            ChannelView::Rgb => "RGB".into(),
+            ChannelView::Single(channel) => CHANNEL_NAMES[*channel].into(),
+            ChannelView::Luma => "Luma".into(),
+            ChannelView::Swizzle(mapping) => mapping.iter().map(|c| CHANNEL_NAMES[*c]).collect(),
+        }
+    }
+
+    // Parse a mapping like "BGR" or "aaa", one letter among R, G, B and A per output channel
+    pub fn parse_swizzle(text: &str) -> Option<ChannelView> {
+        let mut mapping = [0; 3];
+        let mut letters = text.trim().chars();
+        for output in &mut mapping {
+            let letter = letters.next()?.to_ascii_uppercase();
+            *output = CHANNEL_NAMES.iter().position(|name| *name == letter)?;
+        }
+        if letters.next().is_some() {
+            return None;
+        }
+        Some(ChannelView::Swizzle(mapping))
+    }
+
+    // Next (or previous) view of the keyboard cycle, a custom mapping goes back to RGB
+    pub fn cycle(&self, step: i32) -> ChannelView {
+        let len = Self::CYCLE.len() as i32;
+        match Self::CYCLE.iter().position(|view| view == self) {
+            Some(index) => Self::CYCLE[(index as i32 + step).rem_euclid(len) as usize],
+            None => ChannelView::Rgb,
+        }
+    }
+
+    // Apply on linear RGBA values. Alpha is a coverage so it is converted like an encoded value to be
+    // displayed with the gray level of its stored value. Isolated channels are shown opaque.
+    pub fn apply(&self, rgba: [f32; 4]) -> [f32; 4] {
+        let [r, g, b, a] = rgba;
+        let channels = [r, g, b, srgb_to_linear(a)];
+        match self {
+            ChannelView::Rgb => rgba,
+            ChannelView::Single(channel) => {
+                let v = channels[*channel];
+                [v, v, v, 1.]
+            }
+            ChannelView::Luma => {
+                let v = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+                [v, v, v, 1.]
+            }
+            ChannelView::Swizzle(mapping) => {
+                let alpha = if mapping.contains(&3) { 1. } else { a };
+                [channels[mapping[0]], channels[mapping[1]], channels[mapping[2]], alpha]
+            }
+        }
+    }
+}
+
+// Colormap used to show single-channel images (depth, heat, segmentation labels)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Colormap {
+    Viridis,
+    Turbo,
+    Jet,
+    Labels, // one random color per integer value
+}
+
+impl Colormap {
+    pub const ALL: [Colormap; 4] = [Colormap::Viridis, Colormap::Turbo, Colormap::Jet, Colormap::Labels];
+
+    // sRGB color of t in [0, 1]
+    pub fn color(&self, t: f32) -> [f32; 3] {
+        let t = t.clamp(0., 1.);
+        match self {
+            // Polynomial fit by Matt Zucker
+            Colormap::Viridis => {
+                let c = [
+                    [0.277_727_33, 0.005_407_344_5, 0.334_099_8],
+                    [0.105_093_04, 1.404_613_5, 1.384_590_2],
+                    [-0.330_861_83, 0.214_847_56, 0.095_095_16],
+                    [-4.634_230_6, -5.799_101, -19.332_441],
+                    [6.228_27, 14.179_933, 56.690_55],
+                    [4.776_385, -13.745_146, -65.353_03],
+                    [-5.435_456, 4.645_852_6, 26.312_435],
+                ];
+                [0, 1, 2].map(|i| c.iter().rev().fold(0., |acc, coef| acc * t + coef[i]).clamp(0., 1.))
+            }
+            // Polynomial approximation by Anton Mikhailov
+            Colormap::Turbo => {
+                let c = [
+                    [0.135_721_38, 0.091_402_61, 0.106_673_3],
+                    [4.615_392_6, 2.194_188_4, 12.641_946],
+                    [-42.660_324, 4.842_966_6, -60.582_05],
+                    [132.131_08, -14.185_033, 110.362_77],
+                    [-152.942_4, 4.277_299, -89.903_11],
+                    [59.286_38, 2.829_566, 27.348_25],
+                ];
+                [0, 1, 2].map(|i| c.iter().rev().fold(0., |acc, coef| acc * t + coef[i]).clamp(0., 1.))
+            }
+            Colormap::Jet => [3., 2., 1.].map(|center| (1.5 - (4. * t - center).abs()).clamp(0., 1.)),
+            Colormap::Labels => label_color((t * 255.).round() as u32),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Colormap::Viridis => "Viridis",
+            Colormap::Turbo => "Turbo",
+            Colormap::Jet => "Jet",
+            Colormap::Labels => "Labels",
+        }
+    }
+}
+
+// Stable random sRGB color of a label, 0 is kept black as it's usually the background
+pub fn label_color(label: u32) -> [f32; 3] {
+    if label == 0 {
+        return [0., 0., 0.];
+    }
+    // splitmix32 like mixing
+    let mut x = label.wrapping_mul(0x9E37_79B9);
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85EB_CA6B);
+    x ^= x >> 13;
+    x = x.wrapping_mul(0xC2B2_AE35);
+    x ^= x >> 16;
+    // Keep colors away from black
+    [x, x >> 8, x >> 16].map(|v| 0.2 + 0.8 * (v & 0xFF) as f32 / 255.)
+}
+
+// Colormap with the value range mapped to [0, 1], in stored units (e.g. 0-65535 for 16-bit)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColormapSettings {
+    pub colormap: Colormap,
+    pub min: f32,
+    pub max: f32,
+}
+
+// Stored value and alpha of the pixels of single-channel images, None for color images.
+// Float images have no single-channel format, one channel (e.g. the depth of an EXR) is stored
+// in R, G and B, so they are single-channel when these are equal.
+fn single_channel_values(image: &DynamicImage) -> Option<Box<dyn Iterator<Item = (f32, f32)> + '_>> {
+    let gray = |rgb: &[f32]| rgb[0].to_bits() == rgb[1].to_bits() && rgb[1].to_bits() == rgb[2].to_bits();
+    let values: Box<dyn Iterator<Item = (f32, f32)>> = match image {
+        DynamicImage::ImageLuma8(buffer) => Box::new(buffer.pixels().map(|p| (p.0[0] as f32, 1.))),
+        DynamicImage::ImageLumaA8(buffer) => Box::new(buffer.pixels().map(|p| (p.0[0] as f32, p.0[1] as f32 / 255.))),
+        DynamicImage::ImageLuma16(buffer) => Box::new(buffer.pixels().map(|p| (p.0[0] as f32, 1.))),
+        DynamicImage::ImageLumaA16(buffer) => {
+            Box::new(buffer.pixels().map(|p| (p.0[0] as f32, p.0[1] as f32 / 65535.)))
+        }
+        DynamicImage::ImageRgb32F(buffer) if buffer.pixels().all(|p| gray(&p.0)) => {
+            Box::new(buffer.pixels().map(|p| (p.0[0], 1.)))
+        }
+        DynamicImage::ImageRgba32F(buffer) if buffer.pixels().all(|p| gray(&p.0)) => {
+            Box::new(buffer.pixels().map(|p| (p.0[0], p.0[3])))
+        }
+        _ => return None,
+    };
+    Some(values)
+}
+
+// Smallest and largest finite stored value of a single-channel image, used for the automatic normalization
+pub fn single_channel_range(image: &DynamicImage) -> Option<(f32, f32)> {
+    let range = single_channel_values(image)?
+        .map(|(value, _)| value)
+        .filter(|value| value.is_finite())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), value| {
+            (min.min(value), max.max(value))
+        });
+    (range.0 <= range.1).then_some(range)
+}
+
+// Colormapped pixels as linear RGBA, None if the image isn't single-channel
+pub fn render_colormap(image: &DynamicImage, settings: &ColormapSettings) -> Option<Rgba32FImage> {
+    let values = single_channel_values(image)?;
+    let span = (settings.max - settings.min).max(f32::EPSILON);
+    let mut rgba = Rgba32FImage::new(image.width(), image.height());
+    for (pixel, (value, alpha)) in rgba.pixels_mut().zip(values) {
+        let color = match settings.colormap {
+            Colormap::Labels => label_color(value.max(0.).round() as u32),
+            colormap => colormap.color((value - settings.min) / span),
+        };
+        let [r, g, b] = color.map(srgb_to_linear);
+        pixel.0 = [r, g, b, alpha];
+    }
+    Some(rgba)
+}
+
+// Numeric problems of a pixel, checked on the RGB values in this order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Problem {
+    Nan,
+    PosInf,
+    NegInf,
+    Low,  // negative float, or integer clipped at 0 on every channel
+    High, // float above 1, or integer clipped at its largest value on every channel
+}
+
+impl Problem {
+    pub const ALL: [Problem; 5] = [
+        Problem::Nan,
+        Problem::PosInf,
+        Problem::NegInf,
+        Problem::Low,
+        Problem::High,
+    ];
+
+    // sRGB color used to highlight the pixel
+    pub fn color(&self) -> [f32; 3] {
+        match self {
+            Problem::Nan => [1., 0., 1.],
+            Problem::PosInf => [1., 1., 0.],
+            Problem::NegInf => [0., 1., 1.],
+            Problem::Low => [0., 0.3, 1.],
+            Problem::High => [1., 0., 0.],
+        }
+    }
+
+    pub fn name(&self, linear: bool) -> &'static str {
+        match (self, linear) {
+            (Problem::Nan, _) => "NaN",
+            (Problem::PosInf, _) => "+Inf",
+            (Problem::NegInf, _) => "-Inf",
+            (Problem::Low, true) => "<0",
+            (Problem::High, true) => ">1",
+            (Problem::Low, false) => "min",
+            (Problem::High, false) => "max",
+        }
+    }
+}
+
+// Classify stored values (integers normalized to [0, 1], floats as is). A float is out of range on any
+// channel, an integer pixel is clipped when all its channels are (a saturated color isn't clipped).
+pub fn classify(rgb: &[f32], linear: bool) -> Option<Problem> {
+    if rgb.iter().any(|v| v.is_nan()) {
+        Some(Problem::Nan)
+    } else if rgb.contains(&f32::INFINITY) {
+        Some(Problem::PosInf)
+    } else if rgb.contains(&f32::NEG_INFINITY) {
+        Some(Problem::NegInf)
+    } else if (linear && rgb.iter().any(|v| *v < 0.)) || (!linear && rgb.iter().all(|v| *v <= 0.)) {
+        Some(Problem::Low)
+    } else if (linear && rgb.iter().any(|v| *v > 1.)) || (!linear && rgb.iter().all(|v| *v >= 1.)) {
+        Some(Problem::High)
+    } else {
+        None
+    }
+}
+
+// Number of pixels for each problem, in the order of Problem::ALL
+pub fn count_problems(image: &DynamicImage) -> [u64; 5] {
+    let linear = is_linear(image);
+    let mut counts = [0; 5];
+    for pixel in image.to_rgba32f().pixels() {
+        if let Some(problem) = classify(&pixel.0[..3], linear) {
+            counts[problem as usize] += 1;
+        }
+    }
+    counts
+}
+
+// Paint the pixels with a numeric problem in their opaque highlight color
+pub fn highlight_problems(image: &DynamicImage, rgba: &mut Rgba32FImage) {
+    let linear = is_linear(image);
+    let values = image.to_rgba32f();
+    for (pixel, value) in rgba.pixels_mut().zip(values.pixels()) {
+        if let Some(problem) = classify(&value.0[..3], linear) {
+            let [r, g, b] = problem.color().map(srgb_to_linear);
+            pixel.0 = [r, g, b, 1.];
+        }
+    }
+}
+
+// Comparison against a reference image: |image - reference| * amplification
+#[derive(Clone)]
+pub struct Difference {
+    pub reference: Arc<DynamicImage>,
+    pub mapping: ReferenceMapping,
+    pub amplification: f32,
+    pub heatmap: bool,
+}
+
+// Position in the reference of a position in the image (in pixels, from the top-left corner):
+// axes * position + offset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReferenceMapping {
+    pub axes: [[f32; 2]; 2], // columns
+    pub offset: [f32; 2],
+}
+
+impl Default for ReferenceMapping {
+    fn default() -> Self {
+        ReferenceMapping {
+            axes: [[1., 0.], [0., 1.]],
+            offset: [0., 0.],
+        }
+    }
+}
+
+// How an image is shown in its cell: the pixel at the center of the cell, the zoom and the
+// clockwise quarter turns
+#[derive(Debug, Clone, Copy)]
+pub struct CellView {
+    pub anchor: [f32; 2],
+    pub scale: f32,
+    pub turns: i32,
+}
+
+impl ReferenceMapping {
+    // Pixels shown at the same place of their cells are compared
+    pub fn between(image: CellView, reference: CellView) -> ReferenceMapping {
+        // Quarter turns with y pointing down
+        let [x, y] = match (image.turns - reference.turns).rem_euclid(4) {
+            0 => [[1., 0.], [0., 1.]],
+            1 => [[0., 1.], [-1., 0.]],
+            2 => [[-1., 0.], [0., -1.]],
+            _ => [[0., -1.], [1., 0.]],
+        };
+        let ratio = image.scale / reference.scale;
+        let axes = [x.map(|v| v * ratio), y.map(|v| v * ratio)];
+        let [ax, ay] = image.anchor;
+        let offset = [
+            reference.anchor[0] - axes[0][0] * ax - axes[1][0] * ay,
+            reference.anchor[1] - axes[0][1] * ax - axes[1][1] * ay,
+        ];
+        ReferenceMapping { axes, offset }
+    }
+
+    pub fn apply(&self, x: f32, y: f32) -> [f32; 2] {
+        [
+            self.axes[0][0] * x + self.axes[1][0] * y + self.offset[0],
+            self.axes[0][1] * x + self.axes[1][1] * y + self.offset[1],
+        ]
+    }
+
+    // Mapping of a region of the image starting at (x, y)
+    fn translated(&self, x: f32, y: f32) -> ReferenceMapping {
+        ReferenceMapping {
+            axes: self.axes,
+            offset: self.apply(x, y),
+        }
+    }
+}
+
+// Everything needed to build the displayed pixels of one image
+#[derive(Clone, Default)]
+pub struct DisplaySettings {
+    pub tone: ToneSettings,
+    pub channels: ChannelView,
+    pub ignore_alpha: bool, // show every pixel opaque
+    pub colormap: Option<ColormapSettings>,
+    pub levels: Option<(f32, f32)>,      // stored values stretched to the display range
+    pub highlight: bool,                 // paint NaN, Inf and clipped pixels
+    pub color_space: Option<ImageSpace>, // stored values converted from this space, None when shown as is
+    pub difference: Option<Difference>,
+}
+
+// Build the displayed pixels as linear RGBA.
+// Returns None when the settings don't change anything so the raw image can be uploaded.
+// The highlight isn't painted over a difference, it would hide it.
+pub fn render(image: &DynamicImage, settings: &DisplaySettings) -> Option<Rgba32FImage> {
+    let rendered = render_values(image, settings);
+    if !settings.highlight || settings.difference.is_some() {
+        return rendered;
+    }
+    let mut rgba = rendered.unwrap_or_else(|| to_working_rgba(image, None, settings.color_space.as_ref()));
+    highlight_problems(image, &mut rgba);
+    Some(rgba)
+}
+
+fn render_values(image: &DynamicImage, settings: &DisplaySettings) -> Option<Rgba32FImage> {
+    if let Some(difference) = &settings.difference {
+        return Some(render_difference(image, difference));
+    }
+    if let Some(colormap) = &settings.colormap {
+        if let Some(rgba) = render_colormap(image, colormap) {
+            return Some(rgba);
+        }
+    }
+    let keeps_alpha = !settings.ignore_alpha || !image.color().has_alpha();
+    if settings.tone.is_identity()
+        && settings.channels == ChannelView::Rgb
+        && keeps_alpha
+        && settings.levels.is_none()
+        && settings.color_space.is_none()
+    {
+        return None;
+    }
+    let mut rgba = to_working_rgba(image, settings.levels, settings.color_space.as_ref());
+    for pixel in rgba.pixels_mut() {
+        pixel.0 = settings.channels.apply(pixel.0);
+        for channel in &mut pixel.0[..3] {
+            *channel = settings.tone.apply(*channel);
+        }
+        if settings.ignore_alpha {
+            pixel.0[3] = 1.;
+        }
+    }
+    Some(rgba)
+}
+
+// Displayed pixels of a part of an image in 8-bit sRGB, for exports. The screen is drawn by the display
+// shader, this is the same transform on the CPU, limited to the region.
+pub fn render_region(
+    image: &DynamicImage,
+    settings: &DisplaySettings,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> RgbaImage {
+    let region = image.crop_imm(x, y, width, height);
+    let mut settings = settings.clone();
+    if let Some(difference) = &mut settings.difference {
+        difference.mapping = difference.mapping.translated(x as f32, y as f32);
+    }
+    let rgba = render(&region, &settings).unwrap_or_else(|| to_linear_rgba(&region, None));
+    let encode = |linear: f32| (linear_to_srgb(linear.clamp(0., 1.)) * 255.).round() as u8;
+    RgbaImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        image::Rgba([encode(r), encode(g), encode(b), (a.clamp(0., 1.) * 255.).round() as u8])
+    })
+}
+
+// Absolute difference of the stored values (integers normalized to [0, 1], floats as is).
+// Shown as a color difference or as a heat-map of the largest channel difference.
+// Pixels outside of the reference are left transparent.
+pub fn render_difference(image: &DynamicImage, difference: &Difference) -> Rgba32FImage {
+    let values = image.to_rgba32f();
+    // Only the part of the reference covered by the image is converted
+    let (width, height) = (image.width() as f32, image.height() as f32);
+    let corners = [[0., 0.], [width, 0.], [0., height], [width, height]].map(|[x, y]| difference.mapping.apply(x, y));
+    let bound = |axis: usize, size: u32| {
+        let values = corners.map(|corner| corner[axis]);
+        let min = values.iter().copied().fold(f32::INFINITY, f32::min).floor().max(0.) as u32;
+        let max = values
+            .iter()
+            .copied()
+            .fold(f32::NEG_INFINITY, f32::max)
+            .ceil()
+            .min(size as f32) as u32;
+        (min.min(size), max.max(min).min(size))
+    };
+    let (left, right) = bound(0, difference.reference.width());
+    let (top, bottom) = bound(1, difference.reference.height());
+    let reference = difference
+        .reference
+        .crop_imm(left, top, right - left, bottom - top)
+        .to_rgba32f();
+
+    Rgba32FImage::from_fn(values.width(), values.height(), |x, y| {
+        let [rx, ry] = difference.mapping.apply(x as f32 + 0.5, y as f32 + 0.5);
+        let (rx, ry) = (rx.floor() - left as f32, ry.floor() - top as f32);
+        if rx < 0. || ry < 0. || rx >= reference.width() as f32 || ry >= reference.height() as f32 {
+            return image::Rgba([0., 0., 0., 0.]);
+        }
+        let a = values.get_pixel(x, y).0;
+        let b = reference.get_pixel(rx as u32, ry as u32).0;
+        let delta = [(a[0] - b[0]).abs(), (a[1] - b[1]).abs(), (a[2] - b[2]).abs()]
+            .map(|d| (d * difference.amplification).clamp(0., 1.));
+        // Amplified differences are display values, converted back to linear for the texture
+        let color = if difference.heatmap {
+            heat(delta[0].max(delta[1]).max(delta[2]))
+        } else {
+            delta
+        };
+        let [r, g, b] = color.map(srgb_to_linear);
+        image::Rgba([r, g, b, 1.])
+    })
+}
+
+// Black -> red -> yellow -> white
+pub fn heat(t: f32) -> [f32; 3] {
+    let t = t.clamp(0., 1.) * 3.;
+    [t.min(1.), (t - 1.).clamp(0., 1.), (t - 2.).clamp(0., 1.)]
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb32FImage, RgbImage};
+
+    use super::*;
+
+    fn approx(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn default_is_identity() {
+        assert!(ToneSettings::default().is_identity());
+        let tone = ToneSettings {
+            exposure: 1.,
+            ..Default::default()
+        };
+        assert!(!tone.is_identity());
+    }
+
+    #[test]
+    fn exposure_in_stops() {
+        let tone = ToneSettings {
+            exposure: 1.,
+            ..Default::default()
+        };
+        assert!(approx(tone.apply(0.25), 0.5));
+        let tone = ToneSettings {
+            exposure: -2.,
+            ..Default::default()
+        };
+        assert!(approx(tone.apply(2.0), 0.5));
+    }
+
+    #[test]
+    fn offset_then_clamp() {
+        let tone = ToneSettings {
+            offset: 0.5,
+            ..Default::default()
+        };
+        assert!(approx(tone.apply(0.25), 0.75));
+        assert!(approx(tone.apply(4.0), 1.0));
+        let tone = ToneSettings {
+            offset: -0.5,
+            ..Default::default()
+        };
+        assert!(approx(tone.apply(0.25), 0.0));
+    }
+
+    #[test]
+    fn gamma() {
+        let tone = ToneSettings {
+            gamma: 2.,
+            ..Default::default()
+        };
+        assert!(approx(tone.apply(0.25), 0.5));
+    }
+
+    #[test]
+    fn operators_stay_in_range() {
+        for tonemapping in [ToneMapping::Reinhard, ToneMapping::Aces, ToneMapping::Filmic] {
+            let tone = ToneSettings {
+                tonemapping,
+                ..Default::default()
+            };
+            assert!(approx(tone.apply(0.), 0.));
+            let high = tone.apply(1000.);
+            assert!(high > 0.9 && high <= 1.0, "{tonemapping:?}: {high}");
+            assert!(tone.apply(0.5) < tone.apply(2.0));
+        }
+    }
+
+    #[test]
+    fn reinhard_half_at_one() {
+        let tone = ToneSettings {
+            tonemapping: ToneMapping::Reinhard,
+            ..Default::default()
+        };
+        assert!(approx(tone.apply(1.), 0.5));
+    }
+
+    #[test]
+    fn integer_images_are_linearized() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, image::Rgb([255, 128, 0])));
+        let rgba = to_linear_rgba(&image, None);
+        let pixel = rgba.get_pixel(0, 0).0;
+        assert!(approx(pixel[0], 1.0));
+        assert!(approx(pixel[1], 0.2158));
+        assert!(approx(pixel[2], 0.0));
+        assert!(approx(pixel[3], 1.0));
+    }
+
+    #[test]
+    fn float_images_keep_values() {
+        let image = DynamicImage::ImageRgb32F(Rgb32FImage::from_pixel(1, 1, image::Rgb([4.0, 0.5, -1.0])));
+        let rgba = to_linear_rgba(&image, None);
+        assert_eq!(rgba.get_pixel(0, 0).0, [4.0, 0.5, -1.0, 1.0]);
+    }
+
+    fn gray(width: u32, height: u32, value: u8) -> Arc<DynamicImage> {
+        Arc::new(DynamicImage::ImageRgb8(RgbImage::from_pixel(
+            width,
+            height,
+            image::Rgb([value; 3]),
+        )))
+    }
+
+    #[test]
+    fn identity_renders_nothing() {
+        let image = gray(2, 2, 10);
+        assert!(render(&image, &DisplaySettings::default()).is_none());
+    }
+
+    #[test]
+    fn difference_is_amplified() {
+        let image = gray(2, 2, 100);
+        let difference = Difference {
+            reference: gray(2, 2, 90),
+            mapping: ReferenceMapping::default(),
+            amplification: 10.,
+            heatmap: false,
+        };
+        let rgba = render_difference(&image, &difference);
+        let pixel = rgba.get_pixel(1, 1).0;
+        let expected = srgb_to_linear(100. / 255.);
+        assert!(approx(pixel[0], expected), "{pixel:?}");
+        assert!(approx(pixel[3], 1.));
+    }
+
+    #[test]
+    fn difference_of_same_image_is_black() {
+        let image = gray(2, 2, 100);
+        let difference = Difference {
+            reference: image.clone(),
+            mapping: ReferenceMapping::default(),
+            amplification: 100.,
+            heatmap: true,
+        };
+        let rgba = render_difference(&image, &difference);
+        assert_eq!(rgba.get_pixel(0, 0).0, [0., 0., 0., 1.]);
+    }
+
+    #[test]
+    fn difference_outside_reference_is_transparent() {
+        let image = gray(3, 2, 100);
+        let difference = Difference {
+            reference: gray(2, 2, 100),
+            mapping: ReferenceMapping::default(),
+            amplification: 1.,
+            heatmap: false,
+        };
+        let rgba = render_difference(&image, &difference);
+        assert_eq!(rgba.get_pixel(2, 0).0[3], 0.);
+        assert_eq!(rgba.get_pixel(1, 0).0[3], 1.);
+    }
+
+    #[test]
+    fn difference_follows_the_cells() {
+        let view = CellView {
+            anchor: [2., 1.],
+            scale: 1.,
+            turns: 0,
+        };
+        assert_eq!(ReferenceMapping::between(view, view), ReferenceMapping::default());
+
+        // The reference is zoomed twice as much and turned a quarter clockwise
+        let reference = CellView {
+            anchor: [10., 20.],
+            scale: 2.,
+            turns: 1,
+        };
+        let mapping = ReferenceMapping::between(view, reference);
+        assert_eq!(mapping.apply(2., 1.), [10., 20.]);
+        // One pixel right of the center of the cell is half a pixel up in the reference
+        assert_eq!(mapping.apply(3., 1.), [10., 19.5]);
+        assert_eq!(mapping.apply(2., 2.), [10.5, 20.]);
+
+        let image = gray(2, 2, 100);
+        let mut buffer = RgbImage::new(4, 4);
+        buffer.put_pixel(3, 2, image::Rgb([100, 100, 100]));
+        let difference = Difference {
+            reference: Arc::new(DynamicImage::ImageRgb8(buffer)),
+            mapping: ReferenceMapping {
+                axes: [[1., 0.], [0., 1.]],
+                offset: [2., 2.],
+            },
+            amplification: 1.,
+            heatmap: false,
+        };
+        let rgba = render_difference(&image, &difference);
+        assert_eq!(rgba.get_pixel(1, 0).0, [0., 0., 0., 1.]);
+        assert!(rgba.get_pixel(0, 0).0[0] > 0.);
+    }
+
+    #[test]
+    fn single_channel_as_gray() {
+        let pixel = [0.1, 0.2, 0.3, 1.];
+        assert_eq!(ChannelView::Single(1).apply(pixel), [0.2, 0.2, 0.2, 1.]);
+        let luma = ChannelView::Luma.apply(pixel)[0];
+        assert!(approx(luma, 0.2126 * 0.1 + 0.7152 * 0.2 + 0.0722 * 0.3));
+        let alpha = ChannelView::Single(3).apply([0., 0., 0., 0.5]);
+        assert!(approx(alpha[0], srgb_to_linear(0.5)));
+        assert_eq!(alpha[3], 1.);
+    }
+
+    #[test]
+    fn swizzle() {
+        assert_eq!(ChannelView::parse_swizzle("bgr"), Some(ChannelView::Swizzle([2, 1, 0])));
+        assert_eq!(ChannelView::parse_swizzle("RG"), None);
+        assert_eq!(ChannelView::parse_swizzle("RGBA"), None);
+        assert_eq!(ChannelView::parse_swizzle("RGX"), None);
+        let view = ChannelView::Swizzle([2, 1, 0]);
+        assert_eq!(view.name(), "BGR");
+        assert_eq!(view.apply([0.1, 0.2, 0.3, 0.5]), [0.3, 0.2, 0.1, 0.5]);
+    }
+
+    #[test]
+    fn channel_cycle() {
+        assert_eq!(ChannelView::Rgb.cycle(1), ChannelView::Single(0));
+        assert_eq!(ChannelView::Rgb.cycle(-1), ChannelView::Luma);
+        assert_eq!(ChannelView::Luma.cycle(1), ChannelView::Rgb);
+        assert_eq!(ChannelView::Swizzle([0, 0, 0]).cycle(1), ChannelView::Rgb);
+    }
+
+    #[test]
+    fn channel_view_is_rendered() {
+        let image = gray(1, 1, 10);
+        let settings = DisplaySettings {
+            channels: ChannelView::Single(0),
+            ..Default::default()
+        };
+        assert!(render(&image, &settings).is_some());
+    }
+
+    #[test]
+    fn ignore_alpha() {
+        let image = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 0, 0, 10])));
+        let settings = DisplaySettings {
+            ignore_alpha: true,
+            ..Default::default()
+        };
+        let rgba = render(&image, &settings).unwrap();
+        assert_eq!(rgba.get_pixel(0, 0).0, [1., 0., 0., 1.]);
+        // Nothing to do for opaque images
+        assert!(render(&gray(1, 1, 10), &settings).is_none());
+    }
+
+    #[test]
+    fn color_space_is_converted() {
+        let image = DynamicImage::ImageRgb32F(image::Rgb32FImage::from_pixel(1, 1, image::Rgb([0.5, 0.5, 0.5])));
+        let settings = DisplaySettings {
+            color_space: Some(ImageSpace::Known(crate::colorspace::ColorSpace::Srgb)),
+            ..Default::default()
+        };
+        let rgba = render(&image, &settings).unwrap();
+        assert!(approx(rgba.get_pixel(0, 0).0[1], srgb_to_linear(0.5)));
+    }
+
+    #[test]
+    fn colormap_ends() {
+        let viridis = Colormap::Viridis;
+        let start = viridis.color(0.);
+        let end = viridis.color(1.);
+        // #440154 and #fde725, up to the error of the polynomial fit
+        let close = |a: [f32; 3], b: [f32; 3]| a.iter().zip(b).all(|(x, y)| (x - y / 255.).abs() < 0.02);
+        assert!(close(start, [68., 1., 84.]), "{start:?}");
+        assert!(close(end, [253., 231., 37.]), "{end:?}");
+        assert_eq!(Colormap::Jet.color(0.5), [0.5, 1., 0.5]);
+        assert_eq!(Colormap::Jet.color(0.), [0., 0., 0.5]);
+        let turbo = Colormap::Turbo.color(1.);
+        assert!(turbo[0] < 0.6 && turbo[2] < 0.1, "{turbo:?}");
+    }
+
+    #[test]
+    fn labels_are_stable_and_distinct() {
+        assert_eq!(label_color(0), [0., 0., 0.]);
+        assert_eq!(label_color(3), label_color(3));
+        assert_ne!(label_color(1), label_color(2));
+    }
+
+    #[test]
+    fn float_labels_are_rounded_like_the_shader() {
+        let mut buffer = image::Rgb32FImage::from_pixel(2, 1, image::Rgb([2.6; 3]));
+        buffer.put_pixel(1, 0, image::Rgb([-1.; 3]));
+        let settings = ColormapSettings {
+            colormap: Colormap::Labels,
+            min: 0.,
+            max: 255.,
+        };
+        let rgba = render_colormap(&DynamicImage::ImageRgb32F(buffer), &settings).unwrap();
+        let linear = |label| label_color(label).map(srgb_to_linear);
+        assert_eq!(rgba.get_pixel(0, 0).0[..3], linear(3));
+        assert_eq!(rgba.get_pixel(1, 0).0[..3], linear(0));
+    }
+
+    #[test]
+    fn colormap_normalizes_stored_values() {
+        let buffer: image::ImageBuffer<image::Luma<u16>, Vec<u16>> =
+            image::ImageBuffer::from_fn(3, 1, |x, _| image::Luma([[1000, 2000, 3000][x as usize]]));
+        let image = DynamicImage::ImageLuma16(buffer);
+        assert_eq!(single_channel_range(&image), Some((1000., 3000.)));
+        let settings = ColormapSettings {
+            colormap: Colormap::Jet,
+            min: 1000.,
+            max: 3000.,
+        };
+        let rgba = render_colormap(&image, &settings).unwrap();
+        let middle = rgba.get_pixel(1, 0).0;
+        assert!(
+            approx(middle[1], 1.) && approx(middle[0], srgb_to_linear(0.5)),
+            "{middle:?}"
+        );
+        // Color images are not colormapped
+        assert!(render_colormap(&gray(1, 1, 0), &settings).is_none());
+        assert!(single_channel_range(&gray(1, 1, 0)).is_none());
+    }
+
+    #[test]
+    fn float_images_with_equal_channels_are_single_channel() {
+        let mut buffer = image::Rgb32FImage::from_pixel(3, 1, image::Rgb([2.5; 3]));
+        buffer.put_pixel(1, 0, image::Rgb([-1.; 3]));
+        buffer.put_pixel(2, 0, image::Rgb([f32::NAN; 3]));
+        let depth = DynamicImage::ImageRgb32F(buffer.clone());
+        assert_eq!(single_channel_range(&depth), Some((-1., 2.5)));
+
+        buffer.put_pixel(0, 0, image::Rgb([2.5, 0., 0.]));
+        assert!(single_channel_range(&DynamicImage::ImageRgb32F(buffer)).is_none());
+    }
+
+    #[test]
+    fn levels_stretch_stored_values() {
+        let buffer: image::ImageBuffer<image::Luma<u16>, Vec<u16>> =
+            image::ImageBuffer::from_fn(2, 1, |x, _| image::Luma([[1000, 3000][x as usize]]));
+        let image = DynamicImage::ImageLuma16(buffer);
+        let (low, high) = level_range(&image, 0.).unwrap();
+        assert!(approx(low * stored_unit(&image), 1000.) && approx(high * stored_unit(&image), 3000.));
+        let rgba = to_linear_rgba(&image, Some((low, high)));
+        assert!(approx(rgba.get_pixel(0, 0).0[0], 0.));
+        assert!(approx(rgba.get_pixel(1, 0).0[0], 1.));
+    }
+
+    #[test]
+    fn levels_percentile_ignores_outliers() {
+        let mut buffer = Rgb32FImage::from_fn(100, 1, |x, _| image::Rgb([x as f32 / 100.; 3]));
+        buffer.put_pixel(0, 0, image::Rgb([-1000.; 3]));
+        buffer.put_pixel(99, 0, image::Rgb([f32::INFINITY; 3]));
+        let image = DynamicImage::ImageRgb32F(buffer);
+        assert_eq!(level_range(&image, 0.), Some((-1000., 0.98)));
+        let (low, high) = level_range(&image, 2.).unwrap();
+        assert!(low > 0. && high < 0.98, "{low} {high}");
+    }
+
+    #[test]
+    fn problems_of_float_images() {
+        assert_eq!(classify(&[f32::NAN, f32::INFINITY, 0.], true), Some(Problem::Nan));
+        assert_eq!(classify(&[0.5, f32::INFINITY, -1.], true), Some(Problem::PosInf));
+        assert_eq!(classify(&[0.5, f32::NEG_INFINITY, 2.], true), Some(Problem::NegInf));
+        assert_eq!(classify(&[0.5, -0.1, 2.], true), Some(Problem::Low));
+        assert_eq!(classify(&[0.5, 1.1, 0.], true), Some(Problem::High));
+        assert_eq!(classify(&[0., 1., 0.5], true), None);
+    }
+
+    #[test]
+    fn problems_of_integer_images() {
+        let mut buffer = RgbImage::from_pixel(3, 1, image::Rgb([10, 20, 30]));
+        buffer.put_pixel(0, 0, image::Rgb([0, 0, 0]));
+        buffer.put_pixel(1, 0, image::Rgb([255, 255, 255]));
+        let image = DynamicImage::ImageRgb8(buffer);
+        assert_eq!(count_problems(&image), [0, 0, 0, 1, 1]);
+        // A saturated color only clips some of its channels
+        assert_eq!(classify(&[1., 0., 0.], false), None);
+
+        let settings = DisplaySettings {
+            highlight: true,
+            ..Default::default()
+        };
+        let rgba = render(&image, &settings).unwrap();
+        assert_eq!(rgba.get_pixel(1, 0).0, [1., 0., 0., 1.]);
+        assert!(approx(rgba.get_pixel(2, 0).0[0], srgb_to_linear(10. / 255.)));
+    }
+
+    #[test]
+    fn exported_region() {
+        let mut buffer = RgbImage::from_pixel(4, 2, image::Rgb([10, 20, 30]));
+        buffer.put_pixel(2, 1, image::Rgb([200, 100, 0]));
+        let image = DynamicImage::ImageRgb8(buffer);
+        let region = render_region(&image, &DisplaySettings::default(), 1, 1, 2, 1);
+        assert_eq!(region.dimensions(), (2, 1));
+        assert_eq!(region.get_pixel(0, 0).0, [10, 20, 30, 255]);
+        assert_eq!(region.get_pixel(1, 0).0, [200, 100, 0, 255]);
+
+        // The reference is sampled at the same place
+        let settings = DisplaySettings {
+            difference: Some(Difference {
+                reference: Arc::new(image.clone()),
+                mapping: ReferenceMapping::default(),
+                amplification: 1.,
+                heatmap: false,
+            }),
+            ..Default::default()
+        };
+        let region = render_region(&image, &settings, 1, 1, 2, 1);
+        assert_eq!(region.get_pixel(1, 0).0, [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn heat_ramp() {
+        assert_eq!(heat(0.), [0., 0., 0.]);
+        assert_eq!(heat(1.), [1., 1., 1.]);
+        assert_eq!(heat(0.5), [1., 0.5, 0.]);
+    }
+}
@@ -0,0 +1,301 @@
+// Display transform of the stored values of an image, the GPU side of display::render.
+// Integers are normalized to [0, 1] by the texture format, floats are stored as is.
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+
+struct DisplayParams {
+    to_working: mat3x3<f32>,
+    levels: vec2<f32>,
+    colormap_range: vec2<f32>,
+    mapping: vec4<u32>,
+    reference_axes: mat2x2<f32>,
+    reference_offset: vec2<f32>,
+    exposure: f32,
+    offset: f32,
+    gamma: f32,
+    tonemapping: u32,
+    tone_identity: u32,
+    transfer: u32,
+    channels: u32,
+    colormap: u32,
+    ignore_alpha: u32,
+    highlight: u32,
+    linear: u32,
+    stored_unit: f32,
+    difference: u32,
+    amplification: f32,
+    opacity: f32,
+    bilinear: u32,
+}
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(0) var<uniform> params: DisplayParams;
+@group(#{MATERIAL_BIND_GROUP}) @binding(1) var stored_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(2) var reference_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(3) var icc_lut: texture_3d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(4) var icc_lut_sampler: sampler;
+
+const EPSILON: f32 = 1.1920929e-7;
+
+// Value at a position in texels, the edges are clamped. Float textures can't be filtered on
+// every backend, the bilinear interpolation is done here from the loaded texels.
+fn sample(texture: texture_2d<f32>, texel: vec2<f32>) -> vec4<f32> {
+    let last = vec2<i32>(textureDimensions(texture)) - 1;
+    if params.bilinear == 0u {
+        return textureLoad(texture, clamp(vec2<i32>(floor(texel)), vec2(0), last), 0);
+    }
+    let position = texel - 0.5;
+    let origin = vec2<i32>(floor(position));
+    let weight = fract(position);
+    let a = textureLoad(texture, clamp(origin, vec2(0), last), 0);
+    let b = textureLoad(texture, clamp(origin + vec2(1, 0), vec2(0), last), 0);
+    let c = textureLoad(texture, clamp(origin + vec2(0, 1), vec2(0), last), 0);
+    let d = textureLoad(texture, clamp(origin + vec2(1, 1), vec2(0), last), 0);
+    return mix(mix(a, b, weight.x), mix(c, d, weight.x), weight.y);
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        return value / 12.92;
+    }
+    return pow((value + 0.055) / 1.055, 2.4);
+}
+
+fn srgb_to_linear3(color: vec3<f32>) -> vec3<f32> {
+    return vec3(srgb_to_linear(color.r), srgb_to_linear(color.g), srgb_to_linear(color.b));
+}
+
+// colorspace::Transfer, negative values (out of gamut) are mirrored
+fn decode(value: f32) -> f32 {
+    let magnitude = abs(value);
+    var linear = magnitude;
+    switch params.transfer {
+        case 1u: {
+            linear = srgb_to_linear(magnitude);
+        }
+        case 2u: {
+            linear = pow(magnitude, 563.0 / 256.0);
+        }
+        case 3u: {
+            linear = pow(magnitude, 2.4);
+        }
+        default: {}
+    }
+    return select(linear, -linear, value < 0.0);
+}
+
+// Stored values -> linear sRGB
+fn to_working(rgb: vec3<f32>) -> vec3<f32> {
+    if params.transfer == 4u {
+        let size = f32(textureDimensions(icc_lut).x);
+        let coordinates = (clamp(rgb, vec3(0.0), vec3(1.0)) * (size - 1.0) + 0.5) / size;
+        return textureSampleLevel(icc_lut, icc_lut_sampler, coordinates, 0.0).rgb;
+    }
+    return params.to_working * vec3(decode(rgb.r), decode(rgb.g), decode(rgb.b));
+}
+
+// display::ChannelView::apply
+fn view_channels(rgba: vec4<f32>) -> vec4<f32> {
+    let channels = vec4(rgba.rgb, srgb_to_linear(rgba.a));
+    let mapping = params.mapping;
+    switch params.channels {
+        case 1u: {
+            return vec4(vec3(channels[mapping.x]), 1.0);
+        }
+        case 2u: {
+            return vec4(vec3(dot(rgba.rgb, vec3(0.2126, 0.7152, 0.0722))), 1.0);
+        }
+        case 3u: {
+            let alpha = select(rgba.a, 1.0, any(mapping.xyz == vec3(3u)));
+            return vec4(channels[mapping.x], channels[mapping.y], channels[mapping.z], alpha);
+        }
+        default: {
+            return rgba;
+        }
+    }
+}
+
+fn hable(x: f32) -> f32 {
+    let a = 0.15;
+    let b = 0.50;
+    let c = 0.10;
+    let d = 0.20;
+    let e = 0.02;
+    let f = 0.30;
+    return ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f;
+}
+
+// display::ToneSettings::apply
+fn tone(value: f32) -> f32 {
+    let exposed = value * exp2(params.exposure) + params.offset;
+    var mapped = exposed;
+    switch params.tonemapping {
+        case 1u: {
+            let v = max(exposed, 0.0);
+            mapped = v / (1.0 + v);
+        }
+        case 2u: {
+            let v = max(exposed, 0.0);
+            mapped = (v * (2.51 * v + 0.03)) / (v * (2.43 * v + 0.59) + 0.14);
+        }
+        case 3u: {
+            mapped = hable(2.0 * max(exposed, 0.0)) / hable(11.2);
+        }
+        default: {}
+    }
+    return pow(clamp(mapped, 0.0, 1.0), 1.0 / max(params.gamma, 0.01));
+}
+
+fn viridis(t: f32) -> vec3<f32> {
+    let c0 = vec3(0.27772733, 0.0054073445, 0.3340998);
+    let c1 = vec3(0.10509304, 1.4046135, 1.3845902);
+    let c2 = vec3(-0.33086183, 0.21484756, 0.09509516);
+    let c3 = vec3(-4.6342306, -5.799101, -19.332441);
+    let c4 = vec3(6.22827, 14.179933, 56.69055);
+    let c5 = vec3(4.776385, -13.745146, -65.35303);
+    let c6 = vec3(-5.435456, 4.6458526, 26.312435);
+    return c0 + t * (c1 + t * (c2 + t * (c3 + t * (c4 + t * (c5 + t * c6)))));
+}
+
+fn turbo(t: f32) -> vec3<f32> {
+    let c0 = vec3(0.13572138, 0.09140261, 0.1066733);
+    let c1 = vec3(4.6153926, 2.1941884, 12.641946);
+    let c2 = vec3(-42.660324, 4.8429666, -60.58205);
+    let c3 = vec3(132.13108, -14.185033, 110.36277);
+    let c4 = vec3(-152.9424, 4.277299, -89.90311);
+    let c5 = vec3(59.28638, 2.829566, 27.34825);
+    return c0 + t * (c1 + t * (c2 + t * (c3 + t * (c4 + t * c5))));
+}
+
+// display::label_color
+fn label_color(label: u32) -> vec3<f32> {
+    if label == 0u {
+        return vec3(0.0);
+    }
+    var x = label * 0x9E3779B9u;
+    x ^= x >> 16u;
+    x *= 0x85EBCA6Bu;
+    x ^= x >> 13u;
+    x *= 0xC2B2AE35u;
+    x ^= x >> 16u;
+    return 0.2 + 0.8 * vec3(f32(x & 0xFFu), f32((x >> 8u) & 0xFFu), f32((x >> 16u) & 0xFFu)) / 255.0;
+}
+
+// sRGB color of a stored value, display::Colormap::color
+fn colormap(value: f32) -> vec3<f32> {
+    if params.colormap == 4u {
+        return label_color(u32(max(round(value), 0.0)));
+    }
+    let range = params.colormap_range;
+    let t = clamp((value - range.x) / max(range.y - range.x, EPSILON), 0.0, 1.0);
+    switch params.colormap {
+        case 1u: {
+            return clamp(viridis(t), vec3(0.0), vec3(1.0));
+        }
+        case 2u: {
+            return clamp(turbo(t), vec3(0.0), vec3(1.0));
+        }
+        default: {
+            return clamp(1.5 - abs(4.0 * t - vec3(3.0, 2.0, 1.0)), vec3(0.0), vec3(1.0));
+        }
+    }
+}
+
+fn heat(t: f32) -> vec3<f32> {
+    let s = clamp(t, 0.0, 1.0) * 3.0;
+    return vec3(min(s, 1.0), clamp(s - 1.0, 0.0, 1.0), clamp(s - 2.0, 0.0, 1.0));
+}
+
+// display::render_difference, the reference is sampled where it's shown in its cell.
+// Pixels outside of the reference are transparent.
+fn difference(value: vec4<f32>, uv: vec2<f32>) -> vec4<f32> {
+    let texel = uv * vec2<f32>(textureDimensions(stored_texture));
+    let reference_texel = params.reference_axes * texel + params.reference_offset;
+    let reference_size = vec2<f32>(textureDimensions(reference_texture));
+    if any(reference_texel < vec2(0.0)) || any(reference_texel >= reference_size) {
+        return vec4(0.0);
+    }
+    let reference = sample(reference_texture, reference_texel);
+    let delta = clamp(abs(value.rgb - reference.rgb) * params.amplification, vec3(0.0), vec3(1.0));
+    var color = delta;
+    if params.difference == 2u {
+        color = heat(max(delta.r, max(delta.g, delta.b)));
+    }
+    return vec4(srgb_to_linear3(color), 1.0);
+}
+
+// NaN and infinities are checked on the bits, compilers may assume floats are finite
+fn is_nan(value: f32) -> bool {
+    let bits = bitcast<u32>(value);
+    return (bits & 0x7F800000u) == 0x7F800000u && (bits & 0x007FFFFFu) != 0u;
+}
+
+// display::classify, 0 for none, then display::Problem + 1
+fn classify(rgb: vec3<f32>) -> u32 {
+    let bits = bitcast<vec3<u32>>(rgb);
+    if is_nan(rgb.r) || is_nan(rgb.g) || is_nan(rgb.b) {
+        return 1u;
+    }
+    if any(bits == vec3(0x7F800000u)) {
+        return 2u;
+    }
+    if any(bits == vec3(0xFF800000u)) {
+        return 3u;
+    }
+    // A float is out of range on any channel, an integer pixel is clipped when all its channels are
+    let linear = params.linear != 0u;
+    if (linear && any(rgb < vec3(0.0))) || (!linear && all(rgb <= vec3(0.0))) {
+        return 4u;
+    }
+    if (linear && any(rgb > vec3(1.0))) || (!linear && all(rgb >= vec3(1.0))) {
+        return 5u;
+    }
+    return 0u;
+}
+
+// sRGB color of display::Problem::color
+fn problem_color(problem: u32) -> vec3<f32> {
+    switch problem {
+        case 1u: {
+            return vec3(1.0, 0.0, 1.0);
+        }
+        case 2u: {
+            return vec3(1.0, 1.0, 0.0);
+        }
+        case 3u: {
+            return vec3(0.0, 1.0, 1.0);
+        }
+        case 4u: {
+            return vec3(0.0, 0.3, 1.0);
+        }
+        default: {
+            return vec3(1.0, 0.0, 0.0);
+        }
+    }
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let value = sample(stored_texture, in.uv * vec2<f32>(textureDimensions(stored_texture)));
+    var color: vec4<f32>;
+    if params.difference != 0u {
+        color = difference(value, in.uv);
+    } else if params.colormap != 0u {
+        color = vec4(srgb_to_linear3(colormap(value.r * params.stored_unit)), value.a);
+    } else {
+        let stretched = (value.rgb - params.levels.x) / max(params.levels.y - params.levels.x, EPSILON);
+        color = view_channels(vec4(to_working(stretched), value.a));
+        if params.tone_identity == 0u {
+            color = vec4(tone(color.r), tone(color.g), tone(color.b), color.a);
+        }
+        if params.ignore_alpha != 0u {
+            color.a = 1.0;
+        }
+    }
+    // The highlight would hide the difference
+    if params.highlight != 0u && params.difference == 0u {
+        let problem = classify(value.rgb);
+        if problem != 0u {
+            color = vec4(srgb_to_linear3(problem_color(problem)), 1.0);
+        }
+    }
+    return vec4(color.rgb, color.a * params.opacity);
+}
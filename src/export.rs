@@ -0,0 +1,677 @@
+use std::io::{Seek, Write};
+use std::path::{Path, PathBuf};
+
+use ab_glyph::{point, Font, FontArc, PxScale, ScaleFont};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::codecs::jpeg::JpegEncoder;
+use image::error::{EncodingError, ImageFormatHint};
+use image::imageops::{self, FilterType};
+use image::{ColorType, Delay, DynamicImage, Frame, ImageError, ImageFormat, ImageResult, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::display::{is_linear, linear_to_srgb};
+
+// File format of the saved crops, Source keeps the format of each image
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum CropFormat {
+    Source,
+    Png,
+    Jpeg,
+    Tiff,
+    WebP,
+    Exr,
+}
+
+impl CropFormat {
+    pub const ALL: [CropFormat; 6] = [
+        CropFormat::Source,
+        CropFormat::Png,
+        CropFormat::Jpeg,
+        CropFormat::Tiff,
+        CropFormat::WebP,
+        CropFormat::Exr,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CropFormat::Source => "Same as source",
+            CropFormat::Png => "PNG",
+            CropFormat::Jpeg => "JPEG",
+            CropFormat::Tiff => "TIFF",
+            CropFormat::WebP => "WebP",
+            CropFormat::Exr => "EXR",
+        }
+    }
+
+    // Format written for an image read from `source`. Sources that can't be written fall back to PNG.
+    pub fn output_format(self, source: &Path) -> ImageFormat {
+        let format = match self {
+            CropFormat::Source => ImageFormat::from_path(source).unwrap_or(ImageFormat::Png),
+            CropFormat::Png => ImageFormat::Png,
+            CropFormat::Jpeg => ImageFormat::Jpeg,
+            CropFormat::Tiff => ImageFormat::Tiff,
+            CropFormat::WebP => ImageFormat::WebP,
+            CropFormat::Exr => ImageFormat::OpenExr,
+        };
+        if format.writing_enabled() {
+            format
+        } else {
+            ImageFormat::Png
+        }
+    }
+}
+
+// Extension of the written file: the source one when it matches the format (jpg, jpeg, ...)
+pub fn output_extension(source: &Path, format: ImageFormat) -> String {
+    match source.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ImageFormat::from_extension(ext) == Some(format) => ext.to_string(),
+        _ => format.extensions_str()[0].to_string(),
+    }
+}
+
+// What to do when a crop is saved over an existing file
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum OverwritePolicy {
+    Overwrite,
+    Increment, // add _1, _2, ... before the extension
+    Skip,
+}
+
+impl OverwritePolicy {
+    pub const ALL: [OverwritePolicy; 3] = [
+        OverwritePolicy::Overwrite,
+        OverwritePolicy::Increment,
+        OverwritePolicy::Skip,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OverwritePolicy::Overwrite => "Overwrite",
+            OverwritePolicy::Increment => "Add a number",
+            OverwritePolicy::Skip => "Skip",
+        }
+    }
+
+    // Path to write, None when the file exists and is skipped
+    pub fn resolve(self, path: PathBuf) -> Option<PathBuf> {
+        if !path.exists() {
+            return Some(path);
+        }
+        match self {
+            OverwritePolicy::Overwrite => Some(path),
+            OverwritePolicy::Skip => None,
+            OverwritePolicy::Increment => {
+                let stem = path.file_stem()?.to_str()?;
+                let extension = path.extension().and_then(|ext| ext.to_str());
+                (1..)
+                    .map(|n| {
+                        let name = match extension {
+                            Some(extension) => format!("{stem}_{n}.{extension}"),
+                            None => format!("{stem}_{n}"),
+                        };
+                        path.with_file_name(name)
+                    })
+                    .find(|candidate| !candidate.exists())
+            }
+        }
+    }
+}
+
+// Values of the placeholders of the crop filename template
+pub struct CropName<'a> {
+    pub stem: &'a str, // file name of the source without its extension
+    pub ext: &'a str,
+    pub x: u32, // visible crop, in image pixels
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+    pub index: usize,         // position of the cell, from 1
+    pub frame: Option<usize>, // frame shown, from 1, for animations
+}
+
+// Expand `{stem}`, `{ext}`, `{x}`, `{y}`, `{w}`, `{h}`, `{index}` and `{frame}` in the template.
+// Frames of animations are numbered even when the template has no `{frame}`, so they don't overwrite each other.
+// None when the name would leave the output folder (path separators or `..`).
+pub fn crop_filename(template: &str, name: &CropName) -> Option<String> {
+    let mut template = template.to_string();
+    if name.frame.is_some() && !template.contains("{frame}") {
+        template = match template.rfind('.') {
+            Some(dot) => format!("{}_frame{{frame}}{}", &template[..dot], &template[dot..]),
+            None => template + "_frame{frame}",
+        };
+    }
+    let frame = name.frame.map(|frame| format!("{frame:03}")).unwrap_or_default();
+    let filename = template
+        .replace("{stem}", name.stem)
+        .replace("{ext}", name.ext)
+        .replace("{x}", &name.x.to_string())
+        .replace("{y}", &name.y.to_string())
+        .replace("{w}", &name.w.to_string())
+        .replace("{h}", &name.h.to_string())
+        .replace("{index}", &name.index.to_string())
+        .replace("{frame}", &frame);
+    let escapes = filename.contains(['/', '\\']) || matches!(filename.as_str(), "" | "." | "..");
+    (!escapes).then_some(filename)
+}
+
+// Float values are linear light, integer formats store sRGB encoded values
+fn srgb_encoded(image: &DynamicImage) -> DynamicImage {
+    let mut rgba = image.to_rgba32f();
+    for pixel in rgba.pixels_mut() {
+        for channel in &mut pixel.0[..3] {
+            *channel = linear_to_srgb(channel.clamp(0., 1.));
+        }
+    }
+    DynamicImage::ImageRgba32F(rgba)
+}
+
+// The image in the closest color type the format can store: 16-bit and float data are kept
+// where the format supports them, alpha is dropped only by formats without it.
+// Floats quantized to 8 or 16 bits are sRGB encoded first.
+fn encodable(image: &DynamicImage, format: ImageFormat) -> DynamicImage {
+    let alpha = image.color().has_alpha();
+    let gray = !image.color().has_color();
+    let quantized = || {
+        if is_linear(image) {
+            srgb_encoded(image)
+        } else {
+            image.clone()
+        }
+    };
+    match (format, image.color()) {
+        (ImageFormat::Png, ColorType::Rgb32F) => DynamicImage::ImageRgb16(quantized().to_rgb16()),
+        (ImageFormat::Png, ColorType::Rgba32F) => DynamicImage::ImageRgba16(quantized().to_rgba16()),
+        (ImageFormat::Png, _) => image.clone(),
+        (ImageFormat::Tiff, ColorType::La8) => DynamicImage::ImageRgba8(image.to_rgba8()),
+        (ImageFormat::Tiff, ColorType::La16) => DynamicImage::ImageRgba16(image.to_rgba16()),
+        (ImageFormat::Tiff, _) => image.clone(),
+        (ImageFormat::Jpeg, _) if gray => DynamicImage::ImageLuma8(image.to_luma8()),
+        (ImageFormat::Jpeg, _) => DynamicImage::ImageRgb8(quantized().to_rgb8()),
+        (ImageFormat::OpenExr, _) if alpha => DynamicImage::ImageRgba32F(image.to_rgba32f()),
+        (ImageFormat::OpenExr | ImageFormat::Hdr, _) => DynamicImage::ImageRgb32F(image.to_rgb32f()),
+        (_, ColorType::L8 | ColorType::La8 | ColorType::Rgb8 | ColorType::Rgba8) => image.clone(),
+        (_, _) if alpha => DynamicImage::ImageRgba8(quantized().to_rgba8()),
+        (_, _) => DynamicImage::ImageRgb8(quantized().to_rgb8()),
+    }
+}
+
+// Write the image in the given format, `jpeg_quality` goes from 1 to 100
+pub fn write_image<W: Write + Seek>(
+    image: &DynamicImage,
+    format: ImageFormat,
+    jpeg_quality: u8,
+    writer: &mut W,
+) -> ImageResult<()> {
+    let image = encodable(image, format);
+    if format == ImageFormat::Jpeg {
+        let encoder = JpegEncoder::new_with_quality(writer, jpeg_quality.clamp(1, 100));
+        return image.write_with_encoder(encoder);
+    }
+    image.write_to(writer, format)
+}
+
+// Font of the labels written in exported images: the file at `path`, the font of the cell titles when empty
+pub fn label_font(path: &str) -> FontArc {
+    let builtin = || FontArc::try_from_slice(include_bytes!("../assets/fonts/IBMPlexMono-Regular.otf")).unwrap();
+    if path.is_empty() {
+        return builtin();
+    }
+    match std::fs::read(path).map(FontArc::try_from_vec) {
+        Ok(Ok(font)) => font,
+        _ => {
+            println!("Failed to load font {path}, using the default one");
+            builtin()
+        }
+    }
+}
+
+// Label written in an exported image
+pub struct LabelStyle {
+    pub font: FontArc,
+    pub size: f32, // height in pixels, 0 for no label
+    pub color: [u8; 4],
+}
+
+impl LabelStyle {
+    // Height of the band above an image holding its label
+    pub fn band_height(&self) -> u32 {
+        if self.size > 0. {
+            (self.size * 1.4).ceil() as u32
+        } else {
+            0
+        }
+    }
+}
+
+// Write `text` with its top left corner at (x, y), clipped at `max_x`
+pub fn draw_label(canvas: &mut RgbaImage, text: &str, x: f32, y: f32, max_x: u32, style: &LabelStyle) {
+    if style.size <= 0. {
+        return;
+    }
+    let scaled = style.font.as_scaled(PxScale::from(style.size));
+    let mut caret = x;
+    let mut previous = None;
+    for c in text.chars() {
+        let glyph_id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, glyph_id);
+        }
+        let glyph = glyph_id.with_scale_and_position(style.size, point(caret, y + scaled.ascent()));
+        caret += scaled.h_advance(glyph_id);
+        previous = Some(glyph_id);
+        let Some(outline) = style.font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outline.px_bounds();
+        outline.draw(|gx, gy, coverage| {
+            let (px, py) = (bounds.min.x as i32 + gx as i32, bounds.min.y as i32 + gy as i32);
+            if px < 0 || py < 0 || px as u32 >= max_x.min(canvas.width()) || py as u32 >= canvas.height() {
+                return;
+            }
+            let pixel = canvas.get_pixel_mut(px as u32, py as u32);
+            let alpha = coverage * style.color[3] as f32 / 255.;
+            for c in 0..3 {
+                pixel.0[c] = (pixel.0[c] as f32 * (1. - alpha) + style.color[c] as f32 * alpha).round() as u8;
+            }
+            pixel.0[3] = pixel.0[3].max((alpha * 255.).round() as u8);
+        });
+    }
+}
+
+// Visible crop of a cell as shown on screen, with the title of the cell
+pub struct CompositeCell {
+    pub image: RgbaImage,
+    pub label: String,
+}
+
+pub struct CompositeStyle {
+    pub columns: usize,
+    pub scale: f32, // 1 keeps the image pixels of the crops
+    pub gap: u32,   // pixels around and between the cells
+    pub background: [u8; 4],
+    pub label: LabelStyle,
+}
+
+// Resize a crop by the export scale, upscaled crops keep sharp pixels like the viewer
+pub fn scale_image(image: &RgbaImage, scale: f32) -> RgbaImage {
+    if scale == 1. {
+        return image.clone();
+    }
+    let width = ((image.width() as f32 * scale).round() as u32).max(1);
+    let height = ((image.height() as f32 * scale).round() as u32).max(1);
+    let filter = if scale > 1. {
+        FilterType::Nearest
+    } else {
+        FilterType::Triangle
+    };
+    imageops::resize(image, width, height, filter)
+}
+
+// Lay the cells out in rows of `columns` cells, like the cells on screen. Each column is as wide
+// as its widest crop and each row as high as its highest crop plus the label above the crops.
+// A None cell (not shown yet) leaves its slot empty so the next cells keep their place.
+pub fn composite(cells: &[Option<CompositeCell>], style: &CompositeStyle) -> RgbaImage {
+    let columns = style.columns.clamp(1, cells.len().max(1));
+    let rows = cells.len().div_ceil(columns);
+    let images: Vec<Option<RgbaImage>> = cells
+        .iter()
+        .map(|cell| cell.as_ref().map(|cell| scale_image(&cell.image, style.scale)))
+        .collect();
+    let band = style.label.band_height();
+
+    let mut widths = vec![0; columns];
+    let mut heights = vec![0; rows];
+    for (index, image) in images.iter().enumerate() {
+        let Some(image) = image else {
+            continue;
+        };
+        let (row, column) = (index / columns, index % columns);
+        widths[column] = widths[column].max(image.width());
+        heights[row] = heights[row].max(image.height() + band);
+    }
+    let offsets = |sizes: &[u32]| -> Vec<u32> {
+        sizes
+            .iter()
+            .scan(style.gap, |offset, size| {
+                let start = *offset;
+                *offset += size + style.gap;
+                Some(start)
+            })
+            .collect()
+    };
+    let (xs, ys) = (offsets(&widths), offsets(&heights));
+    let width = widths.iter().sum::<u32>() + style.gap * (columns as u32 + 1);
+    let height = heights.iter().sum::<u32>() + style.gap * (rows as u32 + 1);
+
+    let mut canvas = RgbaImage::from_pixel(width, height, Rgba(style.background));
+    for (index, (cell, image)) in cells.iter().zip(&images).enumerate() {
+        let (Some(cell), Some(image)) = (cell, image) else {
+            continue;
+        };
+        let (row, column) = (index / columns, index % columns);
+        let (x, y) = (xs[column], ys[row]);
+        // Crops are centered in their column, under their label
+        let image_x = x + (widths[column] - image.width()) / 2;
+        imageops::overlay(&mut canvas, image, image_x as i64, (y + band) as i64);
+        let label_y = y as f32 + (band as f32 - style.label.size) / 2.;
+        draw_label(
+            &mut canvas,
+            &cell.label,
+            x as f32,
+            label_y,
+            x + widths[column],
+            &style.label,
+        );
+    }
+    canvas
+}
+
+// One frame per crop for an A/B flicker. The frames share the size of the largest crop, the crops
+// are centered under their label and `style.columns` is ignored.
+pub fn flicker_frames(cells: &[CompositeCell], style: &CompositeStyle) -> Vec<RgbaImage> {
+    let images: Vec<RgbaImage> = cells.iter().map(|cell| scale_image(&cell.image, style.scale)).collect();
+    let band = style.label.band_height();
+    let image_width = images.iter().map(|image| image.width()).max().unwrap_or(0);
+    let image_height = images.iter().map(|image| image.height()).max().unwrap_or(0);
+    let width = image_width + 2 * style.gap;
+    let height = image_height + band + 2 * style.gap;
+
+    cells
+        .iter()
+        .zip(&images)
+        .map(|(cell, image)| {
+            let mut canvas = RgbaImage::from_pixel(width, height, Rgba(style.background));
+            let image_x = style.gap + (image_width - image.width()) / 2;
+            let image_y = style.gap + band + (image_height - image.height()) / 2;
+            imageops::overlay(&mut canvas, image, image_x as i64, image_y as i64);
+            let label_y = style.gap as f32 + (band as f32 - style.label.size) / 2.;
+            draw_label(
+                &mut canvas,
+                &cell.label,
+                style.gap as f32,
+                label_y,
+                width - style.gap,
+                &style.label,
+            );
+            canvas
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum AnimationFormat {
+    #[default]
+    Gif,
+    Apng,
+}
+
+impl AnimationFormat {
+    pub const ALL: [AnimationFormat; 2] = [AnimationFormat::Gif, AnimationFormat::Apng];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "GIF",
+            AnimationFormat::Apng => "APNG",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "gif",
+            AnimationFormat::Apng => "png",
+        }
+    }
+}
+
+// Write frames of the same size as an animation looping forever. GIF frames are reduced to 256
+// colors, APNG frames are lossless.
+pub fn write_animation<W: Write>(
+    frames: &[RgbaImage],
+    format: AnimationFormat,
+    frame_duration_ms: u32,
+    writer: W,
+) -> ImageResult<()> {
+    let Some(first) = frames.first() else {
+        return Ok(());
+    };
+    let duration = frame_duration_ms.clamp(10, u16::MAX as u32);
+    match format {
+        AnimationFormat::Gif => {
+            let mut encoder = GifEncoder::new_with_speed(writer, 10);
+            encoder.set_repeat(Repeat::Infinite)?;
+            let delay = Delay::from_numer_denom_ms(duration, 1);
+            encoder.encode_frames(frames.iter().map(|frame| Frame::from_parts(frame.clone(), 0, 0, delay)))
+        }
+        AnimationFormat::Apng => {
+            let error = |e: png::EncodingError| {
+                ImageError::Encoding(EncodingError::new(ImageFormatHint::Exact(ImageFormat::Png), e))
+            };
+            let mut encoder = png::Encoder::new(writer, first.width(), first.height());
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_animated(frames.len() as u32, 0).map_err(error)?;
+            encoder.set_frame_delay(duration as u16, 1000).map_err(error)?;
+            let mut writer = encoder.write_header().map_err(error)?;
+            for frame in frames {
+                writer.write_image_data(frame.as_raw()).map_err(error)?;
+            }
+            writer.finish().map_err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{Rgba, Rgba32FImage, RgbaImage};
+
+    use super::*;
+
+    fn round_trip(image: &DynamicImage, format: ImageFormat) -> DynamicImage {
+        let mut bytes = Cursor::new(Vec::new());
+        write_image(image, format, 90, &mut bytes).unwrap();
+        image::load_from_memory_with_format(bytes.get_ref(), format).unwrap()
+    }
+
+    #[test]
+    fn color_type_is_kept() {
+        let rgba16 = DynamicImage::ImageRgba16(image::ImageBuffer::from_pixel(2, 2, Rgba([1000u16, 2, 3, 40000])));
+        let png = round_trip(&rgba16, ImageFormat::Png);
+        assert_eq!(png.color(), ColorType::Rgba16);
+        assert_eq!(png.as_rgba16().unwrap().get_pixel(1, 1).0, [1000, 2, 3, 40000]);
+
+        let float = DynamicImage::ImageRgba32F(Rgba32FImage::from_pixel(2, 2, Rgba([2.5, 0.5, 0., 1.])));
+        assert_eq!(round_trip(&float, ImageFormat::OpenExr).color(), ColorType::Rgba32F);
+        assert_eq!(round_trip(&float, ImageFormat::Tiff).color(), ColorType::Rgba32F);
+    }
+
+    #[test]
+    fn floats_are_srgb_encoded_in_integer_formats() {
+        let float = DynamicImage::ImageRgb32F(image::Rgb32FImage::from_pixel(2, 2, image::Rgb([0.2, 2.5, -1.])));
+        let encoded = (linear_to_srgb(0.2) * 65535.).round() as u16;
+        let png = round_trip(&float, ImageFormat::Png);
+        assert_eq!(png.as_rgb16().unwrap().get_pixel(0, 0).0, [encoded, 65535, 0]);
+        let webp = round_trip(&float, ImageFormat::WebP);
+        let encoded = (linear_to_srgb(0.2) * 255.).round() as u8;
+        assert_eq!(webp.as_rgb8().unwrap().get_pixel(0, 0).0, [encoded, 255, 0]);
+    }
+
+    #[test]
+    fn jpeg_drops_alpha() {
+        let rgba = DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 8, Rgba([200, 100, 50, 128])));
+        assert_eq!(round_trip(&rgba, ImageFormat::Jpeg).color(), ColorType::Rgb8);
+    }
+
+    #[test]
+    fn output_format_and_extension() {
+        let source = Path::new("dir/photo.jpeg");
+        assert_eq!(CropFormat::Source.output_format(source), ImageFormat::Jpeg);
+        assert_eq!(output_extension(source, ImageFormat::Jpeg), "jpeg");
+        assert_eq!(CropFormat::Png.output_format(source), ImageFormat::Png);
+        assert_eq!(output_extension(source, ImageFormat::Png), "png");
+        // No encoder for this format
+        assert_eq!(
+            CropFormat::Source.output_format(Path::new("scan.dds")),
+            ImageFormat::Png
+        );
+    }
+
+    #[test]
+    fn template_placeholders() {
+        let mut name = CropName {
+            stem: "photo",
+            ext: "png",
+            x: 10,
+            y: 20,
+            w: 300,
+            h: 200,
+            index: 2,
+            frame: None,
+        };
+        assert_eq!(crop_filename("{stem}_crop.{ext}", &name).unwrap(), "photo_crop.png");
+        assert_eq!(
+            crop_filename("{stem}_{x}_{y}_{w}x{h}_{index}.{ext}", &name).unwrap(),
+            "photo_10_20_300x200_2.png"
+        );
+        name.frame = Some(7);
+        assert_eq!(
+            crop_filename("{stem}_crop.{ext}", &name).unwrap(),
+            "photo_crop_frame007.png"
+        );
+        assert_eq!(crop_filename("{frame}_{stem}.{ext}", &name).unwrap(), "007_photo.png");
+    }
+
+    #[test]
+    fn crop_filenames_stay_in_the_output_folder() {
+        let mut name = CropName {
+            stem: "photo",
+            ext: "png",
+            x: 0,
+            y: 0,
+            w: 1,
+            h: 1,
+            index: 1,
+            frame: None,
+        };
+        assert_eq!(crop_filename("../{stem}.{ext}", &name), None);
+        assert_eq!(crop_filename("crops/{stem}.{ext}", &name), None);
+        assert_eq!(crop_filename("crops\\{stem}.{ext}", &name), None);
+        assert_eq!(crop_filename("{stem}..{ext}", &name).unwrap(), "photo..png");
+        name.stem = "..";
+        assert_eq!(crop_filename("{stem}", &name), None);
+    }
+
+    #[test]
+    fn overwrite_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("crop.png");
+        assert_eq!(OverwritePolicy::Skip.resolve(path.clone()), Some(path.clone()));
+
+        std::fs::File::create(&path).unwrap();
+        std::fs::File::create(dir.path().join("crop_1.png")).unwrap();
+        assert_eq!(OverwritePolicy::Overwrite.resolve(path.clone()), Some(path.clone()));
+        assert_eq!(OverwritePolicy::Skip.resolve(path.clone()), None);
+        assert_eq!(
+            OverwritePolicy::Increment.resolve(path),
+            Some(dir.path().join("crop_2.png"))
+        );
+    }
+
+    fn style(columns: usize, label_size: f32) -> CompositeStyle {
+        CompositeStyle {
+            columns,
+            scale: 1.,
+            gap: 2,
+            background: [0, 0, 0, 255],
+            label: LabelStyle {
+                font: label_font(""),
+                size: label_size,
+                color: [255, 255, 255, 255],
+            },
+        }
+    }
+
+    fn cell(width: u32, height: u32, value: u8) -> CompositeCell {
+        CompositeCell {
+            image: RgbaImage::from_pixel(width, height, Rgba([value, value, value, 255])),
+            label: "image.png".to_string(),
+        }
+    }
+
+    #[test]
+    fn cells_are_laid_out_in_rows() {
+        let cells = [Some(cell(4, 2, 10)), Some(cell(6, 3, 20)), Some(cell(2, 2, 30))];
+        let canvas = composite(&cells, &style(2, 0.));
+        // Columns of 4 and 6 pixels, rows of 3 and 2, gaps of 2
+        assert_eq!(canvas.dimensions(), (2 + 4 + 2 + 6 + 2, 2 + 3 + 2 + 2 + 2));
+        assert_eq!(canvas.get_pixel(2, 2).0[0], 10);
+        assert_eq!(canvas.get_pixel(1, 1).0[0], 0);
+        assert_eq!(canvas.get_pixel(2, 4).0[0], 0);
+        assert_eq!(canvas.get_pixel(8, 4).0[0], 20);
+        // The narrower crop is centered in its column
+        assert_eq!(canvas.get_pixel(3, 7).0[0], 30);
+        assert_eq!(canvas.get_pixel(2, 7).0[0], 0);
+
+        let row = composite(&cells, &style(3, 0.));
+        assert_eq!(row.dimensions(), (2 + 4 + 2 + 6 + 2 + 2 + 2, 2 + 3 + 2));
+        assert_eq!(scale_image(&cell(4, 2, 10).image, 2.).dimensions(), (8, 4));
+    }
+
+    #[test]
+    fn missing_cells_keep_their_slot() {
+        let cells = [None, Some(cell(4, 2, 10)), Some(cell(6, 3, 20))];
+        let canvas = composite(&cells, &style(2, 0.));
+        // The first column is empty, the second cell stays in the second column
+        assert_eq!(canvas.dimensions(), (2 + 6 + 2 + 4 + 2, 2 + 2 + 2 + 3 + 2));
+        assert_eq!(canvas.get_pixel(2 + 6 + 2, 2).0[0], 10);
+        assert_eq!(canvas.get_pixel(2, 2 + 2 + 2).0[0], 20);
+    }
+
+    #[test]
+    fn labels_are_drawn_above_the_crops() {
+        let canvas = composite(&[Some(cell(80, 4, 10))], &style(1, 14.));
+        let band = style(1, 14.).label.band_height();
+        assert_eq!(canvas.height(), 2 + band + 4 + 2);
+        let label_pixels = canvas.pixels().take((canvas.width() * (2 + band)) as usize);
+        assert!(label_pixels.filter(|p| p.0[0] > 128).count() > 10);
+    }
+
+    #[test]
+    fn flicker_frames_share_the_largest_size() {
+        let frames = flicker_frames(&[cell(4, 2, 10), cell(2, 4, 20)], &style(1, 0.));
+        assert_eq!(frames.len(), 2);
+        assert!(frames.iter().all(|frame| frame.dimensions() == (2 + 4 + 2, 2 + 4 + 2)));
+        // Smaller crops are centered
+        assert_eq!(frames[0].get_pixel(2, 3).0[0], 10);
+        assert_eq!(frames[0].get_pixel(2, 2).0[0], 0);
+        assert_eq!(frames[1].get_pixel(3, 2).0[0], 20);
+        assert_eq!(frames[1].get_pixel(2, 2).0[0], 0);
+    }
+
+    #[test]
+    fn animations_keep_every_frame() {
+        use image::codecs::gif::GifDecoder;
+        use image::codecs::png::PngDecoder;
+        use image::AnimationDecoder;
+
+        let frames = flicker_frames(&[cell(4, 4, 10), cell(4, 4, 200)], &style(1, 0.));
+        for format in AnimationFormat::ALL {
+            let mut bytes = Vec::new();
+            write_animation(&frames, format, 250, &mut bytes).unwrap();
+            let decoded: Vec<Frame> = match format {
+                AnimationFormat::Gif => GifDecoder::new(Cursor::new(&bytes)).unwrap().into_frames(),
+                AnimationFormat::Apng => PngDecoder::new(Cursor::new(&bytes))
+                    .unwrap()
+                    .apng()
+                    .unwrap()
+                    .into_frames(),
+            }
+            .collect_frames()
+            .unwrap();
+            assert_eq!(decoded.len(), 2);
+            assert_eq!(decoded[1].delay().numer_denom_ms(), (250, 1));
+            assert_eq!(decoded[1].buffer().get_pixel(4, 4).0[0], 200);
+        }
+    }
+}
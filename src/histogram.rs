@@ -0,0 +1,199 @@
+use image::{ColorType, DynamicImage, Rgba32FImage};
+
+use crate::metrics::Region;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    Luma,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 5] = [
+        Channel::Red,
+        Channel::Green,
+        Channel::Blue,
+        Channel::Alpha,
+        Channel::Luma,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Channel::Red => "R",
+            Channel::Green => "G",
+            Channel::Blue => "B",
+            Channel::Alpha => "A",
+            Channel::Luma => "Luma",
+        }
+    }
+}
+
+// Counts of values per bin for each channel of an image.
+// Bins split [min, max] evenly, the last bin includes max.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub min: f32,
+    pub max: f32,
+    pub channels: Vec<(Channel, Vec<u32>)>,
+}
+
+// Values of a region of an image, cropped then converted once for its range and its histogram
+pub struct RegionValues {
+    values: Rgba32FImage,
+    color: ColorType,
+}
+
+impl RegionValues {
+    pub fn new(image: &DynamicImage, region: Region) -> Self {
+        RegionValues {
+            values: image
+                .crop_imm(region.x, region.y, region.width, region.height)
+                .to_rgba32f(),
+            color: image.color(),
+        }
+    }
+}
+
+// Range covered by the bins: [0, 1] for integer images (8-bit and 16-bit values are normalized),
+// the finite values of the region for float images
+pub fn value_range(region: &RegionValues) -> (f32, f32) {
+    if !matches!(region.color, ColorType::Rgb32F | ColorType::Rgba32F) {
+        return (0., 1.);
+    }
+    // The alpha of images without one is the 1 added by the conversion
+    let stored = if region.color.has_alpha() { 4 } else { 3 };
+    let mut range = (f32::INFINITY, f32::NEG_INFINITY);
+    let values = region.values.pixels().flat_map(|p| p.0.into_iter().take(stored));
+    for value in values.filter(|v| v.is_finite()) {
+        range = (range.0.min(value), range.1.max(value));
+    }
+    if range.0 > range.1 {
+        (0., 1.)
+    } else if range.0 == range.1 {
+        (range.0, range.0 + 1.)
+    } else {
+        range
+    }
+}
+
+// Channels stored in the image, the luma only makes sense for color images
+fn channels(color: ColorType) -> Vec<Channel> {
+    let mut channels = if color.has_color() {
+        vec![Channel::Red, Channel::Green, Channel::Blue, Channel::Luma]
+    } else {
+        vec![Channel::Luma]
+    };
+    if color.has_alpha() {
+        channels.push(Channel::Alpha);
+    }
+    channels
+}
+
+// Histogram of the region of the image with `bins` bins over `range`.
+// Values outside of the range and non finite values are not counted.
+pub fn compute(region: &RegionValues, range: (f32, f32), bins: usize) -> Histogram {
+    let channels = channels(region.color);
+    let mut counts = vec![vec![0u32; bins]; channels.len()];
+    let scale = bins as f32 / (range.1 - range.0);
+    for pixel in region.values.pixels() {
+        let [r, g, b, a] = pixel.0;
+        for (channel, counts) in channels.iter().zip(counts.iter_mut()) {
+            let value = match channel {
+                Channel::Red => r,
+                Channel::Green => g,
+                Channel::Blue => b,
+                Channel::Alpha => a,
+                Channel::Luma if region.color.has_color() => 0.2126 * r + 0.7152 * g + 0.0722 * b,
+                Channel::Luma => r,
+            };
+            if !value.is_finite() || value < range.0 || value > range.1 {
+                continue;
+            }
+            let bin = (((value - range.0) * scale) as usize).min(bins - 1);
+            counts[bin] += 1;
+        }
+    }
+    Histogram {
+        min: range.0,
+        max: range.1,
+        channels: channels.into_iter().zip(counts).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, ImageBuffer, Luma, Rgb, Rgb32FImage, RgbaImage};
+
+    use super::*;
+
+    fn counts(histogram: &Histogram, channel: Channel) -> &[u32] {
+        &histogram.channels.iter().find(|(c, _)| *c == channel).unwrap().1
+    }
+
+    #[test]
+    fn eight_bit_values_have_their_own_bin() {
+        let image = DynamicImage::ImageLuma8(GrayImage::from_fn(256, 1, |x, _| Luma([x as u8])));
+        let region = RegionValues::new(&image, Region::full(&image));
+        let histogram = compute(&region, value_range(&region), 256);
+        assert_eq!(histogram.channels.len(), 1);
+        assert!(counts(&histogram, Channel::Luma).iter().all(|c| *c == 1));
+    }
+
+    #[test]
+    fn sixteen_bit_values_are_binned_evenly() {
+        let buffer: ImageBuffer<Luma<u16>, Vec<u16>> =
+            ImageBuffer::from_fn(4, 1, |x, _| Luma([[0, 255, 256, 65535][x as usize]]));
+        let image = DynamicImage::ImageLuma16(buffer);
+        let region = RegionValues::new(&image, Region::full(&image));
+        let histogram = compute(&region, value_range(&region), 256);
+        let luma = counts(&histogram, Channel::Luma);
+        assert_eq!(luma[0], 2);
+        assert_eq!(luma[1], 1);
+        assert_eq!(luma[255], 1);
+    }
+
+    #[test]
+    fn float_range_follows_data() {
+        let mut buffer = Rgb32FImage::from_pixel(2, 1, Rgb([0.5, 0.5, 0.5]));
+        buffer.put_pixel(1, 0, Rgb([-1., 4., f32::NAN]));
+        let image = DynamicImage::ImageRgb32F(buffer);
+        let region = RegionValues::new(&image, Region::full(&image));
+        assert_eq!(value_range(&region), (-1., 4.));
+        let histogram = compute(&region, (-1., 4.), 5);
+        assert_eq!(counts(&histogram, Channel::Red), &[1, 1, 0, 0, 0]);
+        assert_eq!(counts(&histogram, Channel::Green), &[0, 1, 0, 0, 1]);
+        // NaN is not counted
+        assert_eq!(counts(&histogram, Channel::Blue), &[0, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn float_range_ignores_the_missing_alpha() {
+        let mut buffer = Rgb32FImage::from_pixel(2, 1, Rgb([1000.; 3]));
+        buffer.put_pixel(1, 0, Rgb([5000.; 3]));
+        let image = DynamicImage::ImageRgb32F(buffer);
+        let region = RegionValues::new(&image, Region::full(&image));
+        assert_eq!(value_range(&region), (1000., 5000.));
+    }
+
+    #[test]
+    fn rgba_channels_and_region() {
+        let mut buffer = RgbaImage::from_pixel(4, 4, image::Rgba([255, 0, 0, 255]));
+        buffer.put_pixel(0, 0, image::Rgba([0, 255, 0, 0]));
+        let image = DynamicImage::ImageRgba8(buffer);
+        let region = Region {
+            x: 0,
+            y: 0,
+            width: 1,
+            height: 1,
+        };
+        let histogram = compute(&RegionValues::new(&image, region), (0., 1.), 4);
+        let names: Vec<_> = histogram.channels.iter().map(|(c, _)| c.name()).collect();
+        assert_eq!(names, ["R", "G", "B", "Luma", "A"]);
+        assert_eq!(counts(&histogram, Channel::Green), &[0, 0, 0, 1]);
+        assert_eq!(counts(&histogram, Channel::Alpha), &[1, 0, 0, 0]);
+        assert_eq!(counts(&histogram, Channel::Luma), &[0, 0, 1, 0]);
+    }
+}
@@ -0,0 +1,106 @@
+use image::{DynamicImage, GenericImageView};
+
+// Channel names of the stored color type
+fn channel_labels(image: &DynamicImage) -> &'static [&'static str] {
+    match image.color().channel_count() {
+        1 => &["L"],
+        2 => &["L", "A"],
+        3 => &["R", "G", "B"],
+        _ => &["R", "G", "B", "A"],
+    }
+}
+
+// Raw stored values of a pixel: integers for 8 and 16 bits, floats for float images
+pub fn raw_values(image: &DynamicImage, x: u32, y: u32) -> Option<Vec<String>> {
+    if !image.in_bounds(x, y) {
+        return None;
+    }
+    let values = match image {
+        DynamicImage::ImageLuma8(buffer) => buffer.get_pixel(x, y).0.iter().map(u8::to_string).collect(),
+        DynamicImage::ImageLumaA8(buffer) => buffer.get_pixel(x, y).0.iter().map(u8::to_string).collect(),
+        DynamicImage::ImageRgb8(buffer) => buffer.get_pixel(x, y).0.iter().map(u8::to_string).collect(),
+        DynamicImage::ImageRgba8(buffer) => buffer.get_pixel(x, y).0.iter().map(u8::to_string).collect(),
+        DynamicImage::ImageLuma16(buffer) => buffer.get_pixel(x, y).0.iter().map(u16::to_string).collect(),
+        DynamicImage::ImageLumaA16(buffer) => buffer.get_pixel(x, y).0.iter().map(u16::to_string).collect(),
+        DynamicImage::ImageRgb16(buffer) => buffer.get_pixel(x, y).0.iter().map(u16::to_string).collect(),
+        DynamicImage::ImageRgba16(buffer) => buffer.get_pixel(x, y).0.iter().map(u16::to_string).collect(),
+        DynamicImage::ImageRgb32F(buffer) => buffer.get_pixel(x, y).0.iter().map(|v| format_float(*v)).collect(),
+        DynamicImage::ImageRgba32F(buffer) => buffer.get_pixel(x, y).0.iter().map(|v| format_float(*v)).collect(),
+        // DynamicImage is non exhaustive, show future variants as 8-bit RGBA
+        _ => image.get_pixel(x, y).0.iter().map(u8::to_string).collect(),
+    };
+    Some(values)
+}
+
+fn format_float(value: f32) -> String {
+    if value != 0. && (value.abs() >= 1e4 || value.abs() < 1e-3) {
+        format!("{value:.3e}")
+    } else {
+        format!("{value:.4}")
+    }
+}
+
+// Text shown next to a cursor: pixel coordinate then one line per channel
+pub fn describe_pixel(image: &DynamicImage, x: u32, y: u32) -> Option<String> {
+    let values = raw_values(image, x, y)?;
+    let mut text = format!("({x}, {y})");
+    for (label, value) in channel_labels(image).iter().zip(values) {
+        text.push_str(&format!("\n{label} {value}"));
+    }
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, ImageBuffer, Luma, Rgb, RgbImage, Rgba, Rgba32FImage};
+
+    use super::*;
+
+    #[test]
+    fn rgb8_values() {
+        let mut buffer = RgbImage::new(2, 2);
+        buffer.put_pixel(1, 0, Rgb([255, 128, 0]));
+        let image = DynamicImage::ImageRgb8(buffer);
+        assert_eq!(
+            raw_values(&image, 1, 0),
+            Some(vec!["255".into(), "128".into(), "0".into()])
+        );
+        assert_eq!(describe_pixel(&image, 1, 0).unwrap(), "(1, 0)\nR 255\nG 128\nB 0");
+    }
+
+    #[test]
+    fn luma16_values_are_not_normalized() {
+        let buffer: ImageBuffer<Luma<u16>, Vec<u16>> = ImageBuffer::from_pixel(1, 1, Luma([40000]));
+        let image = DynamicImage::ImageLuma16(buffer);
+        assert_eq!(describe_pixel(&image, 0, 0).unwrap(), "(0, 0)\nL 40000");
+    }
+
+    #[test]
+    fn float_values_keep_range() {
+        let buffer = Rgba32FImage::from_pixel(1, 1, Rgba([12.5, -0.25, 0., 1.]));
+        let image = DynamicImage::ImageRgba32F(buffer);
+        assert_eq!(
+            raw_values(&image, 0, 0),
+            Some(vec![
+                "12.5000".into(),
+                "-0.2500".into(),
+                "0.0000".into(),
+                "1.0000".into()
+            ])
+        );
+    }
+
+    #[test]
+    fn float_extremes_use_exponent() {
+        assert_eq!(format_float(123456.), "1.235e5");
+        assert_eq!(format_float(0.00001), "1.000e-5");
+        assert_eq!(format_float(f32::NAN), "NaN");
+    }
+
+    #[test]
+    fn outside_is_none() {
+        let image = DynamicImage::ImageLuma8(GrayImage::new(2, 2));
+        assert!(raw_values(&image, 2, 0).is_none());
+        assert!(describe_pixel(&image, 0, 5).is_none());
+    }
+}
@@ -0,0 +1,446 @@
+use std::io::{Read, Seek};
+
+use exr::block::lines::LineRef;
+use exr::block::reader::ChunksReader;
+use exr::meta::attribute::SampleType;
+use exr::meta::header::Header;
+use exr::meta::MetaData;
+use exr::prelude::f16;
+use image::{DynamicImage, ImageBuffer, ImageFormat};
+
+// Layers of a file holding several images: the pages of a TIFF, the parts and channel groups of an
+// EXR (e.g. `diffuse.R`, `diffuse.G`, `diffuse.B` and `depth.Z` give the layers `diffuse` and `depth`).
+// Empty when the file holds a single image.
+pub fn list_from<R: Read + Seek>(reader: R, format: ImageFormat) -> Vec<String> {
+    let names = match format {
+        ImageFormat::OpenExr => match MetaData::read_from_buffered(reader, false) {
+            Ok(meta) => exr_header_groups(&meta.headers)
+                .into_iter()
+                .map(|group| group.name)
+                .collect(),
+            Err(e) => {
+                println!("Failed to read EXR layers: {e}");
+                Vec::new()
+            }
+        },
+        ImageFormat::Tiff => (0..tiff_page_count(reader))
+            .map(|page| format!("Page {}", page + 1))
+            .collect(),
+        _ => Vec::new(),
+    };
+    if names.len() > 1 {
+        names
+    } else {
+        Vec::new()
+    }
+}
+
+// Decode the layer at `index` in the list of the file
+pub fn decode_from<R: Read + Seek>(reader: R, format: ImageFormat, index: usize) -> Option<DynamicImage> {
+    match format {
+        ImageFormat::OpenExr => decode_exr(reader, index),
+        ImageFormat::Tiff => decode_tiff_page(reader, index),
+        _ => None,
+    }
+}
+
+// Channels of an EXR part sharing the same prefix
+#[derive(Debug, PartialEq)]
+struct ExrGroup {
+    name: String,
+    part: usize,
+    prefix: String,
+}
+
+fn channel_prefix(channel: &str) -> &str {
+    channel.rsplit_once('.').map_or("", |(prefix, _)| prefix)
+}
+
+fn channel_suffix(channel: &str) -> &str {
+    channel.rsplit_once('.').map_or(channel, |(_, suffix)| suffix)
+}
+
+// Groups in file order. The name joins the part name and the prefix, a group without any of them
+// is named after its channels (e.g. `RGBA` or `Z`).
+fn exr_groups(parts: impl Iterator<Item = (Option<String>, Vec<String>)>) -> Vec<ExrGroup> {
+    let mut groups = Vec::new();
+    for (part, (part_name, channels)) in parts.enumerate() {
+        let mut prefixes: Vec<&str> = Vec::new();
+        for prefix in channels.iter().map(|channel| channel_prefix(channel)) {
+            if !prefixes.contains(&prefix) {
+                prefixes.push(prefix);
+            }
+        }
+        for prefix in prefixes {
+            let mut name: Vec<String> = part_name.iter().cloned().collect();
+            if !prefix.is_empty() {
+                name.push(prefix.to_string());
+            }
+            if name.is_empty() {
+                let suffixes = channels
+                    .iter()
+                    .filter(|channel| channel_prefix(channel) == prefix)
+                    .map(|channel| channel_suffix(channel).to_string());
+                name.push(order_channels(suffixes.collect()).concat());
+            }
+            groups.push(ExrGroup {
+                name: name.join("."),
+                part,
+                prefix: prefix.to_string(),
+            });
+        }
+    }
+    groups
+}
+
+fn exr_header_groups(headers: &[Header]) -> Vec<ExrGroup> {
+    exr_groups(headers.iter().map(|header| {
+        let channels = header.channels.list.iter().map(|channel| channel.name.to_string());
+        (
+            header.own_attributes.layer_name.as_ref().map(|name| name.to_string()),
+            channels.collect(),
+        )
+    }))
+}
+
+// EXR sorts channels by name, show them as RGBA (or XYZ) when possible
+fn order_channels(mut names: Vec<String>) -> Vec<String> {
+    let rank = |name: &String| {
+        ["R", "G", "B", "A", "X", "Y", "Z"]
+            .iter()
+            .position(|known| name.eq_ignore_ascii_case(known))
+            .unwrap_or(usize::MAX)
+    };
+    names.sort_by_key(rank);
+    names
+}
+
+// Only the blocks of the part holding the group are read, and only the channels of the group are kept
+fn decode_exr<R: Read + Seek>(reader: R, index: usize) -> Option<DynamicImage> {
+    let chunks = match exr::block::read(reader, false) {
+        Ok(chunks) => chunks,
+        Err(e) => {
+            println!("Failed to decode EXR: {e}");
+            return None;
+        }
+    };
+    let group = exr_header_groups(chunks.headers()).into_iter().nth(index)?;
+    let header = chunks.headers().get(group.part)?.clone();
+    let size = header.layer_size;
+    let mut planes: Vec<(String, Vec<f32>)> = header
+        .channels
+        .list
+        .iter()
+        .map(|channel| {
+            let name = channel.name.to_string();
+            let samples = if channel_prefix(&name) == group.prefix {
+                vec![0.; size.area()]
+            } else {
+                Vec::new()
+            };
+            (name, samples)
+        })
+        .collect();
+
+    let read = chunks
+        .filter_chunks(false, |_, tile, block| {
+            block.layer == group.part && tile.is_largest_resolution_level()
+        })
+        .and_then(|blocks| {
+            blocks.decompress_sequential(false, |_, block| {
+                for line in block.lines(&header.channels) {
+                    let location = line.location;
+                    let start = location.position.y() * size.width() + location.position.x();
+                    let samples = planes[location.channel].1.get_mut(start..start + location.sample_count);
+                    if let Some(samples) = samples {
+                        read_exr_line(line, header.channels.list[location.channel].sample_type, samples)?;
+                    }
+                }
+                Ok(())
+            })
+        });
+    if let Err(e) = read {
+        println!("Failed to decode EXR: {e}");
+        return None;
+    }
+    planes.retain(|(name, _)| channel_prefix(name) == group.prefix);
+    exr_group_image(size.width() as u32, size.height() as u32, &planes)
+}
+
+fn read_exr_line(line: LineRef, sample_type: SampleType, samples: &mut [f32]) -> exr::error::UnitResult {
+    match sample_type {
+        SampleType::F32 => line.read_samples_into_slice(samples)?,
+        SampleType::F16 => {
+            for (sample, value) in samples.iter_mut().zip(line.read_samples::<f16>()) {
+                *sample = value?.to_f32();
+            }
+        }
+        SampleType::U32 => {
+            for (sample, value) in samples.iter_mut().zip(line.read_samples::<u32>()) {
+                *sample = value? as f32;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Pixels of a group: R, G, B and A (or X, Y and Z) channels as color, a single channel as gray
+fn exr_group_image(width: u32, height: u32, channels: &[(String, Vec<f32>)]) -> Option<DynamicImage> {
+    let find = |suffix: &str| {
+        channels
+            .iter()
+            .find(|(name, _)| channel_suffix(name).eq_ignore_ascii_case(suffix))
+            .map(|(_, samples)| samples)
+    };
+    let mut planes: Vec<&Vec<f32>> = match (find("R"), find("G"), find("B"), find("X"), find("Y"), find("Z")) {
+        (Some(r), Some(g), Some(b), ..) | (.., Some(r), Some(g), Some(b)) => vec![r, g, b],
+        _ => {
+            // Channels are sorted by name, so alpha may come first (e.g. A and Y)
+            let colors: Vec<&Vec<f32>> = channels
+                .iter()
+                .filter(|(name, _)| !channel_suffix(name).eq_ignore_ascii_case("A"))
+                .map(|(_, samples)| samples)
+                .collect();
+            match colors[..] {
+                [r, g, b, ..] => vec![r, g, b],
+                [gray, ..] => vec![gray, gray, gray],
+                [] => return None,
+            }
+        }
+    };
+    if let Some(alpha) = find("A") {
+        planes.push(alpha);
+    }
+
+    let samples: Vec<f32> = (0..planes[0].len())
+        .flat_map(|i| planes.iter().map(move |plane| plane[i]))
+        .collect();
+    if planes.len() == 4 {
+        ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgba32F)
+    } else {
+        ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgb32F)
+    }
+}
+
+fn tiff_page_count<R: Read + Seek>(reader: R) -> usize {
+    let Ok(mut decoder) = tiff::decoder::Decoder::new(reader) else {
+        return 0;
+    };
+    let mut count = 1;
+    while decoder.more_images() && decoder.next_image().is_ok() {
+        count += 1;
+    }
+    count
+}
+
+fn decode_tiff_page<R: Read + Seek>(reader: R, page: usize) -> Option<DynamicImage> {
+    use tiff::decoder::DecodingResult;
+    use tiff::ColorType;
+
+    let mut decoder = tiff::decoder::Decoder::new(reader).ok()?;
+    decoder.seek_to_image(page).ok()?;
+    let (width, height) = decoder.dimensions().ok()?;
+    let color = decoder.colortype().ok()?;
+    let data = match decoder.read_image() {
+        Ok(data) => data,
+        Err(e) => {
+            println!("Failed to decode TIFF page {}: {e}", page + 1);
+            return None;
+        }
+    };
+
+    let image = match (color, data) {
+        (ColorType::Gray(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma8)
+        }
+        (ColorType::GrayA(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA8)
+        }
+        (ColorType::RGB(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb8)
+        }
+        (ColorType::RGBA(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba8)
+        }
+        (ColorType::Gray(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma16)
+        }
+        (ColorType::GrayA(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA16)
+        }
+        (ColorType::RGB(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb16)
+        }
+        (ColorType::RGBA(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba16)
+        }
+        // Bits are packed from the most significant one, each row starts on a new byte
+        (ColorType::Gray(1), DecodingResult::U8(data)) => {
+            let row_bytes = (width as usize).div_ceil(8);
+            let data = data
+                .chunks(row_bytes)
+                .flat_map(|row| (0..width as usize).map(move |x| ((row[x / 8] >> (7 - x % 8)) & 1) * 255))
+                .collect();
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma8)
+        }
+        (ColorType::CMYK(8), DecodingResult::U8(data)) => {
+            let data = data
+                .chunks_exact(4)
+                .flat_map(|cmyk| {
+                    let white = 255 - cmyk[3] as u16;
+                    cmyk[..3]
+                        .iter()
+                        .map(move |ink| ((255 - *ink as u16) * white / 255) as u8)
+                })
+                .collect();
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb8)
+        }
+        // There is no gray float image, the value is repeated in RGB
+        (ColorType::Gray(32), DecodingResult::F32(data)) => {
+            let data = data.iter().flat_map(|value| [*value; 3]).collect();
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb32F)
+        }
+        (ColorType::RGB(32), DecodingResult::F32(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb32F)
+        }
+        (ColorType::RGBA(32), DecodingResult::F32(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba32F)
+        }
+        (color, _) => {
+            println!("Unsupported color type of TIFF page {}: {color:?}", page + 1);
+            None
+        }
+    };
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use exr::image::{AnyChannels, FlatSamples, Layer};
+    use exr::meta::header::ImageAttributes;
+    use exr::prelude::{AnyChannel, Encoding, LayerAttributes, Vec2, WritableImage};
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    use exr::image::Image;
+
+    use super::*;
+
+    fn exr_layer(name: Option<&str>, channels: &[(&str, f32)]) -> Layer<AnyChannels<FlatSamples>> {
+        let size = Vec2(3, 2);
+        let channels: Vec<_> = channels
+            .iter()
+            .map(|(name, value)| AnyChannel::new(*name, FlatSamples::F32(vec![*value; size.area()])))
+            .collect();
+        let attributes = match name {
+            Some(name) => LayerAttributes::named(name),
+            None => LayerAttributes::default(),
+        };
+        Layer::new(
+            size,
+            attributes,
+            Encoding::UNCOMPRESSED,
+            AnyChannels::sort(channels.into()),
+        )
+    }
+
+    fn exr_file(channels: &[(&str, f32)]) -> Vec<u8> {
+        let mut bytes = Cursor::new(Vec::new());
+        Image::from_layer(exr_layer(None, channels))
+            .write()
+            .to_buffered(&mut bytes)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn exr_channel_groups() {
+        let bytes = exr_file(&[
+            ("R", 0.1),
+            ("G", 0.2),
+            ("B", 0.3),
+            ("A", 1.),
+            ("depth.Z", 7.),
+            ("normal.X", -1.),
+            ("normal.Y", 0.5),
+            ("normal.Z", 2.),
+        ]);
+        let names = list_from(Cursor::new(&bytes), ImageFormat::OpenExr);
+        assert_eq!(names, ["RGBA", "depth", "normal"]);
+
+        let rgba = decode_from(Cursor::new(&bytes), ImageFormat::OpenExr, 0).unwrap();
+        assert_eq!(rgba.to_rgba32f().get_pixel(2, 1).0, [0.1, 0.2, 0.3, 1.]);
+        let depth = decode_from(Cursor::new(&bytes), ImageFormat::OpenExr, 1).unwrap();
+        assert_eq!(depth.to_rgb32f().get_pixel(0, 0).0, [7., 7., 7.]);
+        let normal = decode_from(Cursor::new(&bytes), ImageFormat::OpenExr, 2).unwrap();
+        assert_eq!((normal.width(), normal.height()), (3, 2));
+        assert_eq!(normal.to_rgb32f().get_pixel(1, 0).0, [-1., 0.5, 2.]);
+        assert!(decode_from(Cursor::new(&bytes), ImageFormat::OpenExr, 3).is_none());
+    }
+
+    #[test]
+    fn exr_alpha_is_not_a_color() {
+        let bytes = exr_file(&[("Y", 0.25), ("A", 0.5)]);
+        let gray = decode_from(Cursor::new(&bytes), ImageFormat::OpenExr, 0).unwrap();
+        assert_eq!(gray.to_rgba32f().get_pixel(1, 1).0, [0.25, 0.25, 0.25, 0.5]);
+
+        let bytes = exr_file(&[("A", 0.5), ("U", 0.1), ("V", 0.2), ("W", 0.3)]);
+        let uvw = decode_from(Cursor::new(&bytes), ImageFormat::OpenExr, 0).unwrap();
+        assert_eq!(uvw.to_rgba32f().get_pixel(0, 1).0, [0.1, 0.2, 0.3, 0.5]);
+    }
+
+    #[test]
+    fn exr_parts() {
+        let layers = vec![
+            exr_layer(Some("beauty"), &[("R", 0.1), ("G", 0.2), ("B", 0.3)]),
+            exr_layer(Some("mask"), &[("Y", 0.5)]),
+        ];
+        let mut bytes = Cursor::new(Vec::new());
+        Image::from_layers(ImageAttributes::with_size(Vec2(3, 2)), layers)
+            .write()
+            .to_buffered(&mut bytes)
+            .unwrap();
+        let bytes = bytes.into_inner();
+        assert_eq!(list_from(Cursor::new(&bytes), ImageFormat::OpenExr), ["beauty", "mask"]);
+
+        let mask = decode_from(Cursor::new(&bytes), ImageFormat::OpenExr, 1).unwrap();
+        assert_eq!(mask.to_rgb32f().get_pixel(2, 1).0, [0.5, 0.5, 0.5]);
+        let beauty = decode_from(Cursor::new(&bytes), ImageFormat::OpenExr, 0).unwrap();
+        assert_eq!(beauty.to_rgb32f().get_pixel(0, 0).0, [0.1, 0.2, 0.3]);
+    }
+
+    #[test]
+    fn single_layer_exr_has_no_list() {
+        let bytes = exr_file(&[("R", 0.), ("G", 0.), ("B", 0.)]);
+        assert!(list_from(Cursor::new(bytes), ImageFormat::OpenExr).is_empty());
+    }
+
+    #[test]
+    fn tiff_pages() {
+        let mut bytes = Cursor::new(Vec::new());
+        {
+            let mut encoder = TiffEncoder::new(&mut bytes).unwrap();
+            encoder
+                .write_image::<colortype::RGB8>(2, 1, &[255, 0, 0, 0, 255, 0])
+                .unwrap();
+            encoder.write_image::<colortype::Gray16>(1, 1, &[40000]).unwrap();
+            encoder
+                .write_image::<colortype::CMYK8>(1, 1, &[0, 255, 255, 51])
+                .unwrap();
+        }
+        let bytes = bytes.into_inner();
+        assert_eq!(
+            list_from(Cursor::new(&bytes), ImageFormat::Tiff),
+            ["Page 1", "Page 2", "Page 3"]
+        );
+
+        let first = decode_from(Cursor::new(&bytes), ImageFormat::Tiff, 0).unwrap();
+        assert_eq!(first.to_rgb8().get_pixel(1, 0).0, [0, 255, 0]);
+        let second = decode_from(Cursor::new(&bytes), ImageFormat::Tiff, 1).unwrap();
+        assert_eq!(second.as_luma16().unwrap().get_pixel(0, 0).0, [40000]);
+        let cmyk = decode_from(Cursor::new(&bytes), ImageFormat::Tiff, 2).unwrap();
+        assert_eq!(cmyk.to_rgb8().get_pixel(0, 0).0, [204, 0, 0]);
+        assert!(decode_from(Cursor::new(&bytes), ImageFormat::Tiff, 3).is_none());
+    }
+}
@@ -1,28 +1,53 @@
 // #![allow(unused_variables)]
 #![windows_subsystem = "windows"]
 
+mod animation;
+mod colorspace;
+mod display;
+mod export;
+mod histogram;
+mod inspect;
+mod layers;
+mod material;
+mod metadata;
+mod metrics;
 mod review;
+mod sequence;
+mod session;
 
+use std::collections::HashMap;
 use std::f32::consts::{PI, TAU};
 use std::fs::canonicalize;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
 use std::io::BufWriter;
+use std::io::Cursor;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use bevy::asset::RenderAssetUsages;
-use bevy::image::{ImageSampler, ImageSamplerDescriptor};
+use bevy::image::{ImageAddressMode, ImageSampler, ImageSamplerDescriptor};
 use bevy::input::mouse::MouseWheel;
 use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
 use bevy::render::view::Hdr;
+use bevy::sprite::Anchor;
+use bevy::tasks::futures::check_ready;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
 use bevy::window::{PresentMode, WindowResized, WindowResolution};
 use bevy_egui::egui::CollapsingHeader;
+use bevy_egui::input::EguiWantsInput;
 use bevy_egui::{egui, EguiContexts, EguiPlugin, EguiPrimaryContextPass};
 use clap::Parser;
-use image::{ColorType, DynamicImage, ImageFormat, SubImage};
+use colorspace::{ColorSpace, ImageSpace};
+use display::{ChannelView, Colormap, DisplaySettings, ToneMapping, ToneSettings};
+use export::{CropFormat, OverwritePolicy};
+use half::f16;
+use image::metadata::Orientation;
+use image::{ColorType, DynamicImage, ImageDecoder, ImageFormat};
+use material::{DisplayMaterial, DisplayMaterialPlugin, DisplayParams};
 use serde::{Deserialize, Serialize};
 
 #[doc(hidden)]
@@ -33,10 +58,13 @@ type Result<T> = ::std::result::Result<T, Box<dyn ::std::error::Error>>;
 struct Args {
     // Images to show
     images: Vec<String>,
+    // Session file (.ivs) to open instead of images
+    #[arg(long, conflicts_with = "images")]
+    session: Option<String>,
 }
 
 const HELP_STRING: &str = "Keyboard Shortcut:
-    L: Change Layout (Grid, Stack, Horizontal, Vertical)
+    L: Change Layout (Grid, Stack, Vertical, Horizontal, Wipe, Blend)
     Double-click: Switch between layout Grid-Stack or Horizontal-Vertical
     Shift + 1, 2, 3, ...: Move Image on top
     Ctrl/Cmd + 1, 2, 3, 4, 5: Zoom by 1, 2, 4, 8, 16
@@ -47,9 +75,13 @@ const HELP_STRING: &str = "Keyboard Shortcut:
     Q: Toggle 'Add Mode' (dropped images are added instead of replacing)
     C: Toggle multi cursor
     P: Save image to disk with the displayed crop (suffixed by _crop)
+    [ / ]: Move the divider of the Wipe layout (Shift for bigger steps)
+    - / =: Change the opacity of the Blend layout (Shift for bigger steps)
+    X: Show next channel (RGB, R, G, B, A, Luma), Shift for previous one
+    Left / Right: Previous / next frame of animations and sequences (Shift for 10 frames)
     H: Toggle Interface
 
-    Drag and Drop image from files explorer.
+    Drag and Drop image from files explorer (a folder is opened as an image sequence, a .ivs file as a session).
 ";
 
 #[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
@@ -81,6 +113,13 @@ struct ConfigShortcut {
     switch_layout: KeyCode,
     rotate_images: KeyCode,
     add_images: KeyCode,
+    #[serde(default = "default_switch_channel")]
+    switch_channel: KeyCode,
+}
+
+// Keep the config files saved before this shortcut existed valid
+fn default_switch_channel() -> KeyCode {
+    KeyCode::KeyX
 }
 
 // Used to store temporary edition during manual edit
@@ -93,6 +132,7 @@ struct ConfigShortcutAsBool {
     switch_layout: bool,
     rotate_images: bool,
     add_images: bool,
+    switch_channel: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -104,6 +144,117 @@ struct ConfigText {
 #[derive(Serialize, Deserialize, Debug)]
 struct ConfigHDR {
     enabled: bool,
+    #[serde(default)]
+    tone: ToneSettings,
+}
+
+// What is drawn behind the transparent pixels of an image
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+enum AlphaBackground {
+    None,
+    Checkerboard,
+    Solid,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+struct ConfigAlpha {
+    background: AlphaBackground,
+    color: Color, // color of the Solid background
+    ignore_alpha: bool,
+}
+
+impl Default for ConfigAlpha {
+    fn default() -> Self {
+        ConfigAlpha {
+            background: AlphaBackground::None,
+            color: Color::srgb(0.5, 0.5, 0.5),
+            ignore_alpha: false,
+        }
+    }
+}
+
+// Output of the saved crops
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+struct ConfigCrop {
+    format: CropFormat,
+    jpeg_quality: u8,
+    directory: String, // empty: next to the source, relative paths start from the folder of the source
+    template: String,
+    overwrite: OverwritePolicy,
+}
+
+impl Default for ConfigCrop {
+    fn default() -> Self {
+        ConfigCrop {
+            format: CropFormat::Source,
+            jpeg_quality: 90,
+            directory: String::new(),
+            template: "{stem}_crop.{ext}".to_string(),
+            overwrite: OverwritePolicy::Increment,
+        }
+    }
+}
+
+// Composite image of the whole layout
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+struct ConfigComposite {
+    scale: f32, // 1 keeps the image pixels of the visible crops
+    gap: u32,   // pixels around and between the cells
+    background: Color,
+    label_size: f32, // 0 hides the file names
+    label_color: Color,
+    font: String, // font file of the labels, empty for the font of the cell titles
+}
+
+impl Default for ConfigComposite {
+    fn default() -> Self {
+        ConfigComposite {
+            scale: 1.,
+            gap: 8,
+            background: Color::srgb(0.1, 0.1, 0.1),
+            label_size: 16.,
+            label_color: Color::WHITE,
+            font: String::new(),
+        }
+    }
+}
+
+impl ConfigComposite {
+    fn style(&self, columns: usize) -> export::CompositeStyle {
+        export::CompositeStyle {
+            columns,
+            scale: self.scale,
+            gap: self.gap,
+            background: self.background.to_srgba().to_u8_array(),
+            label: export::LabelStyle {
+                font: export::label_font(&self.font),
+                size: self.label_size,
+                color: self.label_color.to_srgba().to_u8_array(),
+            },
+        }
+    }
+}
+
+// A/B flicker animation of the visible crops, drawn with the composite settings
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+struct ConfigFlicker {
+    format: export::AnimationFormat,
+    frame_duration_ms: u32,
+    captions: bool, // write the title of each cell above its crop
+}
+
+impl Default for ConfigFlicker {
+    fn default() -> Self {
+        ConfigFlicker {
+            format: export::AnimationFormat::Gif,
+            frame_duration_ms: 500,
+            captions: true,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -118,13 +269,34 @@ struct Config {
     text: ConfigText,
     shortcut: ConfigShortcut,
     hdr: ConfigHDR,
+    #[serde(default)]
+    alpha: ConfigAlpha,
+    #[serde(default)]
+    crop: ConfigCrop,
+    #[serde(default)]
+    composite: ConfigComposite,
+    #[serde(default)]
+    flicker: ConfigFlicker,
     misc: ConfigMisc,
 }
 
 // MARK: Main
 fn main() -> Result<()> {
     let args = Args::parse();
-    let images_filename = check_all_images_exist(&args.images)?;
+    // A session given among the images is opened as with --session, it replaces the images
+    let (sessions, images): (Vec<String>, Vec<String>) =
+        args.images.iter().cloned().partition(|path| session::is_session(path));
+    if sessions.len() > 1 || (!sessions.is_empty() && !images.is_empty()) {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "A session can't be opened with other images or sessions",
+        )));
+    }
+    let images_filename = check_all_images_exist(&images)?;
+    let session_filename = match args.session.as_ref().or(sessions.first()) {
+        Some(session) => Some(check_all_images_exist(std::slice::from_ref(session))?.remove(0)),
+        None => None,
+    };
 
     let user_config_data = 'block: {
         let Some(home_directory) = home::home_dir() else {
@@ -181,6 +353,7 @@ fn main() -> Result<()> {
             })
             .set(ImagePlugin::default()),
         EguiPlugin::default(),
+        DisplayMaterialPlugin,
     ));
 
     // Inject the macOS dock drop handler before the event loop starts.
@@ -192,10 +365,16 @@ fn main() -> Result<()> {
 
     app.insert_state(MyAppState::Working)
         .insert_resource(InitialImagesFilename(images_filename))
+        .insert_resource(InitialSession(session_filename))
+        .insert_resource(PendingSession(None))
+        .insert_resource(SessionDialog(None))
         .insert_resource(UiState {
             visible: true,
             settings_panel_visible: false,
             image_list_visible: false,
+            display_bar_visible: false,
+            metrics_visible: false,
+            histogram_visible: false,
         })
         .insert_resource(config_data)
         .insert_resource(GlobalScale(1. / 8.))
@@ -208,6 +387,13 @@ fn main() -> Result<()> {
             layout: GridLayout::Grid,
             index: 0,
         })
+        .insert_resource(CompareState {
+            second: 1,
+            divider: 0.5,
+            vertical: true,
+            dragging: false,
+            opacity: 0.5,
+        })
         .insert_resource(MouseState {
             origin: Vec2::ZERO,
             delta: Vec2::ZERO,
@@ -215,6 +401,42 @@ fn main() -> Result<()> {
         })
         .insert_resource(ImageOrder(Vec::new()))
         .insert_resource(ReviewState::default())
+        .insert_resource(MetricsState::default())
+        .insert_resource(ChannelState::default())
+        .insert_resource(HighlightState::default())
+        .insert_resource(ExportNotice::default())
+        .insert_resource(ExportTasks::default())
+        .insert_resource(PlaybackState {
+            playback: animation::Playback::default(),
+            frame_count: 0,
+            sequence_fps: 24.,
+        })
+        .insert_resource(LevelsState {
+            mode: LevelsMode::Off,
+            percentile: 0.,
+        })
+        .insert_resource(ColormapState {
+            colormap: None,
+            auto_range: true,
+            min: 0.,
+            max: 255.,
+            legend: true,
+        })
+        .insert_resource(HistogramState {
+            images: vec![0],
+            channels: [true; 5],
+            crop_only: false,
+            log_scale: false,
+            dirty: true,
+            histograms: Vec::new(),
+            task: None,
+        })
+        .insert_resource(DifferenceState {
+            enabled: false,
+            reference: 0,
+            amplification: 1.,
+            heatmap: false,
+        })
         .add_systems(Startup, setup)
         .add_message::<LoadNewImageEvent>()
         .add_message::<NewImageLoadedEvent>()
@@ -227,11 +449,20 @@ fn main() -> Result<()> {
         .add_message::<ChangeTitleStyleEvent>()
         .add_message::<SaveSettingsEvent>()
         .add_message::<ChangeSamplerEvent>()
+        .add_message::<RefreshDisplayEvent>()
         .add_message::<RemoveImageEvent>()
+        .add_message::<DecodeFailedEvent>()
         .add_message::<ReorderImagesEvent>()
         .add_message::<NavigateReviewEvent>()
         .add_message::<RefreshReviewEvent>()
         .add_message::<ActivateReviewEvent>()
+        .add_message::<ComputeMetricsEvent>()
+        .add_message::<ExportCompositeEvent>()
+        .add_message::<ExportFlickerEvent>()
+        .add_message::<SaveSessionEvent>()
+        .add_message::<OpenSessionEvent>()
+        .add_message::<ChangeLayerEvent>()
+        .add_message::<ChangeColorSpaceEvent>()
         // Egui systems must run in EguiPrimaryContextPass (not Update)
         .add_systems(EguiPrimaryContextPass, configure_visuals.run_if(run_once))
         .add_systems(
@@ -241,6 +472,13 @@ fn main() -> Result<()> {
                 ui_image_list_panel.after(ui_bottom_menu),
                 ui_settings_menu.after(ui_bottom_menu),
                 ui_review_panel.after(ui_bottom_menu),
+                ui_display_bar.after(ui_bottom_menu),
+                ui_metrics_window.after(ui_bottom_menu),
+                ui_histogram_window.after(ui_bottom_menu),
+                ui_colormap_legend.after(ui_bottom_menu),
+                ui_cell_readout.after(ui_bottom_menu),
+                ui_export_notice,
+                ui_session_dialog,
             )
                 .run_if(in_state(MyAppState::Working)),
         )
@@ -252,11 +490,11 @@ fn main() -> Result<()> {
         .add_systems(
             Update,
             (
-                key_change_layout,
+                key_change_layout.run_if(keyboard_available),
                 change_layout_on_click,
-                change_global_zoom,
-                change_zoom_individually,
-                change_rotation_individually,
+                change_global_zoom.run_if(keyboard_available),
+                change_zoom_individually.run_if(keyboard_available),
+                change_rotation_individually.run_if(keyboard_available),
                 scroll_events,
                 mouse_button_input,
                 cursor_move,
@@ -264,12 +502,14 @@ fn main() -> Result<()> {
                 on_reset_visibility,
                 on_resize_system,
                 on_image_loaded,
-                on_move_cursor,
+                on_change_layer,
+                on_change_color_space,
+                on_move_cursor.after(on_move_image),
                 on_move_image,
                 on_move_image_title,
                 on_load_image,
                 on_image_spawned,
-                toggle_help,
+                toggle_help.run_if(keyboard_available),
             )
                 .run_if(in_state(MyAppState::Working)),
         )
@@ -281,20 +521,25 @@ fn main() -> Result<()> {
         .add_systems(
             Update,
             (
-                change_top_image,
-                change_global_rotation,
-                key_toggle_cursor,
+                change_top_image.run_if(keyboard_available),
+                change_global_rotation.run_if(keyboard_available),
+                key_toggle_cursor.run_if(keyboard_available),
                 toggle_cursor,
                 reset_scales,
                 fit_to_screen,
-                key_save_cropped,
-                key_toggle_add_mode,
+                key_save_cropped.run_if(keyboard_available),
+                key_toggle_add_mode.run_if(keyboard_available),
                 save_cropped,
                 save_settings,
                 change_image_title_style,
                 change_sampler,
                 on_remove_image,
                 on_reorder_images,
+                key_move_divider.run_if(keyboard_available),
+                key_change_opacity.run_if(keyboard_available),
+                key_switch_channel.run_if(keyboard_available),
+                key_step_frame.run_if(keyboard_available),
+                on_update_pixel_values.after(on_move_cursor),
             )
                 .run_if(in_state(MyAppState::Working)),
         )
@@ -304,6 +549,32 @@ fn main() -> Result<()> {
                 on_navigate_review,
                 on_activate_review,
                 on_refresh_review,
+                compute_metrics,
+                poll_metrics,
+                compute_histograms,
+                poll_histograms,
+                on_update_alpha_background.after(on_move_image),
+                on_update_image_mesh.after(on_move_image),
+                on_request_problem_counts,
+                on_problem_counts_computed,
+                export_composite,
+                export_flicker,
+                poll_exports,
+                save_session,
+                on_open_session,
+                apply_session.after(fit_to_screen),
+                (
+                    on_image_decoded,
+                    on_advance_playback,
+                    on_preload_sequence,
+                    on_request_levels,
+                    on_levels_computed,
+                    on_refresh_display,
+                    on_upload_source,
+                    on_texture_built,
+                    on_update_display,
+                )
+                    .chain(),
             )
                 .run_if(in_state(MyAppState::Working)),
         )
@@ -323,14 +594,19 @@ struct UiState {
     visible: bool,
     settings_panel_visible: bool,
     image_list_visible: bool,
+    display_bar_visible: bool,
+    metrics_visible: bool,
+    histogram_visible: bool,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
 enum GridLayout {
     Stack,
     Horizontal,
     Vertical,
     Grid,
+    Wipe,
+    Blend,
 }
 
 #[derive(Resource)]
@@ -339,6 +615,16 @@ struct GridLayoutState {
     index: usize,
 }
 
+// Second image shown with the top image (layout_state.index) in the Wipe layout
+#[derive(Resource)]
+struct CompareState {
+    second: usize,  // Id of the second image
+    divider: f32,   // position of the wipe divider as a fraction of the window
+    vertical: bool, // vertical divider (left/right halves) or horizontal (top/bottom halves)
+    dragging: bool, // the divider is being moved with the mouse
+    opacity: f32,   // opacity of the second image drawn over the first one in the Blend layout
+}
+
 #[derive(Resource)]
 struct GlobalScale(f32);
 
@@ -361,9 +647,24 @@ struct MouseState {
 #[derive(Component)]
 struct FontHandle(Handle<Font>);
 
+// Repeating texture drawn behind images with the Checkerboard alpha background
+#[derive(Component)]
+struct CheckerHandle(Handle<Image>);
+
 #[derive(Resource)]
 struct InitialImagesFilename(Vec<String>);
 
+#[derive(Resource)]
+struct InitialSession(Option<String>);
+
+// Session being opened, its zoom and pan are applied once its images are loaded and fitted
+#[derive(Resource)]
+struct PendingSession(Option<session::Session>);
+
+// Path edited in the window saving the session, the window is open when set
+#[derive(Resource)]
+struct SessionDialog(Option<String>);
+
 #[derive(Resource, Debug)]
 struct RecordedPressedKey(Option<KeyCode>);
 
@@ -371,6 +672,134 @@ struct RecordedPressedKey(Option<KeyCode>);
 #[derive(Resource)]
 struct ImageOrder(Vec<usize>);
 
+// Difference view: every cell shows |image - reference| instead of the image
+#[derive(Resource)]
+struct DifferenceState {
+    enabled: bool,
+    reference: usize, // display slot of the reference image, see ImageOrder
+    amplification: f32,
+    heatmap: bool,
+}
+
+// Quality metrics of every image against a reference image, computed in the background
+#[derive(Resource, Default)]
+struct MetricsState {
+    reference: usize, // Id of the reference image
+    crop_only: bool,  // restrict to the visible part of each image
+    rows: Vec<metrics::MetricsRow>,
+    task: Option<Task<Vec<metrics::MetricsRow>>>,
+}
+
+impl MetricsState {
+    // Rows are named after display slots, they are stale once an image is removed, replaced or moved
+    fn invalidate(&mut self) {
+        self.rows.clear();
+        self.task = None;
+    }
+}
+
+// Histograms of the selected images, computed in the background
+#[derive(Resource)]
+struct HistogramState {
+    images: Vec<usize>,  // Ids (display slots) of the overlaid images
+    channels: [bool; 5], // shown channels, in the order of histogram::Channel::ALL
+    crop_only: bool,     // restrict to the visible part of each image
+    log_scale: bool,
+    dirty: bool, // signal to recompute the histograms
+    histograms: Vec<(usize, histogram::Histogram)>,
+    task: Option<Task<Vec<(usize, histogram::Histogram)>>>,
+}
+
+impl HistogramState {
+    // Follow the images to their new slot after a remove or a reorder, `new_slot` is None for removed images
+    fn remap(&mut self, new_slot: impl Fn(usize) -> Option<usize>) {
+        self.images = self.images.iter().filter_map(|&slot| new_slot(slot)).collect();
+        self.histograms.clear();
+        self.task = None;
+        self.dirty = true;
+    }
+}
+
+// Channels shown in every cell
+#[derive(Resource, Default)]
+struct ChannelState(ChannelView);
+
+// Shared play/pause and frame of the animated images and image sequences
+#[derive(Resource)]
+struct PlaybackState {
+    playback: animation::Playback,
+    frame_count: usize, // frames of the longest animation or sequence, 0 when there is none
+    sequence_fps: f32,
+}
+
+// Highlight of NaN, Inf and clipped pixels in every cell
+#[derive(Resource, Default)]
+struct HighlightState(bool);
+
+// Export rendered, encoded and written in the background, its task returns the written files
+struct ExportTask {
+    title: &'static str,
+    task: Task<Option<Vec<String>>>,
+}
+
+#[derive(Resource, Default)]
+struct ExportTasks(Vec<ExportTask>);
+
+// Files written by the last export, listed on screen for a few seconds
+#[derive(Resource, Default)]
+struct ExportNotice {
+    title: String,
+    files: Vec<String>,
+    skipped: Vec<String>, // existing files left as they were
+    shown_at: Option<Instant>,
+}
+
+impl ExportNotice {
+    const DURATION: Duration = Duration::from_secs(6);
+
+    fn show(&mut self, title: &str, files: Vec<String>) {
+        self.show_with_skipped(title, files, Vec::new());
+    }
+
+    // Nothing is shown when no file was written or skipped
+    fn show_with_skipped(&mut self, title: &str, files: Vec<String>, skipped: Vec<String>) {
+        let plural = |count: usize| if count == 1 { "" } else { "s" };
+        self.title = match (files.len(), skipped.len()) {
+            (0, 0) => return,
+            (0, s) => format!("{title}: {s} existing file{} skipped", plural(s)),
+            (n, 0) => format!("{title}: {n} file{}", plural(n)),
+            (n, s) => format!("{title}: {n} file{}, {s} skipped", plural(n)),
+        };
+        self.files = files;
+        self.skipped = skipped;
+        self.shown_at = Some(Instant::now());
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum LevelsMode {
+    Off,
+    PerImage, // each image is stretched from its own range
+    Joint,    // every image is stretched from the range covering all of them
+}
+
+// Stretch of low dynamic range data (e.g. 16-bit images using a small part of their range)
+#[derive(Resource)]
+struct LevelsState {
+    mode: LevelsMode,
+    percentile: f32, // percentage of values ignored at each end, 0 uses the min and max
+}
+
+// Colormap applied to single-channel images
+#[derive(Resource)]
+struct ColormapState {
+    colormap: Option<Colormap>,
+    auto_range: bool, // normalize each image with its own min and max
+    min: f32,         // manual range, in stored units
+    max: f32,
+    legend: bool,
+}
+
 #[derive(Resource, Default)]
 struct ReviewState {
     enabled: bool,
@@ -386,6 +815,10 @@ struct ReviewState {
 #[derive(Component)]
 struct Id(usize);
 
+// Index of the image in the request that loaded it, unlike Id it isn't renumbered on removal
+#[derive(Component)]
+struct LoadSlot(usize);
+
 #[derive(Component)]
 struct Scale(f32);
 
@@ -405,12 +838,164 @@ struct MyCursor;
 #[derive(Component)]
 struct MyImage;
 
+// Raw pixel values shown next to a cursor
+#[derive(Component)]
+struct MyPixelValue;
+
 #[derive(Component)]
 struct MyText;
 
 #[derive(Component)]
 struct MyHelp;
 
+#[derive(Component)]
+struct MyDivider;
+
+// Background drawn behind an image (child of the MyImage entity)
+#[derive(Component)]
+struct MyBackground;
+
+// Background decoding of an image (and its frames when animated), removed once decoded
+#[derive(Component)]
+struct DecodeTask(Task<Option<DecodedImage>>);
+
+struct DecodedImage {
+    image: Arc<DynamicImage>,
+    animation: Option<animation::Animation>,
+    layers: Vec<String>, // layers of the file when it holds several images
+    layer: usize,        // the decoded one
+    metadata: Option<metadata::Metadata>,
+    color_space: Option<ImageSpace>, // embedded ICC profile
+    icc_lut: Option<Image>,          // and its conversion for the shader
+    range: Option<(f32, f32)>,       // ValueRange of the image
+}
+
+// Layers (EXR parts and channel groups, TIFF pages) of a file holding several images
+#[derive(Component)]
+struct Layers {
+    names: Vec<String>,
+    selected: usize,
+}
+
+// EXIF fields of the file loaded in the cell
+#[derive(Component)]
+struct ExifMetadata(metadata::Metadata);
+
+// Ordered files shown in a cell (a directory or a `frame_####.png` pattern).
+// The ImagePath is the file currently shown, the frames around it are decoded ahead of time.
+#[derive(Component)]
+struct Sequence {
+    source: String, // folder or frame pattern the sequence was opened from
+    paths: Vec<String>,
+    frames: HashMap<usize, DecodedFrame>,
+    pending: HashMap<usize, Task<Option<DecodedFrame>>>,
+    shown: usize,
+}
+
+// Frame of a sequence, with its ValueRange computed by the decoding task
+struct DecodedFrame {
+    image: Arc<DynamicImage>,
+    range: Option<(f32, f32)>,
+}
+
+impl DecodedFrame {
+    fn new(image: Arc<DynamicImage>) -> DecodedFrame {
+        let range = display::single_channel_range(&image);
+        DecodedFrame { image, range }
+    }
+}
+
+// Frames of a sequence decoded after and before the one shown
+const SEQUENCE_PRELOAD_AHEAD: usize = 8;
+const SEQUENCE_PRELOAD_BEHIND: usize = 2;
+
+// Every frame of an animated image, the SourceImage is the one currently shown
+#[derive(Component)]
+struct AnimationFrames {
+    animation: animation::Animation,
+    shown: usize,
+}
+
+// Texture and visible part of an image cell, drawn by the mesh of the cell with its DisplayMaterial
+#[derive(Component, Default)]
+struct ImageView {
+    image: Handle<Image>, // stored values of the SourceImage, only kept on the GPU
+    rect: Option<Rect>,   // visible part, in texels
+    anchor: Vec2,         // texel shown at the center of the cell
+    color: Color,         // the alpha is the opacity of the Blend layout
+}
+
+// Background conversion of the SourceImage to a texture, removed once uploaded
+#[derive(Component)]
+struct TextureTask(Task<Image>);
+
+// The SourceImage changed while its TextureTask was running
+#[derive(Component)]
+struct TextureOutdated;
+
+// Decoded pixels, the only copy on the CPU. Uploaded as is, the display transform is done by the shader.
+#[derive(Component)]
+struct SourceImage(Arc<DynamicImage>);
+
+// Marks an image whose display material must be rebuilt
+#[derive(Component)]
+struct DisplayDirty;
+
+// Display settings the material was last built with, to render the exports the same way on the CPU
+#[derive(Component)]
+struct AppliedDisplay(DisplaySettings);
+
+// Smallest and largest stored value of single-channel images, used to normalize colormaps
+#[derive(Component)]
+struct ValueRange(Option<(f32, f32)>);
+
+// Range of the stored values stretched to the display range by the levels normalization
+#[derive(Component)]
+struct LevelRange {
+    percentile: f32,
+    range: Option<(f32, f32)>, // integers normalized to [0, 1], floats as is
+    unit: f32,                 // largest stored value, to show the range in stored units
+}
+
+// Background computation of the LevelRange for the given percentile
+#[derive(Component)]
+struct LevelsTask(Task<Option<(f32, f32)>>, f32);
+
+// Number of pixels with each numeric problem, in the order of display::Problem::ALL
+#[derive(Component)]
+struct ProblemCounts {
+    counts: [u64; 5],
+    linear: bool, // float image, the clipping problems are values outside [0, 1]
+}
+
+// Background count of the pixels with a numeric problem
+#[derive(Component)]
+struct ProblemsTask(Task<[u64; 5]>);
+
+// Per image display transform, None follows the global one
+#[derive(Component, Default)]
+struct ToneOverride(Option<ToneSettings>);
+
+// Color space of the stored values: the one assigned in the image list, else the embedded ICC profile,
+// else sRGB for integer images and linear sRGB for float ones
+#[derive(Component, Default)]
+struct ImageColorSpace {
+    assigned: Option<ColorSpace>,
+    embedded: Option<ImageSpace>,
+    icc_lut: Option<Handle<Image>>, // conversion of the embedded profile for the shader
+    linear: bool,                   // float image
+}
+
+impl ImageColorSpace {
+    fn active(&self) -> ImageSpace {
+        match (self.assigned, &self.embedded) {
+            (Some(space), _) => ImageSpace::Known(space),
+            (None, Some(embedded)) => embedded.clone(),
+            (None, None) => ImageSpace::Known(ColorSpace::default_for(self.linear)),
+        }
+    }
+}
+
 // MARK: Messages
 #[derive(Message)]
 struct MoveImageEvent;
@@ -439,9 +1024,11 @@ struct ResetVisibilityEvent;
 #[derive(Message)]
 struct ChangeSamplerEvent;
 
+#[derive(Message)]
+struct RefreshDisplayEvent; // signal to rebuild displayed textures after a display setting changed
+
 #[derive(Message)]
 struct NewImageLoadedEvent {
-    handle: Handle<Image>,
     path: String,
     index: usize,
 }
@@ -450,11 +1037,27 @@ struct NewImageLoadedEvent {
 struct LoadNewImageEvent {
     path: String,
     index: usize,
+    layer: usize, // layer (or page) of files holding several images
+}
+
+#[derive(Message)]
+struct ChangeLayerEvent {
+    id: usize,
+    layer: usize,
+}
+
+#[derive(Message)]
+struct ChangeColorSpaceEvent {
+    id: usize,
+    assigned: Option<ColorSpace>, // None goes back to the embedded profile (or the default)
 }
 
 #[derive(Message)]
 struct RemoveImageEvent(usize); // the Id of the image to remove
 
+#[derive(Message)]
+struct DecodeFailedEvent(Entity); // the image entity whose file couldn't be decoded
+
 #[derive(Message)]
 struct ReorderImagesEvent; // signal to recompute layout after reorder
 
@@ -467,24 +1070,81 @@ struct RefreshReviewEvent;
 #[derive(Message)]
 struct ActivateReviewEvent;
 
+#[derive(Message)]
+struct ComputeMetricsEvent;
+
+#[derive(Message)]
+struct ExportCompositeEvent;
+
+#[derive(Message)]
+struct ExportFlickerEvent;
+
+#[derive(Message)]
+struct SaveSessionEvent(String); // path of the session file
+
+#[derive(Message)]
+struct OpenSessionEvent(String); // path of the session file
+
 // MARK: Setup
+#[allow(clippy::redundant_field_names, clippy::too_many_arguments)]
 fn setup(
     mut commands: Commands,
     images_filename: ResMut<InitialImagesFilename>,
+    session_filename: Res<InitialSession>,
     config: Res<Config>,
     mut load_image_evw: MessageWriter<LoadNewImageEvent>,
+    mut open_session_evw: MessageWriter<OpenSessionEvent>,
     mut fonts: ResMut<Assets<Font>>,
+    mut images: ResMut<Assets<Image>>,
 ) {
     let mut camera = commands.spawn(Camera2d);
     if config.hdr.enabled {
         camera.insert(Hdr);
     }
 
+    // Line shown between the two images of the Wipe layout
+    commands.spawn((
+        Sprite {
+            color: Color::WHITE,
+            custom_size: Some(Vec2::ONE),
+            ..default()
+        },
+        Transform::from_translation(Vec3::new(0., 0., 0.5)),
+        Visibility::Hidden,
+        MyDivider,
+    ));
+
     let bytes = include_bytes!("../assets/fonts/IBMPlexMono-Regular.otf");
     let font = Font::try_from_bytes(bytes.to_vec()).unwrap();
     let font_handle = fonts.add(font);
     commands.spawn(FontHandle(font_handle.clone()));
 
+    // 2x2 checks repeated over the image area by the sampler
+    let (light, dark) = (204, 153);
+    let mut checker = Image::new(
+        Extent3d {
+            width: 2,
+            height: 2,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        [
+            [light, light, light, 255],
+            [dark, dark, dark, 255],
+            [dark, dark, dark, 255],
+            [light, light, light, 255],
+        ]
+        .concat(),
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    checker.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+        address_mode_u: ImageAddressMode::Repeat,
+        address_mode_v: ImageAddressMode::Repeat,
+        ..ImageSamplerDescriptor::nearest()
+    });
+    commands.spawn(CheckerHandle(images.add(checker)));
+
     commands.spawn((
         Text::new(HELP_STRING),
         TextFont {
@@ -510,8 +1170,12 @@ fn setup(
         load_image_evw.write(LoadNewImageEvent {
             path: image.clone(),
             index: index,
+            layer: 0,
         });
     }
+    if let Some(session) = &session_filename.0 {
+        open_session_evw.write(OpenSessionEvent(session.clone()));
+    }
 }
 
 fn configure_visuals(mut egui_ctx: EguiContexts) {
@@ -519,6 +1183,7 @@ fn configure_visuals(mut egui_ctx: EguiContexts) {
     ctx.set_visuals(egui::Visuals { ..Default::default() });
 }
 
+#[allow(clippy::collapsible_if)]
 fn keycode_dropdown(
     ui: &mut egui::Ui,
     next_state: &mut ResMut<NextState<MyAppState>>,
@@ -545,61 +1210,712 @@ fn keycode_dropdown(
     });
 }
 
-fn ui_bottom_menu(
+// Edit a display transform, return true when a value changed
+fn tone_settings_ui(ui: &mut egui::Ui, id_salt: impl std::hash::Hash, tone: &mut ToneSettings) -> bool {
+    let mut changed = false;
+    changed |= ui
+        .add(
+            egui::DragValue::new(&mut tone.exposure)
+                .prefix("Exposure ")
+                .suffix(" EV")
+                .speed(0.05)
+                .range(-20.0..=20.),
+        )
+        .on_hover_text("Exposure in stops")
+        .changed();
+    changed |= ui
+        .add(
+            egui::DragValue::new(&mut tone.offset)
+                .prefix("Offset ")
+                .speed(0.01)
+                .range(-1.0..=1.),
+        )
+        .on_hover_text("Offset added after exposure")
+        .changed();
+    changed |= ui
+        .add(
+            egui::DragValue::new(&mut tone.gamma)
+                .prefix("Gamma ")
+                .speed(0.01)
+                .range(0.1..=5.),
+        )
+        .changed();
+    egui::ComboBox::from_id_salt(id_salt)
+        .selected_text(format!("{:?}", tone.tonemapping))
+        .show_ui(ui, |ui| {
+            for tonemapping in [
+                ToneMapping::Clamp,
+                ToneMapping::Reinhard,
+                ToneMapping::Aces,
+                ToneMapping::Filmic,
+            ] {
+                changed |= ui
+                    .selectable_value(&mut tone.tonemapping, tonemapping, format!("{tonemapping:?}"))
+                    .changed();
+            }
+        });
+    if ui.button("\u{27F2}").on_hover_text("Reset display transform").clicked() {
+        *tone = ToneSettings::default();
+        changed = true;
+    }
+    changed
+}
+
+#[allow(clippy::too_many_arguments)]
+fn ui_display_bar(
     mut contexts: EguiContexts,
-    mut layout_state: ResMut<GridLayoutState>,
-    mut reset_vix_evw: MessageWriter<ResetVisibilityEvent>,
-    mut ui_state: ResMut<UiState>,
-    mut global_scale: ResMut<GlobalScale>,
-    mut move_image_evw: MessageWriter<MoveImageEvent>,
-    mut save_cropped_evw: MessageWriter<SaveCropped>,
-    mut reset_scales_evw: MessageWriter<ResetScales>,
-    mut fit_to_screen_evw: MessageWriter<FitToScreen>,
-    mut add_mode: ResMut<AddMode>,
-    mut review_state: ResMut<ReviewState>,
-    mut activate_evw: MessageWriter<ActivateReviewEvent>,
+    ui_state: Res<UiState>,
+    mut config: ResMut<Config>,
+    mut difference_state: ResMut<DifferenceState>,
+    mut channel_state: ResMut<ChannelState>,
+    mut colormap_state: ResMut<ColormapState>,
+    mut levels_state: ResMut<LevelsState>,
+    mut highlight_state: ResMut<HighlightState>,
+    image_order: Res<ImageOrder>,
+    mut refresh_display_evw: MessageWriter<RefreshDisplayEvent>,
+    mut swizzle_text: Local<String>,
 ) {
-    if ui_state.visible {
-        let Ok(ctx) = contexts.ctx_mut() else { return };
-        egui::TopBottomPanel::bottom("wrap_app_top_bar").show(ctx, |ui| {
-            // equivalent to horizontal_wrapped but with a small factor on y to avoid the clip of button
-            let initial_size = egui::vec2(ui.available_size_before_wrap().x, ui.spacing().interact_size.y * 1.2);
-            ui.allocate_ui_with_layout(
-                initial_size,
-                egui::Layout::left_to_right(egui::Align::Center).with_main_wrap(true),
-                |ui| {
-                    egui::widgets::global_theme_preference_switch(ui);
-                    ui.toggle_value(&mut ui_state.settings_panel_visible, "\u{2699}");
-                    ui.toggle_value(&mut ui_state.image_list_visible, "\u{2630}");
-                    ui.separator();
-                    let mut scale = global_scale.0.log2();
+    if !ui_state.visible || !ui_state.display_bar_visible {
+        return;
+    }
+    let Ok(ctx) = contexts.ctx_mut() else { return };
+    egui::TopBottomPanel::bottom("display_bar").show(ctx, |ui| {
+        ui.horizontal_wrapped(|ui| {
+            let mut changed = tone_settings_ui(ui, "global_tone", &mut config.hdr.tone);
 
-                    if ui
-                        .add(
-                            egui::DragValue::new(&mut scale)
-                                .prefix("\u{1F50E} ")
-                                .speed(0.1)
-                                .range(-10.0..=10.),
-                        )
-                        .on_hover_text("Zoom")
-                        .changed()
-                    {
-                        global_scale.0 = 2f32.powf(scale);
+            ui.separator();
+            egui::ComboBox::from_id_salt("channel_view")
+                .selected_text(channel_state.0.name())
+                .show_ui(ui, |ui| {
+                    for view in ChannelView::CYCLE {
+                        changed |= ui.selectable_value(&mut channel_state.0, view, view.name()).changed();
                     }
+                });
+            let swizzle = ui
+                .add(
+                    egui::TextEdit::singleline(&mut *swizzle_text)
+                        .desired_width(40.)
+                        .hint_text("BGR"),
+                )
+                .on_hover_text("Custom mapping of the displayed R, G and B (letters among R, G, B, A)");
+            if swizzle.changed() {
+                if let Some(view) = ChannelView::parse_swizzle(&swizzle_text) {
+                    channel_state.0 = view;
+                    changed = true;
+                }
+            }
 
-                    if ui.button("1:1").on_hover_text("Reset All Zoom").clicked() {
-                        reset_scales_evw.write(ResetScales);
-                    }
+            ui.separator();
+            ui.label("Levels:");
+            changed |= ui
+                .selectable_value(&mut levels_state.mode, LevelsMode::Off, "Off")
+                .changed();
+            changed |= ui
+                .selectable_value(&mut levels_state.mode, LevelsMode::PerImage, "Each")
+                .on_hover_text("Stretch each image from its own range")
+                .changed();
+            changed |= ui
+                .selectable_value(&mut levels_state.mode, LevelsMode::Joint, "Joint")
+                .on_hover_text("Stretch every image from the range covering all of them")
+                .changed();
+            if levels_state.mode != LevelsMode::Off {
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut levels_state.percentile)
+                            .suffix("%")
+                            .speed(0.05)
+                            .range(0.0..=10.),
+                    )
+                    .on_hover_text("Percentage of values ignored at each end (0: min and max)")
+                    .changed();
+            }
 
-                    if ui.button("Fit").on_hover_text("Fit to Screen").clicked() {
-                        fit_to_screen_evw.write(FitToScreen);
+            ui.separator();
+            let colormap_name = colormap_state.colormap.map_or("Gray", |colormap| colormap.name());
+            egui::ComboBox::from_id_salt("colormap")
+                .selected_text(colormap_name)
+                .show_ui(ui, |ui| {
+                    changed |= ui
+                        .selectable_value(&mut colormap_state.colormap, None, "Gray")
+                        .changed();
+                    for colormap in Colormap::ALL {
+                        changed |= ui
+                            .selectable_value(&mut colormap_state.colormap, Some(colormap), colormap.name())
+                            .changed();
                     }
-
-                    for i in 0..10 {
+                })
+                .response
+                .on_hover_text("Colormap of single-channel images");
+            if colormap_state
+                .colormap
+                .is_some_and(|colormap| colormap != Colormap::Labels)
+            {
+                changed |= ui
+                    .checkbox(&mut colormap_state.auto_range, "Auto")
+                    .on_hover_text("Normalize each image with its own min and max")
+                    .changed();
+                if !colormap_state.auto_range {
+                    let max = colormap_state.max;
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut colormap_state.min)
+                                .prefix("min ")
+                                .range(f32::MIN..=max),
+                        )
+                        .changed();
+                    let min = colormap_state.min;
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut colormap_state.max)
+                                .prefix("max ")
+                                .range(min..=f32::MAX),
+                        )
+                        .changed();
+                }
+                ui.checkbox(&mut colormap_state.legend, "Legend");
+            }
+
+            ui.separator();
+            changed |= ui
+                .toggle_value(&mut highlight_state.0, "NaN/Clip")
+                .on_hover_text(
+                    "Highlight NaN (magenta), +Inf (yellow), -Inf (cyan), \
+                     values below 0 or at the minimum (blue) and above 1 or at the maximum (red)",
+                )
+                .changed();
+
+            ui.separator();
+            changed |= ui
+                .toggle_value(&mut difference_state.enabled, "Diff")
+                .on_hover_text("Show |image - reference| in every cell")
+                .changed();
+            if difference_state.enabled {
+                egui::ComboBox::from_id_salt("difference_reference")
+                    .selected_text(format!("Ref {}", difference_state.reference + 1))
+                    .show_ui(ui, |ui| {
+                        for slot in 0..image_order.0.len() {
+                            changed |= ui
+                                .selectable_value(&mut difference_state.reference, slot, format!("Ref {}", slot + 1))
+                                .changed();
+                        }
+                    });
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut difference_state.amplification)
+                            .prefix("\u{00D7}")
+                            .speed(0.1)
+                            .range(1.0..=1000.),
+                    )
+                    .on_hover_text("Amplification of the difference")
+                    .changed();
+                changed |= ui.checkbox(&mut difference_state.heatmap, "Heat map").changed();
+            }
+
+            if changed {
+                refresh_display_evw.write(RefreshDisplayEvent);
+            }
+        });
+    });
+}
+
+fn ui_metrics_window(
+    mut contexts: EguiContexts,
+    mut ui_state: ResMut<UiState>,
+    mut metrics_state: ResMut<MetricsState>,
+    image_order: Res<ImageOrder>,
+    mut compute_metrics_evw: MessageWriter<ComputeMetricsEvent>,
+) {
+    if !ui_state.visible || !ui_state.metrics_visible {
+        return;
+    }
+    let Ok(ctx) = contexts.ctx_mut() else { return };
+    egui::Window::new("Metrics")
+        .open(&mut ui_state.metrics_visible)
+        .resizable(true)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let mut changed = false;
+                egui::ComboBox::from_id_salt("metrics_reference")
+                    .selected_text(format!("Ref {}", metrics_state.reference + 1))
+                    .show_ui(ui, |ui| {
+                        for slot in 0..image_order.0.len() {
+                            changed |= ui
+                                .selectable_value(&mut metrics_state.reference, slot, format!("Ref {}", slot + 1))
+                                .changed();
+                        }
+                    });
+                changed |= ui
+                    .checkbox(&mut metrics_state.crop_only, "Visible crop")
+                    .on_hover_text("Only compare the part of each image visible in its cell")
+                    .changed();
+                if ui.button("Compute").clicked() || changed {
+                    compute_metrics_evw.write(ComputeMetricsEvent);
+                }
+                if metrics_state.task.is_some() {
+                    ui.spinner();
+                }
+                if ui
+                    .add_enabled(!metrics_state.rows.is_empty(), egui::Button::new("Copy CSV"))
+                    .clicked()
+                {
+                    ui.ctx().copy_text(metrics::to_csv(&metrics_state.rows));
+                }
+            });
+            ui.separator();
+
+            egui::Grid::new("metrics_table").striped(true).show(ui, |ui| {
+                ui.strong("Image");
+                ui.strong("MSE");
+                ui.strong("PSNR (dB)");
+                ui.strong("SSIM");
+                ui.end_row();
+                for row in &metrics_state.rows {
+                    let name = ui.label(&row.name);
+                    if row.size_mismatch {
+                        name.on_hover_text("Size differs from the reference, only the overlap is compared");
+                    }
+                    match &row.metrics {
+                        Some(m) => {
+                            ui.label(format!("{:.6}", m.mse));
+                            ui.label(format!("{:.2}", m.psnr));
+                            ui.label(format!("{:.4}", m.ssim));
+                        }
+                        None => {
+                            ui.label("-");
+                            ui.label("-");
+                            ui.label("-");
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+fn ui_histogram_window(
+    mut contexts: EguiContexts,
+    mut ui_state: ResMut<UiState>,
+    mut histogram_state: ResMut<HistogramState>,
+    image_order: Res<ImageOrder>,
+) {
+    if !ui_state.visible || !ui_state.histogram_visible {
+        return;
+    }
+    let Ok(ctx) = contexts.ctx_mut() else { return };
+    egui::Window::new("Histogram")
+        .open(&mut ui_state.histogram_visible)
+        .resizable(true)
+        .show(ctx, |ui| {
+            let mut changed = false;
+            ui.horizontal_wrapped(|ui| {
+                for slot in 0..image_order.0.len() {
+                    let mut selected = histogram_state.images.contains(&slot);
+                    if ui.toggle_value(&mut selected, format!("{}", slot + 1)).changed() {
+                        if selected {
+                            histogram_state.images.push(slot);
+                        } else {
+                            histogram_state.images.retain(|id| *id != slot);
+                        }
+                        changed = true;
+                    }
+                }
+                ui.separator();
+                for (channel, shown) in histogram::Channel::ALL.iter().zip(histogram_state.channels.iter_mut()) {
+                    ui.toggle_value(shown, channel.name());
+                }
+                ui.separator();
+                changed |= ui
+                    .checkbox(&mut histogram_state.crop_only, "Visible crop")
+                    .on_hover_text("Only count the part of each image visible in its cell")
+                    .changed();
+                ui.checkbox(&mut histogram_state.log_scale, "Log");
+                if histogram_state.task.is_some() {
+                    ui.spinner();
+                }
+            });
+            if changed {
+                histogram_state.dirty = true;
+            }
+
+            let width = ui.available_width().max(256.);
+            let (rect, _) = ui.allocate_exact_size(egui::vec2(width, 160.), egui::Sense::hover());
+            let painter = ui.painter_at(rect);
+            painter.rect_filled(rect, 0., ui.visuals().extreme_bg_color);
+
+            let scale = |count: u32| {
+                if histogram_state.log_scale {
+                    (count as f32).ln_1p()
+                } else {
+                    count as f32
+                }
+            };
+            let shown = |channel: &histogram::Channel| {
+                let index = histogram::Channel::ALL.iter().position(|c| c == channel).unwrap();
+                histogram_state.channels[index]
+            };
+            let highest = histogram_state
+                .histograms
+                .iter()
+                .flat_map(|(_, h)| h.channels.iter())
+                .filter(|(channel, _)| shown(channel))
+                .flat_map(|(_, counts)| counts.iter().map(|c| scale(*c)))
+                .fold(1f32, f32::max);
+
+            // One color per channel, or per image when several images are overlaid
+            let palette = [
+                egui::Color32::from_rgb(230, 159, 0),
+                egui::Color32::from_rgb(86, 180, 233),
+                egui::Color32::from_rgb(0, 158, 115),
+                egui::Color32::from_rgb(240, 228, 66),
+                egui::Color32::from_rgb(204, 121, 167),
+                egui::Color32::from_rgb(213, 94, 0),
+            ];
+            let overlay = histogram_state.histograms.len() > 1;
+            for (index, (_, histogram)) in histogram_state.histograms.iter().enumerate() {
+                for (channel, counts) in histogram.channels.iter().filter(|(channel, _)| shown(channel)) {
+                    let color = if overlay {
+                        palette[index % palette.len()]
+                    } else {
+                        match channel {
+                            histogram::Channel::Red => egui::Color32::from_rgb(230, 60, 60),
+                            histogram::Channel::Green => egui::Color32::from_rgb(60, 200, 60),
+                            histogram::Channel::Blue => egui::Color32::from_rgb(80, 120, 255),
+                            histogram::Channel::Alpha => egui::Color32::GRAY,
+                            histogram::Channel::Luma => ui.visuals().strong_text_color(),
+                        }
+                    };
+                    let step = rect.width() / counts.len() as f32;
+                    let points = counts
+                        .iter()
+                        .enumerate()
+                        .map(|(bin, count)| {
+                            egui::pos2(
+                                rect.left() + (bin as f32 + 0.5) * step,
+                                rect.bottom() - scale(*count) / highest * rect.height(),
+                            )
+                        })
+                        .collect();
+                    painter.add(egui::Shape::line(points, egui::Stroke::new(1., color)));
+                }
+            }
+
+            if let Some((_, first)) = histogram_state.histograms.first() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}", first.min));
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.label(format!("{}", first.max));
+                    });
+                });
+            }
+            if overlay {
+                ui.horizontal_wrapped(|ui| {
+                    for (index, (id, _)) in histogram_state.histograms.iter().enumerate() {
+                        ui.colored_label(palette[index % palette.len()], format!("\u{25A0} {}", id + 1));
+                    }
+                });
+            }
+        });
+}
+
+// Mapping applied by the levels normalization and count of highlighted pixels,
+// drawn under the title of each cell
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn ui_cell_readout(
+    mut contexts: EguiContexts,
+    windows: Query<&Window>,
+    image_query: Query<(&Id, Option<&LevelRange>, Option<&ProblemCounts>), With<MyImage>>,
+    level_query: Query<&LevelRange>,
+    levels_state: Res<LevelsState>,
+    highlight_state: Res<HighlightState>,
+    layout_state: Res<GridLayoutState>,
+    compare_state: Res<CompareState>,
+    config: Res<Config>,
+) {
+    if levels_state.mode == LevelsMode::Off && !highlight_state.0 {
+        return;
+    }
+    let Ok(ctx) = contexts.ctx_mut() else { return };
+    let window = windows.single().unwrap();
+    let num_images = image_query.iter().count();
+
+    for (id, level_range, problem_counts) in &image_query {
+        let levels = level_range.zip(applied_levels(&levels_state, level_range, &level_query));
+        let problem_counts = problem_counts.filter(|_| highlight_state.0);
+        if levels.is_none() && problem_counts.is_none() {
+            continue;
+        }
+        if !is_cell_visible(id.0, &layout_state, &compare_state) {
+            continue;
+        }
+        let (cell_offset, _) = get_cell_rect(id.0, num_images, &layout_state.layout, window, config.misc.grid_width);
+        let mut position = egui::pos2(cell_offset.x + 5., cell_offset.y + config.text.font_size * 1.5);
+        // Both images of Wipe and Blend share the window, the readout of the second one goes below
+        if matches!(layout_state.layout, GridLayout::Wipe | GridLayout::Blend) && id.0 != layout_state.index {
+            position.y += config.text.font_size * 2.;
+        }
+        egui::Area::new(egui::Id::new(("cell_readout", id.0)))
+            .fixed_pos(position)
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    if let Some((level_range, (low, high))) = levels {
+                        let format = |value: f32| {
+                            if level_range.unit > 1. {
+                                format!("{:.0}", value * level_range.unit)
+                            } else {
+                                format!("{value:.4}")
+                            }
+                        };
+                        let (low, high) = (format(low), format(high));
+                        ui.small(format!("{low} \u{2013} {high} \u{2192} 0 \u{2013} 1"));
+                    }
+                    if let Some(problem_counts) = problem_counts {
+                        ui.horizontal(|ui| {
+                            for (problem, count) in display::Problem::ALL.iter().zip(problem_counts.counts) {
+                                let [r, g, b] = problem.color().map(|v| (v * 255.) as u8);
+                                let text = format!("{} {count}", problem.name(problem_counts.linear));
+                                ui.label(
+                                    egui::RichText::new(text)
+                                        .small()
+                                        .color(egui::Color32::from_rgb(r, g, b)),
+                                );
+                            }
+                        });
+                    }
+                });
+            });
+    }
+}
+
+// List of the files written by the last export, in the bottom right corner above the bottom bar
+fn ui_export_notice(mut contexts: EguiContexts, mut notice: ResMut<ExportNotice>) {
+    let Some(shown_at) = notice.shown_at else {
+        return;
+    };
+    if shown_at.elapsed() > ExportNotice::DURATION {
+        notice.shown_at = None;
+        return;
+    }
+    let Ok(ctx) = contexts.ctx_mut() else { return };
+    let mut open = true;
+    egui::Window::new(notice.title.as_str())
+        .id(egui::Id::new("export_notice"))
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10., -40.))
+        .show(ctx, |ui| {
+            for file in &notice.files {
+                ui.small(file);
+            }
+            for file in &notice.skipped {
+                ui.small(format!("Skipped {file}"));
+            }
+        });
+    if !open {
+        notice.shown_at = None;
+    }
+    // Keep repainting so the notice disappears even when nothing else happens
+    ctx.request_repaint_after(Duration::from_millis(500));
+}
+
+// Path of the session file to save, next to the first image by default
+fn ui_session_dialog(
+    mut contexts: EguiContexts,
+    mut session_dialog: ResMut<SessionDialog>,
+    image_query: Query<(&Id, &ImagePath, Option<&Sequence>), With<MyImage>>,
+    mut save_session_evw: MessageWriter<SaveSessionEvent>,
+) {
+    let Some(path) = &mut session_dialog.0 else {
+        return;
+    };
+    if path.is_empty() {
+        let first_path = image_query
+            .iter()
+            .min_by_key(|(id, ..)| id.0)
+            .map(|(_, path, sequence)| sequence.map_or(&path.0, |sequence| &sequence.source));
+        let directory = first_path.and_then(|path| Path::new(path).parent());
+        let file_name = format!("session.{}", session::EXTENSION);
+        *path = directory
+            .unwrap_or(Path::new(""))
+            .join(file_name)
+            .to_string_lossy()
+            .to_string();
+    }
+    let Ok(ctx) = contexts.ctx_mut() else { return };
+    let mut open = true;
+    let mut save = false;
+    let mut cancel = false;
+    egui::Window::new("Save session")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0., 0.))
+        .show(ctx, |ui| {
+            let response = ui.add(egui::TextEdit::singleline(path).desired_width(400.));
+            save = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if Path::new(path.as_str()).exists() {
+                ui.small("The existing file will be replaced");
+            }
+            ui.horizontal(|ui| {
+                save |= ui.button("Save").clicked();
+                cancel = ui.button("Cancel").clicked();
+            });
+        });
+    if save {
+        save_session_evw.write(SaveSessionEvent(path.clone()));
+    }
+    if save || cancel || !open {
+        session_dialog.0 = None;
+    }
+}
+
+// Color bar with the normalization range, drawn in the top right corner of each colormapped cell
+fn ui_colormap_legend(
+    mut contexts: EguiContexts,
+    windows: Query<&Window>,
+    image_query: Query<(&Id, &ValueRange), With<MyImage>>,
+    colormap_state: Res<ColormapState>,
+    layout_state: Res<GridLayoutState>,
+    compare_state: Res<CompareState>,
+    config: Res<Config>,
+) {
+    let Some(colormap) = colormap_state.colormap else {
+        return;
+    };
+    if !colormap_state.legend || colormap == Colormap::Labels {
+        return;
+    }
+    let Ok(ctx) = contexts.ctx_mut() else { return };
+    let window = windows.single().unwrap();
+    let num_images = image_query.iter().count();
+    let bar_size = egui::vec2(120., 10.);
+
+    for (id, value_range) in &image_query {
+        let Some(settings) = colormap_settings(&colormap_state, value_range) else {
+            continue;
+        };
+        if !is_cell_visible(id.0, &layout_state, &compare_state) {
+            continue;
+        }
+        let (cell_offset, cell_size) =
+            get_cell_rect(id.0, num_images, &layout_state.layout, window, config.misc.grid_width);
+        let mut top_right = egui::pos2(cell_offset.x + cell_size.x - 10., cell_offset.y + 10.);
+        // Both images of Wipe and Blend share the window, the legend of the second one goes below
+        if matches!(layout_state.layout, GridLayout::Wipe | GridLayout::Blend) && id.0 != layout_state.index {
+            top_right.y += 45.;
+        }
+
+        egui::Area::new(egui::Id::new(("colormap_legend", id.0)))
+            .fixed_pos(top_right - egui::vec2(bar_size.x, 0.))
+            .interactable(false)
+            .show(ctx, |ui| {
+                let (rect, _) = ui.allocate_exact_size(bar_size, egui::Sense::hover());
+                let steps = 32;
+                let mut mesh = egui::Mesh::default();
+                for step in 0..=steps {
+                    let t = step as f32 / steps as f32;
+                    let [r, g, b] = settings.colormap.color(t).map(|v| (v * 255.).round() as u8);
+                    let color = egui::Color32::from_rgb(r, g, b);
+                    let x = rect.left() + t * rect.width();
+                    mesh.colored_vertex(egui::pos2(x, rect.top()), color);
+                    mesh.colored_vertex(egui::pos2(x, rect.bottom()), color);
+                    if step > 0 {
+                        let i = 2 * step;
+                        mesh.add_triangle(i - 2, i - 1, i);
+                        mesh.add_triangle(i - 1, i, i + 1);
+                    }
+                }
+                ui.painter().add(egui::Shape::mesh(mesh));
+                ui.painter().rect_stroke(
+                    rect,
+                    0.,
+                    egui::Stroke::new(1., egui::Color32::GRAY),
+                    egui::StrokeKind::Outside,
+                );
+                ui.allocate_ui(egui::vec2(bar_size.x, 14.), |ui| {
+                    ui.horizontal(|ui| {
+                        ui.small(format!("{}", settings.min));
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            ui.small(format!("{}", settings.max));
+                        });
+                    });
+                });
+            });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn ui_bottom_menu(
+    mut contexts: EguiContexts,
+    mut layout_state: ResMut<GridLayoutState>,
+    mut reset_vix_evw: MessageWriter<ResetVisibilityEvent>,
+    mut ui_state: ResMut<UiState>,
+    mut global_scale: ResMut<GlobalScale>,
+    mut move_image_evw: MessageWriter<MoveImageEvent>,
+    (mut save_cropped_evw, mut export_composite_evw, mut export_flicker_evw, mut session_dialog): (
+        MessageWriter<SaveCropped>,
+        MessageWriter<ExportCompositeEvent>,
+        MessageWriter<ExportFlickerEvent>,
+        ResMut<SessionDialog>,
+    ),
+    mut reset_scales_evw: MessageWriter<ResetScales>,
+    mut fit_to_screen_evw: MessageWriter<FitToScreen>,
+    mut add_mode: ResMut<AddMode>,
+    mut review_state: ResMut<ReviewState>,
+    mut activate_evw: MessageWriter<ActivateReviewEvent>,
+    loading_query: Query<&ImageView, With<MyImage>>,
+    mut compare_state: ResMut<CompareState>,
+    mut playback: ResMut<PlaybackState>,
+) {
+    if ui_state.visible {
+        let Ok(ctx) = contexts.ctx_mut() else { return };
+        egui::TopBottomPanel::bottom("wrap_app_top_bar").show(ctx, |ui| {
+            // equivalent to horizontal_wrapped but with a small factor on y to avoid the clip of button
+            let initial_size = egui::vec2(ui.available_size_before_wrap().x, ui.spacing().interact_size.y * 1.2);
+            ui.allocate_ui_with_layout(
+                initial_size,
+                egui::Layout::left_to_right(egui::Align::Center).with_main_wrap(true),
+                |ui| {
+                    egui::widgets::global_theme_preference_switch(ui);
+                    ui.toggle_value(&mut ui_state.settings_panel_visible, "\u{2699}");
+                    ui.toggle_value(&mut ui_state.image_list_visible, "\u{2630}");
+                    ui.toggle_value(&mut ui_state.display_bar_visible, "\u{2600}")
+                        .on_hover_text("Display settings (exposure, gamma, tone-mapping)");
+                    ui.toggle_value(&mut ui_state.metrics_visible, "\u{1F4CA}")
+                        .on_hover_text("Quality metrics (MSE, PSNR, SSIM)");
+                    ui.toggle_value(&mut ui_state.histogram_visible, "\u{1F4C8}")
+                        .on_hover_text("Histograms");
+                    ui.separator();
+                    let mut scale = global_scale.0.log2();
+
+                    if ui
+                        .add(
+                            egui::DragValue::new(&mut scale)
+                                .prefix("\u{1F50E} ")
+                                .speed(0.1)
+                                .range(-10.0..=10.),
+                        )
+                        .on_hover_text("Zoom")
+                        .changed()
+                    {
+                        global_scale.0 = 2f32.powf(scale);
+                    }
+
+                    if ui.button("1:1").on_hover_text("Reset All Zoom").clicked() {
+                        reset_scales_evw.write(ResetScales);
+                    }
+
+                    if ui.button("Fit").on_hover_text("Fit to Screen").clicked() {
+                        fit_to_screen_evw.write(FitToScreen);
+                    }
+
+                    for i in 0..10 {
                         let mut state = i == layout_state.index;
                         if ui.toggle_value(&mut state, format!("{}", i + 1)).changed() {
                             layout_state.index = i;
                             reset_vix_evw.write(ResetVisibilityEvent);
+                            move_image_evw.write(MoveImageEvent);
                         }
                     }
 
@@ -616,7 +1932,46 @@ fn ui_bottom_menu(
                     let elem4 = ui
                         .selectable_value(&mut layout_state.layout, GridLayout::Horizontal, "Horizontal")
                         .changed();
-                    if elem1 || elem2 || elem3 || elem4 {
+                    let elem5 = ui
+                        .selectable_value(&mut layout_state.layout, GridLayout::Wipe, "Wipe")
+                        .on_hover_text("Split the window between the selected image and a second one")
+                        .changed();
+                    let elem6 = ui
+                        .selectable_value(&mut layout_state.layout, GridLayout::Blend, "Blend")
+                        .on_hover_text("Draw a second image over the selected one with an adjustable opacity")
+                        .changed();
+                    let mut compare_changed = false;
+                    if matches!(layout_state.layout, GridLayout::Wipe | GridLayout::Blend) {
+                        let num_images = loading_query.iter().count();
+                        egui::ComboBox::from_id_salt("wipe_second")
+                            .selected_text(format!("with {}", compare_state.second + 1))
+                            .show_ui(ui, |ui| {
+                                for i in 0..num_images {
+                                    compare_changed |= ui
+                                        .selectable_value(&mut compare_state.second, i, format!("with {}", i + 1))
+                                        .changed();
+                                }
+                            });
+                    }
+                    if layout_state.layout == GridLayout::Wipe {
+                        let orientation = if compare_state.vertical { "\u{2016}" } else { "\u{2550}" };
+                        compare_changed |= ui
+                            .toggle_value(&mut compare_state.vertical, orientation)
+                            .on_hover_text("Vertical or horizontal divider")
+                            .changed();
+                    }
+                    if layout_state.layout == GridLayout::Blend {
+                        let mut opacity = compare_state.opacity * 100.;
+                        if ui
+                            .add(egui::Slider::new(&mut opacity, 0.0..=100.).suffix("%"))
+                            .on_hover_text("Opacity of the second image")
+                            .changed()
+                        {
+                            compare_state.opacity = opacity / 100.;
+                            compare_changed = true;
+                        }
+                    }
+                    if elem1 || elem2 || elem3 || elem4 || elem5 || elem6 || compare_changed {
                         reset_vix_evw.write(ResetVisibilityEvent);
                         move_image_evw.write(MoveImageEvent);
                     }
@@ -629,6 +1984,68 @@ fn ui_bottom_menu(
                     {
                         save_cropped_evw.write(SaveCropped);
                     }
+                    if ui
+                        .button("\u{1F5BC}")
+                        .on_hover_text("Export the layout with the visible crops and their names as one PNG")
+                        .clicked()
+                    {
+                        export_composite_evw.write(ExportCompositeEvent);
+                    }
+                    if ui
+                        .button("\u{1F39E}")
+                        .on_hover_text("Export the visible crops as an animation flickering between the images")
+                        .clicked()
+                    {
+                        export_flicker_evw.write(ExportFlickerEvent);
+                    }
+                    if ui
+                        .button("\u{1F4BE}")
+                        .on_hover_text("Save the images, layout, zoom, pan and review state to a session file (.ivs)")
+                        .clicked()
+                    {
+                        session_dialog.0.get_or_insert_default();
+                    }
+
+                    let frame_count = playback.frame_count;
+                    if frame_count > 0 {
+                        ui.separator();
+                        if ui.button("\u{23EE}").on_hover_text("Previous frame").clicked() {
+                            playback.playback.playing = false;
+                            playback.playback.step(-1, frame_count);
+                        }
+                        let play_pause = if playback.playback.playing {
+                            "\u{23F8}"
+                        } else {
+                            "\u{25B6}"
+                        };
+                        if ui
+                            .button(play_pause)
+                            .on_hover_text("Play / pause the animations and sequences")
+                            .clicked()
+                        {
+                            playback.playback.playing = !playback.playback.playing;
+                        }
+                        if ui.button("\u{23ED}").on_hover_text("Next frame").clicked() {
+                            playback.playback.playing = false;
+                            playback.playback.step(1, frame_count);
+                        }
+                        let mut frame = playback.playback.frame.min(frame_count - 1) + 1;
+                        ui.spacing_mut().slider_width = 200.;
+                        if ui
+                            .add(egui::Slider::new(&mut frame, 1..=frame_count).suffix(format!("/{frame_count}")))
+                            .on_hover_text("Frame shown in every animated cell and sequence")
+                            .changed()
+                        {
+                            playback.playback.seek(frame - 1);
+                        }
+                        ui.add(
+                            egui::DragValue::new(&mut playback.sequence_fps)
+                                .suffix(" fps")
+                                .speed(0.5)
+                                .range(1.0..=120.),
+                        )
+                        .on_hover_text("Frame rate of the image sequences");
+                    }
 
                     ui.separator();
                     ui.toggle_value(&mut add_mode.0, "Add")
@@ -640,12 +2057,25 @@ fn ui_bottom_menu(
                     if review_state.enabled && review_state.radixes.is_empty() {
                         activate_evw.write(ActivateReviewEvent);
                     }
+
+                    let total = loading_query.iter().count();
+                    let pending = loading_query
+                        .iter()
+                        .filter(|sprite| sprite.image == Handle::default())
+                        .count();
+                    if pending > 0 {
+                        ui.separator();
+                        ui.spinner();
+                        ui.label(format!("{}/{}", total - pending, total))
+                            .on_hover_text("Images decoded in the current batch");
+                    }
                 },
             );
         });
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn ui_settings_menu(
     mut contexts: EguiContexts,
     mut config: ResMut<Config>,
@@ -657,6 +2087,7 @@ fn ui_settings_menu(
     mut change_title_style_evw: MessageWriter<ChangeTitleStyleEvent>,
     mut save_settings_evw: MessageWriter<SaveSettingsEvent>,
     mut change_sampler_evw: MessageWriter<ChangeSamplerEvent>,
+    mut refresh_display_evw: MessageWriter<RefreshDisplayEvent>,
     mut next_state: ResMut<NextState<MyAppState>>,
 ) {
     if ui_state.settings_panel_visible {
@@ -732,6 +2163,125 @@ fn ui_settings_menu(
                     config.text.font_color = Color::LinearRgba(LinearRgba::from_f32_array(color_vec));
                 });
 
+                CollapsingHeader::new("Alpha").default_open(true).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Background:");
+                        ui.selectable_value(&mut config.alpha.background, AlphaBackground::None, "None");
+                        ui.selectable_value(
+                            &mut config.alpha.background,
+                            AlphaBackground::Checkerboard,
+                            "Checkerboard",
+                        );
+                        ui.selectable_value(&mut config.alpha.background, AlphaBackground::Solid, "Solid");
+                    });
+                    if config.alpha.background == AlphaBackground::Solid {
+                        let mut color_vec = config.alpha.color.to_linear().to_f32_array();
+                        ui.horizontal(|ui| {
+                            ui.label("Background Color:");
+                            ui.color_edit_button_rgba_unmultiplied(&mut color_vec);
+                        });
+                        config.alpha.color = Color::LinearRgba(LinearRgba::from_f32_array(color_vec));
+                    }
+                    if ui
+                        .checkbox(&mut config.alpha.ignore_alpha, "Ignore Alpha")
+                        .on_hover_text("Show every pixel opaque")
+                        .changed()
+                    {
+                        refresh_display_evw.write(RefreshDisplayEvent);
+                    }
+                });
+
+                CollapsingHeader::new("Crops").default_open(true).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Format:");
+                        egui::ComboBox::from_id_salt("crop_format")
+                            .selected_text(config.crop.format.name())
+                            .show_ui(ui, |ui| {
+                                for format in CropFormat::ALL {
+                                    ui.selectable_value(&mut config.crop.format, format, format.name());
+                                }
+                            });
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("JPEG Quality:");
+                        ui.add(egui::Slider::new(&mut config.crop.jpeg_quality, 1..=100));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Directory:");
+                        ui.text_edit_singleline(&mut config.crop.directory)
+                            .on_hover_text("Empty to save next to each image, relative paths start from its folder");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("File Name:");
+                        ui.text_edit_singleline(&mut config.crop.template).on_hover_text(
+                            "Placeholders: {stem} {ext} {x} {y} {w} {h} (visible crop) {index} (cell) {frame}",
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Existing Files:");
+                        for policy in OverwritePolicy::ALL {
+                            ui.selectable_value(&mut config.crop.overwrite, policy, policy.name());
+                        }
+                    });
+                });
+
+                CollapsingHeader::new("Composite").default_open(true).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Scale:");
+                        ui.add(
+                            egui::DragValue::new(&mut config.composite.scale)
+                                .speed(0.05)
+                                .range(0.05..=8.),
+                        )
+                        .on_hover_text("1 keeps the image pixels of the visible crops");
+                        ui.label("Gap:");
+                        ui.add(
+                            egui::DragValue::new(&mut config.composite.gap)
+                                .range(0..=200)
+                                .suffix(" px"),
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Label Size:");
+                        ui.add(egui::DragValue::new(&mut config.composite.label_size).range(0.0..=200.))
+                            .on_hover_text("0 hides the file names");
+                        ui.label("Font:");
+                        ui.text_edit_singleline(&mut config.composite.font)
+                            .on_hover_text("Font file of the labels, empty for the font of the cell titles");
+                    });
+                    let mut label_color = config.composite.label_color.to_linear().to_f32_array();
+                    let mut background = config.composite.background.to_linear().to_f32_array();
+                    ui.horizontal(|ui| {
+                        ui.label("Label Color:");
+                        ui.color_edit_button_rgba_unmultiplied(&mut label_color);
+                        ui.label("Background:");
+                        ui.color_edit_button_rgba_unmultiplied(&mut background);
+                    });
+                    config.composite.label_color = Color::LinearRgba(LinearRgba::from_f32_array(label_color));
+                    config.composite.background = Color::LinearRgba(LinearRgba::from_f32_array(background));
+                });
+
+                CollapsingHeader::new("Flicker").default_open(true).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Format:");
+                        egui::ComboBox::from_id_salt("flicker_format")
+                            .selected_text(config.flicker.format.name())
+                            .show_ui(ui, |ui| {
+                                for format in export::AnimationFormat::ALL {
+                                    ui.selectable_value(&mut config.flicker.format, format, format.name());
+                                }
+                            });
+                        ui.label("Frame:");
+                        ui.add(
+                            egui::DragValue::new(&mut config.flicker.frame_duration_ms)
+                                .range(10..=10000)
+                                .suffix(" ms"),
+                        );
+                        ui.checkbox(&mut config.flicker.captions, "Captions")
+                            .on_hover_text("Write the file name above each crop");
+                    });
+                });
+
                 CollapsingHeader::new("Short Cut").default_open(true).show(ui, |ui| {
                     keycode_dropdown(
                         ui,
@@ -789,6 +2339,14 @@ fn ui_settings_menu(
                         &mut ongoing_edit.switch_layout,
                         &mut recorded_key,
                     );
+                    keycode_dropdown(
+                        ui,
+                        &mut next_state,
+                        "Change Channels:",
+                        &mut config.shortcut.switch_channel,
+                        &mut ongoing_edit.switch_channel,
+                        &mut recorded_key,
+                    );
                 });
 
                 if ui.button("Save Settings").clicked() {
@@ -808,13 +2366,23 @@ fn ui_edit_short_cut(mut contexts: EguiContexts) {
     });
 }
 
+#[allow(clippy::too_many_arguments)]
 fn ui_image_list_panel(
     mut contexts: EguiContexts,
     ui_state: Res<UiState>,
     mut image_order: ResMut<ImageOrder>,
-    image_path_query: Query<(&Id, &ImagePath), With<MyImage>>,
+    image_path_query: Query<(&Id, &ImagePath, Option<&Layers>), With<MyImage>>,
+    mut tone_query: Query<(&Id, &mut ToneOverride, &ImageColorSpace), With<MyImage>>,
+    metadata_query: Query<(&Id, &ExifMetadata), With<MyImage>>,
     mut remove_image_evw: MessageWriter<RemoveImageEvent>,
     mut reorder_evw: MessageWriter<ReorderImagesEvent>,
+    mut refresh_display_evw: MessageWriter<RefreshDisplayEvent>,
+    mut change_layer_evw: MessageWriter<ChangeLayerEvent>,
+    mut change_color_space_evw: MessageWriter<ChangeColorSpaceEvent>,
+    mut load_image_evw: MessageWriter<LoadNewImageEvent>,
+    config: Res<Config>,
+    mut tone_edit_id: Local<Option<usize>>,
+    mut metadata_id: Local<Option<usize>>,
 ) {
     if !ui_state.image_list_visible {
         return;
@@ -823,7 +2391,7 @@ fn ui_image_list_panel(
 
     // Build a lookup from image Id -> short name
     let mut name_map: Vec<(usize, String)> = Vec::new();
-    for (id, path) in &image_path_query {
+    for (id, path, _) in &image_path_query {
         let short = get_short_name(&path.0).unwrap_or("?");
         name_map.push((id.0, short.to_string()));
     }
@@ -861,10 +2429,60 @@ fn ui_image_list_panel(
                             })
                             .response;
 
+                        let mut showing = *metadata_id == Some(image_id);
+                        if ui
+                            .toggle_value(&mut showing, "\u{2139}")
+                            .on_hover_text("EXIF metadata of this image")
+                            .changed()
+                        {
+                            *metadata_id = showing.then_some(image_id);
+                        }
+
+                        let mut editing = *tone_edit_id == Some(image_id);
+                        if ui
+                            .toggle_value(&mut editing, "\u{2600}")
+                            .on_hover_text("Display transform of this image")
+                            .changed()
+                        {
+                            *tone_edit_id = editing.then_some(image_id);
+                        }
+
                         if ui.button("\u{2716}").on_hover_text("Remove image").clicked() {
                             to_remove = Some(image_id);
                         }
 
+                        // Layer picker of files holding several images, another layer can fill a new cell
+                        if let Some((_, path, Some(layers))) = image_path_query.iter().find(|(id, ..)| id.0 == image_id)
+                        {
+                            let mut selected = layers.selected;
+                            egui::ComboBox::from_id_salt(row_id.with("layer"))
+                                .selected_text(&layers.names[selected])
+                                .show_ui(ui, |ui| {
+                                    for (index, name) in layers.names.iter().enumerate() {
+                                        ui.selectable_value(&mut selected, index, name);
+                                    }
+                                })
+                                .response
+                                .on_hover_text("Layer or page shown in this cell");
+                            if selected != layers.selected {
+                                change_layer_evw.write(ChangeLayerEvent {
+                                    id: image_id,
+                                    layer: selected,
+                                });
+                            }
+                            if ui
+                                .button("+")
+                                .on_hover_text("Show the next layer in a new cell")
+                                .clicked()
+                            {
+                                load_image_evw.write(LoadNewImageEvent {
+                                    path: path.0.clone(),
+                                    index: name_map.len(),
+                                    layer: (layers.selected + 1) % layers.names.len(),
+                                });
+                            }
+                        }
+
                         // Show a drop indicator line and determine insertion point
                         if let (Some(pointer), Some(hovered_payload)) = (
                             ui.input(|i| i.pointer.interact_pos()),
@@ -890,6 +2508,79 @@ fn ui_image_list_panel(
                             }
                         }
                     });
+
+                    if *metadata_id == Some(image_id) {
+                        ui.indent(row_id.with("metadata"), |ui| {
+                            let Some((_, metadata)) = metadata_query.iter().find(|(id, _)| id.0 == image_id) else {
+                                ui.weak("No EXIF metadata");
+                                return;
+                            };
+                            egui::Grid::new(row_id.with("exif_summary"))
+                                .striped(true)
+                                .show(ui, |ui| {
+                                    for (name, value) in &metadata.0.summary {
+                                        ui.label(*name);
+                                        ui.label(value);
+                                        ui.end_row();
+                                    }
+                                });
+                            ui.collapsing("All fields", |ui| {
+                                egui::Grid::new(row_id.with("exif_fields"))
+                                    .striped(true)
+                                    .show(ui, |ui| {
+                                        for (name, value) in &metadata.0.fields {
+                                            ui.label(name);
+                                            ui.label(value);
+                                            ui.end_row();
+                                        }
+                                    });
+                            });
+                        });
+                    }
+
+                    if *tone_edit_id != Some(image_id) {
+                        continue;
+                    }
+                    let Some((_, mut tone_override, color_space)) =
+                        tone_query.iter_mut().find(|(id, ..)| id.0 == image_id)
+                    else {
+                        continue;
+                    };
+                    ui.indent(row_id, |ui| {
+                        // Auto uses the embedded ICC profile, or the default of the format
+                        let auto = match &color_space.embedded {
+                            Some(embedded) => format!("Auto ({})", embedded.name()),
+                            None => format!("Auto ({})", ColorSpace::default_for(color_space.linear).name()),
+                        };
+                        let mut assigned = color_space.assigned;
+                        ui.horizontal(|ui| {
+                            ui.label("Color space");
+                            egui::ComboBox::from_id_salt(row_id.with("color_space"))
+                                .selected_text(assigned.map_or(auto.clone(), |space| space.name().to_string()))
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut assigned, None, &auto);
+                                    for space in ColorSpace::ALL {
+                                        ui.selectable_value(&mut assigned, Some(space), space.name());
+                                    }
+                                });
+                        });
+                        if assigned != color_space.assigned {
+                            change_color_space_evw.write(ChangeColorSpaceEvent { id: image_id, assigned });
+                        }
+
+                        let mut overridden = tone_override.0.is_some();
+                        if ui.checkbox(&mut overridden, "Override global display").changed() {
+                            tone_override.0 = overridden.then_some(config.hdr.tone);
+                            refresh_display_evw.write(RefreshDisplayEvent);
+                        }
+                        if let Some(tone) = &mut tone_override.0 {
+                            ui.horizontal_wrapped(|ui| {
+                                if tone_settings_ui(ui, row_id.with("tone"), tone) {
+                                    refresh_display_evw.write(RefreshDisplayEvent);
+                                }
+                            });
+                        }
+                    });
                 }
             });
 
@@ -910,72 +2601,116 @@ fn ui_image_list_panel(
         });
 }
 
+// Display slot of the image shown at `slot` once the images at the `removed` slots are gone
+fn slot_after_removal(slot: usize, removed: &[usize]) -> Option<usize> {
+    (!removed.contains(&slot)).then(|| slot - removed.iter().filter(|&&id| id < slot).count())
+}
+
+// Display slot of the image shown at `slot` after a reorder, `order` holds the previous slot of each new slot
+fn slot_after_reorder(slot: usize, order: &[usize]) -> Option<usize> {
+    order.iter().position(|&id| id == slot)
+}
+
+#[allow(clippy::too_many_arguments)]
 fn on_remove_image(
     mut remove_evr: MessageReader<RemoveImageEvent>,
+    mut decode_failed_evr: MessageReader<DecodeFailedEvent>,
     mut commands: Commands,
     mut query_by_id: Query<(Entity, &mut Id)>,
     mut image_order: ResMut<ImageOrder>,
     mut move_image_evw: MessageWriter<MoveImageEvent>,
     mut fit_to_screen_evw: MessageWriter<FitToScreen>,
     mut review_state: ResMut<ReviewState>,
+    mut difference_state: ResMut<DifferenceState>,
+    mut refresh_display_evw: MessageWriter<RefreshDisplayEvent>,
+    mut metrics_state: ResMut<MetricsState>,
+    mut histogram_state: ResMut<HistogramState>,
+    mut compare_state: ResMut<CompareState>,
 ) {
-    for ev in remove_evr.read() {
-        let target_id = ev.0;
+    // Resolve every removal of the frame before renumbering: despawned entities keep their Id
+    // until the end of the frame, so Ids are only valid before the first renumbering
+    let mut target_ids: Vec<usize> = remove_evr.read().map(|ev| ev.0).collect();
+    for ev in decode_failed_evr.read() {
+        // The entity is gone when its batch was replaced meanwhile
+        if let Ok((_, id)) = query_by_id.get(ev.0) {
+            target_ids.push(id.0);
+        }
+    }
+    target_ids.sort_unstable();
+    target_ids.dedup();
+    if target_ids.is_empty() {
+        return;
+    }
 
-        // Despawn all entity with matching Id
-        for (entity, id) in &query_by_id {
-            if id.0 == target_id {
-                commands.entity(entity).despawn();
-                continue;
-            }
+    // Despawn all entity with matching Id
+    for (entity, id) in &query_by_id {
+        if target_ids.contains(&id.0) {
+            commands.entity(entity).despawn();
         }
+    }
 
-        // Remove the corresponding review pattern
+    // Remove the corresponding review patterns, from the last one so indices stay valid
+    for &target_id in target_ids.iter().rev() {
         if review_state.enabled && target_id < review_state.cell_patterns.len() {
             review_state.cell_patterns.remove(target_id);
         }
         if review_state.enabled && target_id < review_state.editable_patterns.len() {
             review_state.editable_patterns.remove(target_id);
         }
+    }
 
-        // Remove from ImageOrder, keeping remaining in display order
-        image_order.0.retain(|&id| id != target_id);
+    // Remove from ImageOrder, keeping remaining in display order
+    image_order.0.retain(|id| !target_ids.contains(id));
 
-        // Reassign contiguous Ids on remaining entities using two passes to avoid collisions.
-        let old_ids: Vec<usize> = image_order.0.clone();
-        let count = old_ids.len();
+    // Reassign contiguous Ids on remaining entities using two passes to avoid collisions.
+    let old_ids: Vec<usize> = image_order.0.clone();
+    let offset = old_ids.len() + target_ids.len() + 1;
 
-        // Pass 1: shift to temporary range
-        for &old_id in &old_ids {
-            let temp_id = old_id + count + 1;
-            for (_, mut id) in &mut query_by_id {
-                if id.0 == old_id {
-                    id.0 = temp_id;
-                    continue;
-                }
+    // Pass 1: shift to temporary range
+    for &old_id in &old_ids {
+        let temp_id = old_id + offset;
+        for (_, mut id) in &mut query_by_id {
+            if id.0 == old_id {
+                id.0 = temp_id;
             }
         }
+    }
 
-        // Pass 2: assign final contiguous values
-        for (new_idx, &old_id) in old_ids.iter().enumerate() {
-            let temp_id = old_id + count + 1;
-            for (_, mut id) in &mut query_by_id {
-                if id.0 == temp_id {
-                    id.0 = new_idx;
-                    continue;
-                }
+    // Pass 2: assign final contiguous values
+    for (new_idx, &old_id) in old_ids.iter().enumerate() {
+        let temp_id = old_id + offset;
+        for (_, mut id) in &mut query_by_id {
+            if id.0 == temp_id {
+                id.0 = new_idx;
             }
         }
+    }
 
-        // Rebuild ImageOrder as contiguous [0, 1, 2, ...]
-        let count = image_order.0.len();
-        image_order.0 = (0..count).collect();
+    // Rebuild ImageOrder as contiguous [0, 1, 2, ...]
+    let count = image_order.0.len();
+    image_order.0 = (0..count).collect();
 
-        fit_to_screen_evw.write(FitToScreen);
-        move_image_evw.write(MoveImageEvent);
+    // The second image of Wipe and Blend follows its image, or falls back to the first one
+    compare_state.second = slot_after_removal(compare_state.second, &target_ids).unwrap_or(0);
+
+    // The reference of the difference view follows its image, or falls back to the first one
+    difference_state.reference = slot_after_removal(difference_state.reference, &target_ids).unwrap_or(0);
+    if difference_state.enabled {
+        refresh_display_evw.write(RefreshDisplayEvent);
     }
+
+    // The metrics reference follows its image, or falls back to the first one when it's removed
+    metrics_state.reference = slot_after_removal(metrics_state.reference, &target_ids).unwrap_or(0);
+    metrics_state.invalidate();
+
+    // The histograms follow their images, removed ones are dropped
+    histogram_state.remap(|slot| slot_after_removal(slot, &target_ids));
+
+    fit_to_screen_evw.write(FitToScreen);
+    move_image_evw.write(MoveImageEvent);
 }
 
+#[allow(clippy::too_many_arguments)]
 fn on_reorder_images(
     mut reorder_evr: MessageReader<ReorderImagesEvent>,
     mut image_order: ResMut<ImageOrder>,
@@ -983,6 +2718,11 @@ fn on_reorder_images(
     mut move_image_evw: MessageWriter<MoveImageEvent>,
     mut reset_vis_evw: MessageWriter<ResetVisibilityEvent>,
     mut review_state: ResMut<ReviewState>,
+    mut difference_state: ResMut<DifferenceState>,
+    mut refresh_display_evw: MessageWriter<RefreshDisplayEvent>,
+    mut metrics_state: ResMut<MetricsState>,
+    mut histogram_state: ResMut<HistogramState>,
+    mut compare_state: ResMut<CompareState>,
 ) {
     if reorder_evr.is_empty() {
         return;
@@ -1035,148 +2775,950 @@ fn on_reorder_images(
     // Rebuild ImageOrder as contiguous [0, 1, 2, ...]
     image_order.0 = (0..count).collect();
 
+    // The second image of Wipe and Blend follows its image
+    if let Some(slot) = slot_after_reorder(compare_state.second, &order_snapshot) {
+        compare_state.second = slot;
+    }
+
+    // The reference of the difference view follows its image
+    if let Some(slot) = slot_after_reorder(difference_state.reference, &order_snapshot) {
+        difference_state.reference = slot;
+    }
+    if difference_state.enabled {
+        refresh_display_evw.write(RefreshDisplayEvent);
+    }
+
+    // The metrics reference follows its image
+    if let Some(slot) = slot_after_reorder(metrics_state.reference, &order_snapshot) {
+        metrics_state.reference = slot;
+    }
+    metrics_state.invalidate();
+
+    // The histograms follow their images
+    histogram_state.remap(|slot| slot_after_reorder(slot, &order_snapshot));
+
     move_image_evw.write(MoveImageEvent);
     reset_vis_evw.write(ResetVisibilityEvent);
 }
 
+// Open and decode an image file. Runs on the async compute pool so it must not touch the ECS.
+fn decode_image(path: &str) -> Option<DynamicImage> {
+    let Some(f) = File::open(path).ok() else {
+        println!("Failed to open file: {}", path);
+        return None;
+    };
+    let Some(format) = ImageFormat::from_path(path).ok() else {
+        println!("Failed to deduce image format from path: {}", path);
+        return None;
+    };
+    decode_image_from(BufReader::new(f), format, path).map(|(image, _)| image)
+}
+
+// Decode an image and its embedded ICC profile, read by the same decoder
+fn decode_image_from<R: BufRead + Seek>(
+    reader: R,
+    format: ImageFormat,
+    path: &str,
+) -> Option<(DynamicImage, Option<Vec<u8>>)> {
+    let mut reader = image::ImageReader::with_format(reader, format);
+
+    // Remove the memory limit on image size we can read
+    // This is required to process large images that would otherwise be rejected by the image crate
+    reader.no_limits();
+
+    // Photos are stored as shot and rotated by their EXIF Orientation tag
+    let Some(decoded) = reader.into_decoder().ok().and_then(|mut decoder| {
+        let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+        let icc = decoder.icc_profile().ok().flatten();
+        let mut image = DynamicImage::from_decoder(decoder).ok()?;
+        image.apply_orientation(orientation);
+        Some((image, icc))
+    }) else {
+        println!("Failed to decode image: {}", path);
+        return None;
+    };
+    Some(decoded)
+}
+
+// Decode the image of a cell: the given layer of files holding several images, and every frame of animations.
+// The file is read once, its layers, frames, profile and metadata are decoded from memory.
+fn decode_cell(path: &str, layer: usize) -> Option<DecodedImage> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) => {
+            println!("Failed to open file {}: {}", path, e);
+            return None;
+        }
+    };
+    let Some(format) = ImageFormat::from_path(path).ok() else {
+        println!("Failed to deduce image format from path: {}", path);
+        return None;
+    };
+    let layers = layers::list_from(Cursor::new(&data), format);
+    // Animations are decoded once, their first frame is the image of the cell
+    let animation = if layers.is_empty() {
+        animation::decode_from(Cursor::new(&data), format)
+    } else {
+        None
+    };
+    let (image, icc) = if let Some(animation) = &animation {
+        (
+            animation.frames[0].clone(),
+            colorspace::embedded(Cursor::new(&data), format),
+        )
+    } else if layers.is_empty() {
+        let (image, icc) = decode_image_from(Cursor::new(&data), format, path)?;
+        (Arc::new(image), icc)
+    } else if let Some(image) = layers::decode_from(Cursor::new(&data), format, layer) {
+        (Arc::new(image), colorspace::embedded(Cursor::new(&data), format))
+    } else if layer == 0 {
+        // The first page or part is also the one read by the image crate, which knows more pixel formats
+        let (image, icc) = decode_image_from(Cursor::new(&data), format, path)?;
+        (Arc::new(image), icc)
+    } else {
+        println!("Failed to decode layer {} of image: {}", layer + 1, path);
+        return None;
+    };
+    let color_space = icc.as_deref().and_then(colorspace::from_icc);
+    let icc_lut = color_space
+        .as_ref()
+        .and_then(|space| space.lut(ICC_LUT_SIZE))
+        .map(|lut| icc_lut_texture(&lut));
+    let range = display::single_channel_range(&image);
+    Some(DecodedImage {
+        image,
+        animation: animation.filter(|animation| animation.is_animated()),
+        layers,
+        layer,
+        metadata: metadata::read_from(Cursor::new(&data)),
+        color_space,
+        icc_lut,
+        range,
+    })
+}
+
+// Title of a cell: the file name, followed by the layer for files holding several images,
+// and the color space of the image
+fn cell_title(path: &str, layers: Option<&Layers>, color_space: &ImageColorSpace) -> String {
+    let short_path = get_short_name(path).unwrap_or("");
+    let layer = layers
+        .map(|layers| format!(" [{}]", layers.names[layers.selected]))
+        .unwrap_or_default();
+    format!("{short_path}{layer} ({})", color_space.active().name())
+}
+
+// Upload the stored values of a decoded image, integers normalized to [0, 1] and floats as is.
+// The pixels are only kept on the GPU, the SourceImage is the CPU copy. The shader loads the texels
+// and interpolates them itself (DisplayParams::bilinear), so the textures have no sampler.
+fn stored_texture(image: &DynamicImage) -> Image {
+    let usage = RenderAssetUsages::RENDER_WORLD;
+    match image.color() {
+        ColorType::Rgb8 | ColorType::Rgba8 | ColorType::L8 | ColorType::La8 => {
+            Image::from_dynamic(DynamicImage::ImageRgba8(image.to_rgba8()), false, usage)
+        }
+        // Bevy uploads gray 16 bits as integer textures that can't be filtered
+        ColorType::Rgb16 | ColorType::Rgba16 | ColorType::L16 | ColorType::La16 => {
+            Image::from_dynamic(DynamicImage::ImageRgba16(image.to_rgba16()), false, usage)
+        }
+        // Float images and any other color type keep full floats: half floats would turn values
+        // above 65504 into infinities and lose the small differences between images
+        _ => {
+            let data: Vec<u8> = image
+                .to_rgba32f()
+                .into_raw()
+                .into_iter()
+                .flat_map(f32::to_le_bytes)
+                .collect();
+            Image::new(
+                Extent3d {
+                    width: image.width(),
+                    height: image.height(),
+                    depth_or_array_layers: 1,
+                },
+                TextureDimension::D2,
+                data,
+                TextureFormat::Rgba32Float,
+                usage,
+            )
+        }
+    }
+}
+
+// Number of samples along each axis of the ICC profile conversions given to the shader
+const ICC_LUT_SIZE: usize = 33;
+
+// 3D texture of an ImageSpace::lut, interpolated by the shader
+fn icc_lut_texture(lut: &[[f32; 3]]) -> Image {
+    let size = ICC_LUT_SIZE as u32;
+    let data: Vec<u8> = lut
+        .iter()
+        .flat_map(|[r, g, b]| [*r, *g, *b, 1.])
+        .flat_map(|value| f16::from_f32(value).to_le_bytes())
+        .collect();
+    let mut texture = Image::new(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: size,
+        },
+        TextureDimension::D3,
+        data,
+        TextureFormat::Rgba16Float,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    texture.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor::linear());
+    texture
+}
+
+// Spawn a placeholder cell for each requested image and start decoding it in the background.
+// Dropping the DecodeTask (when the entity is despawned) cancels the decoding.
+#[allow(clippy::too_many_arguments)]
 fn on_load_image(
     mut load_evr: MessageReader<LoadNewImageEvent>,
-    mut loaded_evw: MessageWriter<NewImageLoadedEvent>,
+    mut commands: Commands,
+    config: Res<Config>,
+    images: Query<Entity, With<Id>>,
+    mut help_query: Query<&mut Visibility, With<MyHelp>>,
+    font_query: Query<&FontHandle>,
+    mut is_new_batch: ResMut<NewImageBatch>,
+    mut image_order: ResMut<ImageOrder>,
+    playback: Res<PlaybackState>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<DisplayMaterial>>,
+) {
+    for ev in load_evr.read() {
+        let font = font_query.single().unwrap();
+
+        // A new batch replaces the current one, this also cancels the images still decoding
+        if is_new_batch.0 {
+            for entity in &images {
+                commands.entity(entity).despawn();
+            }
+            image_order.0.clear();
+            is_new_batch.0 = false;
+        }
+
+        // A directory or a frame pattern binds the cell to a sequence, it starts on the timeline frame
+        let sequence = sequence::resolve(&ev.path).map(|paths| {
+            let shown = playback.playback.frame.min(paths.len() - 1);
+            Sequence {
+                source: ev.path.clone(),
+                paths,
+                frames: HashMap::new(),
+                pending: HashMap::new(),
+                shown,
+            }
+        });
+        let image_path = match &sequence {
+            Some(sequence) => sequence.paths[sequence.shown].clone(),
+            None => ev.path.clone(),
+        };
+
+        let path = image_path.clone();
+        let layer = ev.layer;
+        let task = AsyncComputeTaskPool::get().spawn(async move { decode_cell(&path, layer) });
+
+        // Start hidden; on_move_image will make it visible after the texture is ready and positioned
+        let mut image = commands.spawn((
+            ImageView::default(),
+            Mesh2d(meshes.add(Rectangle::default())),
+            MeshMaterial2d(materials.add(DisplayMaterial::default())),
+            Visibility::Hidden,
+            Id(ev.index),
+            LoadSlot(ev.index),
+            Scale(1.),
+            Position(Vec2::ZERO),
+            Rotation(0),
+            ImagePath(image_path),
+            ToneOverride::default(),
+            ImageColorSpace::default(),
+            DecodeTask(task),
+            MyImage,
+        ));
+        image.with_child((
+            Sprite::default(),
+            Transform::from_translation(Vec3::new(0., 0., -0.05)),
+            MyBackground,
+        ));
+        if let Some(sequence) = sequence {
+            image.insert(sequence);
+        }
+
+        let short_path = get_short_name(&ev.path).unwrap_or("");
+        commands.spawn((
+            Text::new(format!("{short_path} (loading\u{2026})")),
+            TextFont {
+                font: font.0.clone(),
+                font_size: config.text.font_size,
+                ..default()
+            },
+            TextColor(config.text.font_color),
+            TextLayout {
+                justify: Justify::Left,
+                ..default()
+            },
+            Node {
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            Visibility::Hidden,
+            Id(ev.index),
+            MyText,
+        ));
+
+        // Track the new image in display order
+        image_order.0.push(ev.index);
+
+        let mut help_visibility = help_query.single_mut().unwrap();
+        *help_visibility = Visibility::Hidden;
+    }
+}
+
+// Poll the background decoding and keep the decoded pixels, the displayed texture is built afterward
+#[allow(clippy::type_complexity)]
+fn on_image_decoded(
+    mut commands: Commands,
+    mut task_query: Query<
+        (
+            Entity,
+            &Id,
+            &ImagePath,
+            &mut DecodeTask,
+            &mut ImageColorSpace,
+            Option<&mut Sequence>,
+            Has<SourceImage>,
+        ),
+        With<MyImage>,
+    >,
+    mut text_query: Query<(&Id, &mut Text), With<MyText>>,
+    playback: Res<PlaybackState>,
+    mut images: ResMut<Assets<Image>>,
+    mut decode_failed_evw: MessageWriter<DecodeFailedEvent>,
+) {
+    for (entity, id, path, mut task, mut image_color_space, sequence, reloaded) in &mut task_query {
+        let Some(result) = check_ready(&mut task.0) else {
+            continue;
+        };
+        commands.entity(entity).remove::<DecodeTask>();
+
+        let Some(DecodedImage {
+            mut image,
+            animation,
+            layers,
+            layer,
+            metadata,
+            color_space,
+            icc_lut,
+            range,
+        }) = result
+        else {
+            // A cell changing layer keeps the one it shows
+            if reloaded {
+                println!("Keeping the previous layer of image: {}", path.0);
+            } else {
+                println!("Failed to load image: {}", path.0);
+                decode_failed_evw.write(DecodeFailedEvent(entity));
+            }
+            continue;
+        };
+        let layers = (!layers.is_empty()).then_some(Layers {
+            names: layers,
+            selected: layer,
+        });
+        if let Some(metadata) = metadata {
+            commands.entity(entity).insert(ExifMetadata(metadata));
+        }
+        image_color_space.embedded = color_space;
+        image_color_space.icc_lut = icc_lut.map(|lut| images.add(lut));
+        image_color_space.linear = display::is_linear(&image);
+        if reloaded {
+            // The statistics of the previous layer don't apply anymore
+            commands
+                .entity(entity)
+                .remove::<(LevelRange, LevelsTask, ProblemCounts, ProblemsTask)>();
+            let title = cell_title(&path.0, layers.as_ref(), &image_color_space);
+            for (_, mut text) in text_query.iter_mut().filter(|(text_id, _)| text_id.0 == id.0) {
+                text.0 = title.clone();
+            }
+        }
+        if let Some(layers) = layers {
+            commands.entity(entity).insert(layers);
+        }
+        // Animations join the playback at the current frame
+        if let Some(animation) = animation {
+            let shown = animation.frame_index(playback.playback.frame);
+            image = animation.frames[shown].clone();
+            commands.entity(entity).insert(AnimationFrames { animation, shown });
+        }
+        if let Some(mut sequence) = sequence {
+            let shown = sequence.shown;
+            sequence.frames.insert(
+                shown,
+                DecodedFrame {
+                    image: image.clone(),
+                    range,
+                },
+            );
+        }
+        commands.entity(entity).insert((SourceImage(image), ValueRange(range)));
+    }
+}
+
+// Play the animations and sequences in lockstep: every cell shows the same frame index,
+// timed with the frame delays of the longest animation or the frame rate of the sequences
+fn on_advance_playback(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut playback: ResMut<PlaybackState>,
+    mut animation_query: Query<(Entity, &mut AnimationFrames, &mut SourceImage), With<MyImage>>,
+    sequence_query: Query<&Sequence, With<MyImage>>,
+) {
+    let longest_animation = animation_query
+        .iter()
+        .map(|(_, frames, _)| &frames.animation)
+        .max_by_key(|animation| animation.frames.len());
+    let longest_sequence = sequence_query.iter().map(|sequence| sequence.paths.len()).max();
+    let delays = match (longest_animation, longest_sequence) {
+        (None, None) => {
+            playback.frame_count = 0;
+            return;
+        }
+        (Some(animation), sequence_len) if sequence_len.is_none_or(|len| len <= animation.frames.len()) => {
+            animation.delays.clone()
+        }
+        (_, sequence_len) => vec![Duration::from_secs_f32(1. / playback.sequence_fps); sequence_len.unwrap_or(0)],
+    };
+    playback.frame_count = delays.len();
+    if playback.playback.frame >= delays.len() {
+        playback.playback.frame = 0;
+    }
+    playback.playback.advance(time.delta(), &delays);
+
+    // Changing the SourceImage uploads the frame, its levels and problem counts are computed again
+    for (entity, mut frames, mut source) in &mut animation_query {
+        let shown = frames.animation.frame_index(playback.playback.frame);
+        if shown != frames.shown {
+            frames.shown = shown;
+            source.0 = frames.animation.frames[shown].clone();
+            commands
+                .entity(entity)
+                .remove::<(LevelRange, LevelsTask, ProblemCounts, ProblemsTask)>();
+        }
+    }
+}
+
+// Decode the frames around the timeline position in the background, drop the others,
+// and show the frame of the timeline once it's decoded
+#[allow(clippy::type_complexity)]
+fn on_preload_sequence(
+    mut commands: Commands,
+    mut sequence_query: Query<
+        (Entity, &Id, &mut Sequence, &mut ImagePath, &ImageColorSpace),
+        (With<MyImage>, With<SourceImage>),
+    >,
+    mut title_query: Query<(&Id, &mut Text), With<MyText>>,
+    playback: Res<PlaybackState>,
+) {
+    for (entity, id, mut sequence, mut path, color_space) in &mut sequence_query {
+        let sequence = &mut *sequence;
+        let target = playback.playback.frame.min(sequence.paths.len() - 1);
+
+        let mut decoded = Vec::new();
+        sequence.pending.retain(|index, task| match check_ready(task) {
+            Some(image) => {
+                decoded.push((*index, image));
+                false
+            }
+            None => true,
+        });
+        for (index, frame) in decoded {
+            if let Some(frame) = frame {
+                sequence.frames.insert(index, frame);
+            }
+        }
+
+        let wanted = sequence::preload_order(
+            target,
+            sequence.paths.len(),
+            SEQUENCE_PRELOAD_AHEAD,
+            SEQUENCE_PRELOAD_BEHIND,
+        );
+        sequence.frames.retain(|index, _| wanted.contains(index));
+        sequence.pending.retain(|index, _| wanted.contains(index));
+        for index in wanted {
+            if !sequence.frames.contains_key(&index) && !sequence.pending.contains_key(&index) {
+                let frame_path = sequence.paths[index].clone();
+                let task = AsyncComputeTaskPool::get()
+                    .spawn(async move { decode_image(&frame_path).map(|image| DecodedFrame::new(Arc::new(image))) });
+                sequence.pending.insert(index, task);
+            }
+        }
+
+        // Keep the previous frame on screen until the target one is decoded
+        if target == sequence.shown {
+            continue;
+        }
+        let Some(frame) = sequence.frames.get(&target) else {
+            continue;
+        };
+        sequence.shown = target;
+        path.0 = sequence.paths[target].clone();
+        commands
+            .entity(entity)
+            .insert((SourceImage(frame.image.clone()), ValueRange(frame.range)))
+            .remove::<(LevelRange, LevelsTask, ProblemCounts, ProblemsTask)>();
+        let title = cell_title(&path.0, None, color_space);
+        for (_, mut text) in title_query.iter_mut().filter(|(text_id, _)| text_id.0 == id.0) {
+            text.0 = title.clone();
+        }
+    }
+}
+
+// Rebuild the displayed texture of every image
+fn on_refresh_display(
+    mut refresh_evr: MessageReader<RefreshDisplayEvent>,
+    mut commands: Commands,
+    image_query: Query<Entity, (With<MyImage>, With<SourceImage>)>,
+) {
+    if refresh_evr.is_empty() {
+        return;
+    }
+    refresh_evr.clear();
+
+    for entity in &image_query {
+        commands.entity(entity).insert(DisplayDirty);
+    }
+}
+
+// Start uploading the images whose SourceImage changed: decoded, new frame or new layer.
+// A pending upload isn't cancelled, it would never complete while playing frames faster than
+// they are built; the latest SourceImage is uploaded once it's done.
+#[allow(clippy::type_complexity)]
+fn on_upload_source(
+    mut commands: Commands,
+    source_query: Query<(Entity, &SourceImage, Has<TextureTask>), (With<MyImage>, Changed<SourceImage>)>,
+) {
+    for (entity, source, uploading) in &source_query {
+        if uploading {
+            commands.entity(entity).insert(TextureOutdated);
+        } else {
+            commands.entity(entity).insert(upload_task(source));
+        }
+    }
+}
+
+fn upload_task(source: &SourceImage) -> TextureTask {
+    let source = source.0.clone();
+    TextureTask(AsyncComputeTaskPool::get().spawn(async move { stored_texture(&source) }))
+}
+
+// Rebuild the display material of dirty images. Only the uniforms and the bound textures change,
+// the pixels are transformed by the shader.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn on_update_display(
+    mut commands: Commands,
+    dirty_query: Query<
+        (
+            Entity,
+            &Id,
+            &SourceImage,
+            &ImageView,
+            &Scale,
+            &Rotation,
+            &MeshMaterial2d<DisplayMaterial>,
+            &ToneOverride,
+            &ImageColorSpace,
+            &ValueRange,
+            Option<&LevelRange>,
+        ),
+        (With<MyImage>, With<DisplayDirty>),
+    >,
+    view_query: Query<(&Id, &SourceImage, &ImageView, &Scale, &Rotation), With<MyImage>>,
+    level_query: Query<&LevelRange>,
+    mut materials: ResMut<Assets<DisplayMaterial>>,
+    config: Res<Config>,
+    difference_state: Res<DifferenceState>,
+    image_order: Res<ImageOrder>,
+    channel_state: Res<ChannelState>,
+    colormap_state: Res<ColormapState>,
+    levels_state: Res<LevelsState>,
+    highlight_state: Res<HighlightState>,
+) {
+    for (entity, id, source, view, scale, rotation, material, tone_override, color_space, value_range, level_range) in
+        &dirty_query
+    {
+        // The material is built once the texture is uploaded
+        if view.image == Handle::default() {
+            continue;
+        }
+        let reference = image_order.0.get(difference_state.reference).and_then(|reference_id| {
+            view_query
+                .iter()
+                .find(|(view_id, _, view, ..)| view_id.0 == *reference_id && view.image != Handle::default())
+        });
+        let (difference, reference_texture) = match reference {
+            Some((reference_id, reference, reference_view, reference_scale, reference_rotation))
+                if difference_state.enabled && id.0 != reference_id.0 =>
+            {
+                (
+                    Some(display::Difference {
+                        reference: reference.0.clone(),
+                        mapping: display::ReferenceMapping::between(
+                            cell_view(view, scale, rotation),
+                            cell_view(reference_view, reference_scale, reference_rotation),
+                        ),
+                        amplification: difference_state.amplification,
+                        heatmap: difference_state.heatmap,
+                    }),
+                    Some(reference_view.image.clone()),
+                )
+            }
+            _ => (None, None),
+        };
+        let linear = display::is_linear(&source.0);
+        let settings = DisplaySettings {
+            tone: tone_override.0.unwrap_or(config.hdr.tone),
+            channels: channel_state.0,
+            ignore_alpha: config.alpha.ignore_alpha,
+            colormap: colormap_settings(&colormap_state, value_range),
+            levels: applied_levels(&levels_state, level_range, &level_query),
+            highlight: highlight_state.0,
+            color_space: Some(color_space.active()).filter(|space| !space.is_native(linear)),
+            difference,
+        };
+
+        let mut params = DisplayParams::new(&settings, linear, display::stored_unit(&source.0));
+        params.opacity = view.color.alpha();
+        params.bilinear = (config.misc.sampler_mode == SamplerMode::Bilinear) as u32;
+        let display_material = DisplayMaterial {
+            params,
+            image: view.image.clone(),
+            reference: reference_texture,
+            icc_lut: color_space.icc_lut.clone(),
+        };
+        if materials.insert(&material.0, display_material).is_err() {
+            println!("Failed to update the display of image {}", id.0 + 1);
+        }
+        commands
+            .entity(entity)
+            .remove::<DisplayDirty>()
+            .insert(AppliedDisplay(settings));
+    }
+}
+
+// How an image is shown in its cell. The global zoom and rotation apply to every cell,
+// they don't change where the images are compared.
+fn cell_view(view: &ImageView, scale: &Scale, rotation: &Rotation) -> display::CellView {
+    display::CellView {
+        anchor: view.anchor.to_array(),
+        scale: scale.0,
+        turns: rotation.0,
+    }
+}
+
+// Compute the level range of the decoded images when the levels normalization is enabled
+#[allow(clippy::type_complexity)]
+fn on_request_levels(
+    mut commands: Commands,
+    image_query: Query<(Entity, &SourceImage, Option<&LevelRange>), (With<MyImage>, Without<LevelsTask>)>,
+    levels_state: Res<LevelsState>,
+) {
+    if levels_state.mode == LevelsMode::Off {
+        return;
+    }
+    for (entity, source, level_range) in &image_query {
+        if level_range.is_some_and(|level_range| level_range.percentile == levels_state.percentile) {
+            continue;
+        }
+        let source = source.0.clone();
+        let percentile = levels_state.percentile;
+        let task = AsyncComputeTaskPool::get().spawn(async move { display::level_range(&source, percentile) });
+        commands.entity(entity).insert(LevelsTask(task, percentile));
+    }
+}
+
+fn on_levels_computed(
+    mut commands: Commands,
+    mut task_query: Query<(Entity, &SourceImage, &mut LevelsTask), With<MyImage>>,
+    levels_state: Res<LevelsState>,
+    mut refresh_display_evw: MessageWriter<RefreshDisplayEvent>,
+) {
+    let mut finished = false;
+    let mut pending = false;
+    for (entity, source, mut task) in &mut task_query {
+        let Some(range) = check_ready(&mut task.0) else {
+            pending = true;
+            continue;
+        };
+        let level_range = LevelRange {
+            percentile: task.1,
+            range,
+            unit: display::stored_unit(&source.0),
+        };
+        commands.entity(entity).remove::<LevelsTask>().insert(level_range);
+        finished = true;
+        if levels_state.mode != LevelsMode::Joint {
+            commands.entity(entity).insert(DisplayDirty);
+        }
+    }
+    // The joint range covers every image, they all change once the last range is known
+    if levels_state.mode == LevelsMode::Joint && finished && !pending {
+        refresh_display_evw.write(RefreshDisplayEvent);
+    }
+}
+
+// Count the pixels with a numeric problem once per image or frame, when the highlight is enabled
+#[allow(clippy::type_complexity)]
+fn on_request_problem_counts(
+    mut commands: Commands,
+    image_query: Query<(Entity, &SourceImage), (With<MyImage>, Without<ProblemCounts>, Without<ProblemsTask>)>,
+    highlight_state: Res<HighlightState>,
+) {
+    if !highlight_state.0 {
+        return;
+    }
+    for (entity, source) in &image_query {
+        let source = source.0.clone();
+        let task = AsyncComputeTaskPool::get().spawn(async move { display::count_problems(&source) });
+        commands.entity(entity).insert(ProblemsTask(task));
+    }
+}
+
+fn on_problem_counts_computed(
+    mut commands: Commands,
+    mut task_query: Query<(Entity, &SourceImage, &mut ProblemsTask), With<MyImage>>,
+) {
+    for (entity, source, mut task) in &mut task_query {
+        let Some(counts) = check_ready(&mut task.0) else {
+            continue;
+        };
+        let problem_counts = ProblemCounts {
+            counts,
+            linear: display::is_linear(&source.0),
+        };
+        commands.entity(entity).remove::<ProblemsTask>().insert(problem_counts);
+    }
+}
+
+// Range stretched to the display range for an image, None when disabled or not computed yet
+fn applied_levels(
+    levels_state: &LevelsState,
+    level_range: Option<&LevelRange>,
+    level_query: &Query<&LevelRange>,
+) -> Option<(f32, f32)> {
+    match levels_state.mode {
+        LevelsMode::Off => None,
+        LevelsMode::PerImage => level_range?.range,
+        LevelsMode::Joint => level_query
+            .iter()
+            .filter(|level_range| level_range.percentile == levels_state.percentile)
+            .filter_map(|level_range| level_range.range)
+            .reduce(|a, b| (a.0.min(b.0), a.1.max(b.1))),
+    }
+}
+
+// Colormap of an image, None when disabled or when the image isn't single-channel
+fn colormap_settings(colormap_state: &ColormapState, value_range: &ValueRange) -> Option<display::ColormapSettings> {
+    let colormap = colormap_state.colormap?;
+    let (min, max) = match value_range.0 {
+        Some(range) if colormap_state.auto_range => range,
+        Some(_) => (colormap_state.min, colormap_state.max),
+        None => return None,
+    };
+    Some(display::ColormapSettings { colormap, min, max })
+}
+
+// Upload the built textures, their materials are rebuilt around them.
+// The first upload of an image completes its loading.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn on_texture_built(
+    mut commands: Commands,
+    mut task_query: Query<
+        (
+            Entity,
+            &Id,
+            &ImagePath,
+            &SourceImage,
+            &mut ImageView,
+            &mut TextureTask,
+            Has<TextureOutdated>,
+        ),
+        With<MyImage>,
+    >,
     mut images: ResMut<Assets<Image>>,
-    config: Res<Config>,
+    difference_state: Res<DifferenceState>,
+    image_order: Res<ImageOrder>,
+    mut loaded_evw: MessageWriter<NewImageLoadedEvent>,
+    mut refresh_display_evw: MessageWriter<RefreshDisplayEvent>,
 ) {
-    for ev in load_evr.read() {
-        let Some(f) = File::open(&ev.path).ok() else {
-            println!("Failed to open file: {}", ev.path);
-            continue;
-        };
-        let Some(format) = ImageFormat::from_path(&ev.path).ok() else {
-            println!("Failed to deduce image format from path: {}", ev.path);
+    for (entity, id, path, source, mut view, mut task, outdated) in &mut task_query {
+        let Some(texture) = check_ready(&mut task.0) else {
             continue;
         };
+        // Frames changed during the upload are skipped, only the latest one is uploaded next
+        if outdated {
+            commands
+                .entity(entity)
+                .remove::<TextureOutdated>()
+                .insert((upload_task(source), DisplayDirty));
+        } else {
+            commands.entity(entity).remove::<TextureTask>().insert(DisplayDirty);
+        }
 
-        let buf = BufReader::new(f);
-        let mut reader = image::ImageReader::with_format(buf, format);
+        if view.image == Handle::default() {
+            view.image = images.add(texture);
+            loaded_evw.write(NewImageLoadedEvent {
+                path: path.0.clone(),
+                index: id.0,
+            });
+        } else if images.insert(&view.image, texture).is_err() {
+            println!("Failed to update texture of image: {}", path.0);
+        } else {
+            // The size may have changed with the layer, the mesh is fitted again
+            view.set_changed();
+        }
 
-        // Remove the memory limit on image size we can read
-        // This is required to process large images that would otherwise be rejected by the image crate
-        reader.no_limits();
+        // Other cells are compared against the reference, they must be rebuilt with its new texture
+        if difference_state.enabled && image_order.0.get(difference_state.reference) == Some(&id.0) {
+            refresh_display_evw.write(RefreshDisplayEvent);
+        }
+    }
+}
 
-        let Some(image) = reader.decode().ok() else {
-            println!("Failed to decode image: {}", ev.path);
+// Fit the mesh of each image to its visible part, and its material to the opacity.
+// In the difference mode the images are compared where they're shown, every material follows the views.
+#[allow(clippy::type_complexity)]
+fn on_update_image_mesh(
+    mut commands: Commands,
+    image_query: Query<(&ImageView, &Mesh2d, &MeshMaterial2d<DisplayMaterial>), (With<MyImage>, Changed<ImageView>)>,
+    all_images: Query<Entity, With<MyImage>>,
+    images: Res<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<DisplayMaterial>>,
+    difference_state: Res<DifferenceState>,
+) {
+    if difference_state.enabled && !image_query.is_empty() {
+        for entity in &all_images {
+            commands.entity(entity).insert(DisplayDirty);
+        }
+    }
+    for (view, mesh, material) in &image_query {
+        if view.image == Handle::default() {
+            continue;
+        }
+        let Some(texture) = images.get(&view.image) else {
             continue;
         };
+        let size = texture.size().as_vec2();
+        let crop = view.rect.unwrap_or(Rect::from_corners(Vec2::ZERO, size));
+
+        // Centered like a sprite, showing the crop of the texture
+        let (min, max) = (crop.min / size, crop.max / size);
+        let quad = Mesh::from(Rectangle::from_size(crop.size())).with_inserted_attribute(
+            Mesh::ATTRIBUTE_UV_0,
+            vec![[max.x, min.y], [min.x, min.y], [min.x, max.y], [max.x, max.y]],
+        );
+        if meshes.insert(&mesh.0, quad).is_err() {
+            println!("Failed to update the mesh of an image");
+        }
 
-        let loaded_image = match image.color() {
-            ColorType::Rgb8 | ColorType::Rgba8 | ColorType::L8 | ColorType::La8 => Image::from_dynamic(
-                image,
-                true,
-                RenderAssetUsages::RENDER_WORLD | RenderAssetUsages::MAIN_WORLD,
-            ),
-            ColorType::Rgb16 | ColorType::Rgba16 => Image::from_dynamic(
-                image,
-                true,
-                RenderAssetUsages::RENDER_WORLD | RenderAssetUsages::MAIN_WORLD,
-            ),
-            ColorType::L16 => {
-                let image_rgb16 = DynamicImage::ImageRgb16(image.into_rgb16());
-                Image::from_dynamic(
-                    image_rgb16,
-                    true,
-                    RenderAssetUsages::RENDER_WORLD | RenderAssetUsages::MAIN_WORLD,
-                )
-            }
-            _ => {
-                println!("Unsupported image type : image.color(): {:?}", image.color());
-                continue;
+        let opacity = view.color.alpha();
+        if materials
+            .get(&material.0)
+            .is_some_and(|material| material.params.opacity != opacity)
+        {
+            if let Some(material) = materials.get_mut(&material.0) {
+                material.params.opacity = opacity;
             }
-        };
-        let mut loaded_image = loaded_image;
-        loaded_image.sampler = match config.misc.sampler_mode {
-            SamplerMode::Nearest => ImageSampler::Descriptor(ImageSamplerDescriptor::nearest()),
-            SamplerMode::Bilinear => ImageSampler::Descriptor(ImageSamplerDescriptor::linear()),
-        };
-        let handle = images.add(loaded_image);
-        loaded_evw.write(NewImageLoadedEvent {
-            handle: handle,
-            path: ev.path.clone(),
-            index: ev.index,
-        });
+        }
     }
 }
 
 fn on_image_loaded(
-    config: Res<Config>,
     mut load_image_evr: MessageReader<NewImageLoadedEvent>,
-    mut commands: Commands,
-    images: Query<Entity, With<Id>>,
-    mut help_query: Query<&mut Visibility, With<MyHelp>>,
-    font_query: Query<&FontHandle>,
-    mut is_new_batch: ResMut<NewImageBatch>,
-    mut image_order: ResMut<ImageOrder>,
+    mut text_query: Query<(&Id, &mut Text), With<MyText>>,
+    image_query: Query<(&Id, Option<&Layers>, &ImageColorSpace), With<MyImage>>,
+    mut fit_to_screen_evw: MessageWriter<FitToScreen>,
+    mut metrics_state: ResMut<MetricsState>,
 ) {
     for ev in load_image_evr.read() {
-        let font = font_query.single().unwrap();
+        // The image may replace the one the metrics were computed on
+        metrics_state.invalidate();
 
-        if is_new_batch.0 {
-            for entity in &images {
-                commands.entity(entity).despawn();
+        // Drop the loading placeholder from the title
+        let Some((_, layers, color_space)) = image_query.iter().find(|(id, ..)| id.0 == ev.index) else {
+            continue;
+        };
+        let title = cell_title(&ev.path, layers, color_space);
+        for (id, mut text) in &mut text_query {
+            if id.0 == ev.index {
+                text.0 = title.clone();
             }
-            image_order.0.clear();
-            is_new_batch.0 = false;
         }
 
-        // Start hidden; on_move_image will make it visible after positioning
-        commands.spawn((
-            Sprite {
-                image: ev.handle.clone(),
-                ..default()
-            },
-            Visibility::Hidden,
-            Id(ev.index),
-            Scale(1.),
-            Position(Vec2::ZERO),
-            Rotation(0),
-            ImagePath(ev.path.clone()),
-            MyImage,
-        ));
+        fit_to_screen_evw.write(FitToScreen);
+    }
+}
 
-        let short_path = get_short_name(&ev.path).unwrap_or("");
-        commands.spawn((
-            Text::new(short_path),
-            TextFont {
-                font: font.0.clone(),
-                font_size: config.text.font_size,
-                ..default()
-            },
-            TextColor(config.text.font_color),
-            TextLayout {
-                justify: Justify::Left,
-                ..default()
-            },
-            Node {
-                position_type: PositionType::Absolute,
-                ..default()
-            },
-            Visibility::Hidden,
-            Id(ev.index),
-            MyText,
-        ));
+// Decode another layer in the cell, the previous one stays on screen until it's ready,
+// and stays selected when the new one can't be decoded (see on_image_decoded)
+fn on_change_layer(
+    mut change_layer_evr: MessageReader<ChangeLayerEvent>,
+    mut commands: Commands,
+    image_query: Query<(Entity, &Id, &ImagePath, &Layers), With<MyImage>>,
+) {
+    for ev in change_layer_evr.read() {
+        let Some((entity, _, path, layers)) = image_query.iter().find(|(_, id, ..)| id.0 == ev.id) else {
+            continue;
+        };
+        if ev.layer >= layers.names.len() {
+            continue;
+        }
 
-        // Track the new image in display order
-        image_order.0.push(ev.index);
+        let path = path.0.clone();
+        let layer = ev.layer;
+        let task = AsyncComputeTaskPool::get().spawn(async move { decode_cell(&path, layer) });
+        commands.entity(entity).insert(DecodeTask(task));
+    }
+}
 
-        let mut help_visibility = help_query.single_mut().unwrap();
-        *help_visibility = Visibility::Hidden;
+// Assign a color space to an image (or go back to its embedded profile) and rebuild its texture
+#[allow(clippy::type_complexity)]
+fn on_change_color_space(
+    mut change_color_space_evr: MessageReader<ChangeColorSpaceEvent>,
+    mut commands: Commands,
+    mut image_query: Query<(Entity, &Id, &ImagePath, Option<&Layers>, &mut ImageColorSpace), With<MyImage>>,
+    mut text_query: Query<(&Id, &mut Text), With<MyText>>,
+) {
+    for ev in change_color_space_evr.read() {
+        let Some((entity, _, path, layers, mut color_space)) = image_query.iter_mut().find(|(_, id, ..)| id.0 == ev.id)
+        else {
+            continue;
+        };
+        color_space.assigned = ev.assigned;
+        let title = cell_title(&path.0, layers, &color_space);
+        for (_, mut text) in text_query.iter_mut().filter(|(id, _)| id.0 == ev.id) {
+            text.0 = title.clone();
+        }
+        commands.entity(entity).insert(DisplayDirty);
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn on_move_image(
     mut move_image_evr: MessageReader<MoveImageEvent>,
     windows: Query<&Window>,
     assets: Res<Assets<Image>>,
     mut sprite_position: Query<
-        (&Id, &Position, &Scale, &Rotation, &mut Transform, &mut Sprite, &mut Visibility),
+        (
+            &Id,
+            &Position,
+            &Scale,
+            &Rotation,
+            &mut Transform,
+            &mut ImageView,
+            &mut Visibility,
+        ),
         With<MyImage>,
     >,
     global_scale: Res<GlobalScale>,
@@ -1184,6 +3726,8 @@ fn on_move_image(
     layout_state: Res<GridLayoutState>,
     mouse_state: Res<MouseState>,
     mut title_query: Query<&mut Node, With<MyText>>,
+    mut divider_query: Query<(&mut Transform, &mut Visibility), (With<MyDivider>, Without<MyImage>)>,
+    compare_state: Res<CompareState>,
     config: Res<Config>,
 ) {
     if move_image_evr.is_empty() {
@@ -1226,18 +3770,39 @@ fn on_move_image(
             (rotated_cell_size - 2.) / (scale.0 * global_scale.0),
         );
 
-        sprite.rect = Some(cell.intersect(image_crop));
+        let crop = cell.intersect(image_crop);
+        sprite.rect = Some(crop);
+        sprite.anchor = crop.center();
 
         // Make visible after positioning (sprites start hidden to avoid flash at native resolution)
-        *visibility = match layout_state.layout {
-            GridLayout::Stack => {
-                if id.0 == layout_state.index {
-                    Visibility::Visible
-                } else {
-                    Visibility::Hidden
-                }
-            }
-            _ => Visibility::Visible,
+        let mut visible = is_cell_visible(id.0, &layout_state, &compare_state);
+
+        // In the Wipe layout each image only keeps its side of the divider
+        if layout_state.layout == GridLayout::Wipe && visible && compare_state.second != layout_state.index {
+            visible = clip_sprite_to_divider(
+                &mut transform,
+                &mut sprite,
+                rotation_total,
+                wipe_divider_world(&compare_state, window),
+                compare_state.vertical,
+                id.0 == layout_state.index,
+            );
+        }
+
+        // In the Blend layout the second image is drawn over the first one with transparency
+        let blended = layout_state.layout == GridLayout::Blend
+            && id.0 == compare_state.second
+            && compare_state.second != layout_state.index;
+        transform.translation.z = if blended { 0.1 } else { 0. };
+        sprite.color = if blended {
+            Color::WHITE.with_alpha(compare_state.opacity)
+        } else {
+            Color::WHITE
+        };
+        *visibility = if visible {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
         };
     }
 
@@ -1245,6 +3810,329 @@ fn on_move_image(
     for mut node in &mut title_query {
         node.width = Val::Px(cell_size.x);
     }
+
+    let (mut divider_transform, mut divider_visibility) = divider_query.single_mut().unwrap();
+    if layout_state.layout == GridLayout::Wipe {
+        let divider = wipe_divider_world(&compare_state, window);
+        *divider_transform = if compare_state.vertical {
+            Transform::from_xyz(divider, 0., 0.5).with_scale(Vec3::new(2., window.height(), 1.))
+        } else {
+            Transform::from_xyz(0., divider, 0.5).with_scale(Vec3::new(window.width(), 2., 1.))
+        };
+        *divider_visibility = Visibility::Visible;
+    } else {
+        *divider_visibility = Visibility::Hidden;
+    }
+}
+
+// Whether the cell of the given image is shown with the current layout
+fn is_cell_visible(id: usize, layout_state: &GridLayoutState, compare_state: &CompareState) -> bool {
+    match layout_state.layout {
+        GridLayout::Stack => id == layout_state.index,
+        GridLayout::Wipe | GridLayout::Blend => id == layout_state.index || id == compare_state.second,
+        _ => true,
+    }
+}
+
+// Position of the wipe divider in world coordinates (x if vertical, y otherwise)
+fn wipe_divider_world(compare_state: &CompareState, window: &Window) -> f32 {
+    if compare_state.vertical {
+        (compare_state.divider - 0.5) * window.width()
+    } else {
+        (0.5 - compare_state.divider) * window.height()
+    }
+}
+
+// Keep the part of a positioned sprite that is on one side of the divider.
+// `before` keeps the left (or top) part. Returns false if nothing is left to show.
+fn clip_sprite_to_divider(
+    transform: &mut Transform,
+    sprite: &mut ImageView,
+    rotation_total: i32,
+    divider: f32,
+    vertical: bool,
+    before: bool,
+) -> bool {
+    let Some(crop) = sprite.rect else {
+        return false;
+    };
+    let center = transform.translation.truncate();
+    let scale = transform.scale.x;
+
+    // Area covered by the sprite on screen
+    let crop_size = if rotation_total % 2 == 0 {
+        crop.size()
+    } else {
+        Vec2::new(crop.size().y, crop.size().x)
+    };
+    let mut screen = Rect::from_center_size(center, crop_size * scale);
+    match (vertical, before) {
+        (true, true) => screen.max.x = screen.max.x.min(divider),
+        (true, false) => screen.min.x = screen.min.x.max(divider),
+        // World y goes up, the top part is above the divider
+        (false, true) => screen.min.y = screen.min.y.max(divider),
+        (false, false) => screen.max.y = screen.max.y.min(divider),
+    }
+    if screen.is_empty() {
+        return false;
+    }
+
+    // Map the remaining screen area back to texture coordinates
+    sprite.rect = Some(Rect::from_corners(
+        world_to_texture(transform, crop, screen.min),
+        world_to_texture(transform, crop, screen.max),
+    ));
+    transform.translation = screen.center().extend(transform.translation.z);
+    true
+}
+
+// Map a world position to the texture coordinates of a positioned sprite showing `crop`
+fn world_to_texture(transform: &Transform, crop: Rect, point: Vec2) -> Vec2 {
+    let local = transform.compute_affine().inverse().transform_point3(point.extend(0.));
+    crop.center() + Vec2::new(local.x, -local.y)
+}
+
+// Inverse of world_to_texture
+fn texture_to_world(transform: &Transform, crop: Rect, texel: Vec2) -> Vec2 {
+    let local = texel - crop.center();
+    transform.transform_point(Vec3::new(local.x, -local.y, 0.)).truncate()
+}
+
+// Whole image, or the part visible in its cell
+fn image_region(image: &DynamicImage, sprite: &ImageView, crop_only: bool) -> metrics::Region {
+    match sprite.rect {
+        Some(rect) if crop_only => metrics::Region {
+            x: rect.min.x.floor().max(0.) as u32,
+            y: rect.min.y.floor().max(0.) as u32,
+            width: (rect.max.x.ceil() - rect.min.x.floor()).max(0.) as u32,
+            height: (rect.max.y.ceil() - rect.min.y.floor()).max(0.) as u32,
+        },
+        _ => metrics::Region::full(image),
+    }
+}
+
+// Start computing the metrics of every decoded image against the reference.
+// A new request replaces (and cancels) the one still running.
+fn compute_metrics(
+    mut compute_metrics_evr: MessageReader<ComputeMetricsEvent>,
+    mut metrics_state: ResMut<MetricsState>,
+    image_query: Query<(&Id, &ImagePath, &ImageView, &SourceImage), With<MyImage>>,
+    image_order: Res<ImageOrder>,
+) {
+    if compute_metrics_evr.is_empty() {
+        return;
+    }
+    compute_metrics_evr.clear();
+
+    let Some((_, _, _, reference)) = image_query.iter().find(|(id, ..)| id.0 == metrics_state.reference) else {
+        println!("Reference image {} is not loaded", metrics_state.reference + 1);
+        return;
+    };
+    let reference = reference.0.clone();
+
+    // Compared images in display order
+    let mut jobs = Vec::new();
+    for image_id in &image_order.0 {
+        let Some((id, path, sprite, source)) = image_query.iter().find(|(id, ..)| id.0 == *image_id) else {
+            continue;
+        };
+        if id.0 == metrics_state.reference {
+            continue;
+        }
+        let region = image_region(&source.0, sprite, metrics_state.crop_only);
+        let name = format!("{} {}", id.0 + 1, get_short_name(&path.0).unwrap_or("?"));
+        jobs.push((name, source.0.clone(), region));
+    }
+
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        jobs.into_iter()
+            .map(|(name, source, region)| metrics::MetricsRow {
+                name,
+                metrics: metrics::compare(&source, &reference, region),
+                size_mismatch: (source.width(), source.height()) != (reference.width(), reference.height()),
+            })
+            .collect()
+    });
+    metrics_state.task = Some(task);
+}
+
+fn poll_metrics(mut metrics_state: ResMut<MetricsState>) {
+    let Some(task) = &mut metrics_state.task else {
+        return;
+    };
+    if let Some(rows) = check_ready(task) {
+        metrics_state.rows = rows;
+        metrics_state.task = None;
+    }
+}
+
+// Delay of the histograms after the last move of the visible crops or change of an image,
+// a pan, a zoom or a playing animation changes them every frame
+const HISTOGRAM_MOVE_DELAY: Duration = Duration::from_millis(200);
+
+// Recompute the histograms when the selection changes, an image is decoded or the visible crop moves
+fn compute_histograms(
+    mut move_image_evr: MessageReader<MoveImageEvent>,
+    mut histogram_state: ResMut<HistogramState>,
+    image_query: Query<(&Id, &ImageView, &SourceImage), With<MyImage>>,
+    changed_query: Query<(), Changed<SourceImage>>,
+    ui_state: Res<UiState>,
+    mut was_visible: Local<bool>,
+    mut moved_at: Local<Option<Instant>>,
+) {
+    // Decoded images, layers and frames replace the source, a playing animation does it every frame
+    if (!move_image_evr.is_empty() && histogram_state.crop_only) || !changed_query.is_empty() {
+        *moved_at = Some(Instant::now());
+    }
+    move_image_evr.clear();
+    let visible = ui_state.visible && ui_state.histogram_visible;
+    let opened = visible && !*was_visible;
+    *was_visible = visible;
+    if !visible {
+        return;
+    }
+    let moved = moved_at.is_some_and(|moved_at| moved_at.elapsed() >= HISTOGRAM_MOVE_DELAY);
+    if !(opened || histogram_state.dirty || moved) {
+        return;
+    }
+    histogram_state.dirty = false;
+    *moved_at = None;
+
+    let mut jobs = Vec::new();
+    for image_id in &histogram_state.images {
+        if let Some((id, sprite, source)) = image_query.iter().find(|(id, ..)| id.0 == *image_id) {
+            let region = image_region(&source.0, sprite, histogram_state.crop_only);
+            jobs.push((id.0, source.0.clone(), region));
+        }
+    }
+
+    // Overlaid histograms share the same bins
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        let regions: Vec<_> = jobs
+            .into_iter()
+            .map(|(id, source, region)| (id, histogram::RegionValues::new(&source, region)))
+            .collect();
+        let mut range = (f32::INFINITY, f32::NEG_INFINITY);
+        for (_, region) in &regions {
+            let (min, max) = histogram::value_range(region);
+            range = (range.0.min(min), range.1.max(max));
+        }
+        regions
+            .iter()
+            .map(|(id, region)| (*id, histogram::compute(region, range, 256)))
+            .collect()
+    });
+    histogram_state.task = Some(task);
+}
+
+fn poll_histograms(mut histogram_state: ResMut<HistogramState>) {
+    let Some(task) = &mut histogram_state.task else {
+        return;
+    };
+    if let Some(histograms) = check_ready(task) {
+        histogram_state.histograms = histograms;
+        histogram_state.task = None;
+    }
+}
+
+// Size of a check of the Checkerboard background on screen, in pixels
+const CHECKER_SIZE: f32 = 8.;
+
+// Fit the background of each image to its visible area
+#[allow(clippy::type_complexity)]
+fn on_update_alpha_background(
+    config: Res<Config>,
+    image_query: Query<(&Id, &Transform, &ImageView, &Children), (With<MyImage>, Without<MyBackground>)>,
+    mut background_query: Query<(&mut Sprite, &mut Visibility), With<MyBackground>>,
+    checker_query: Query<&CheckerHandle>,
+    layout_state: Res<GridLayoutState>,
+    compare_state: Res<CompareState>,
+) {
+    let checker = checker_query.single().unwrap();
+    for (id, transform, sprite, children) in &image_query {
+        // The second image of the Blend layout is drawn over the first one, not over a background
+        let blended = layout_state.layout == GridLayout::Blend
+            && id.0 == compare_state.second
+            && compare_state.second != layout_state.index;
+        for child in children.iter() {
+            let Ok((mut background, mut visibility)) = background_query.get_mut(child) else {
+                continue;
+            };
+            let crop = match sprite.rect {
+                Some(crop) if !blended && config.alpha.background != AlphaBackground::None => crop,
+                _ => {
+                    *visibility = Visibility::Hidden;
+                    continue;
+                }
+            };
+            *visibility = Visibility::Inherited;
+            background.custom_size = Some(crop.size());
+            if config.alpha.background == AlphaBackground::Checkerboard {
+                // Checks keep the same size on screen whatever the zoom
+                background.image = checker.0.clone();
+                background.color = Color::WHITE;
+                background.rect = Some(Rect::from_corners(
+                    Vec2::ZERO,
+                    crop.size() * transform.scale.x / CHECKER_SIZE,
+                ));
+            } else {
+                background.image = Handle::default();
+                background.color = config.alpha.color;
+                background.rect = None;
+            }
+        }
+    }
+}
+
+// Show the raw values of the pixel under each cursor
+#[allow(clippy::type_complexity)]
+fn on_update_pixel_values(
+    cursor_query: Query<(&Id, &Transform, &Children), (With<MyCursor>, Without<MyImage>)>,
+    image_query: Query<(&Id, &Transform, &ImageView, Option<&SourceImage>), With<MyImage>>,
+    mut value_query: Query<
+        (&mut Text2d, &mut Anchor, &mut Transform),
+        (With<MyPixelValue>, Without<MyCursor>, Without<MyImage>),
+    >,
+    layout_state: Res<GridLayoutState>,
+    compare_state: Res<CompareState>,
+) {
+    for (id, cursor_transform, children) in &cursor_query {
+        let mut value = String::new();
+        if is_cell_visible(id.0, &layout_state, &compare_state) {
+            if let Some((_, transform, sprite, Some(source))) =
+                image_query.iter().find(|(image_id, ..)| image_id.0 == id.0)
+            {
+                if let Some(crop) = sprite.rect {
+                    let texel = world_to_texture(transform, crop, cursor_transform.translation.truncate()).floor();
+                    if texel.x >= 0. && texel.y >= 0. {
+                        value = inspect::describe_pixel(&source.0, texel.x as u32, texel.y as u32).unwrap_or_default();
+                    }
+                }
+            }
+        }
+
+        // Both images of Wipe and Blend share the cursor position, the second one shows its values on the left
+        let on_left = matches!(layout_state.layout, GridLayout::Wipe | GridLayout::Blend)
+            && id.0 == compare_state.second
+            && id.0 != layout_state.index;
+        for child in children.iter() {
+            let Ok((mut text, mut anchor, mut transform)) = value_query.get_mut(child) else {
+                continue;
+            };
+            if text.0 != value {
+                text.0 = value.clone();
+            }
+            let (new_anchor, sign) = if on_left {
+                (Anchor::TOP_RIGHT, -1.)
+            } else {
+                (Anchor::TOP_LEFT, 1.)
+            };
+            if *anchor != new_anchor {
+                *anchor = new_anchor;
+                transform.translation.x = sign * transform.translation.x.abs();
+            }
+        }
+    }
 }
 
 fn on_move_image_title(
@@ -1252,6 +4140,7 @@ fn on_move_image_title(
     windows: Query<&Window>,
     mut text_query: Query<(&Id, &mut Node, &mut Visibility), With<MyText>>,
     layout_state: Res<GridLayoutState>,
+    compare_state: Res<CompareState>,
     config: Res<Config>,
 ) {
     if move_image_evr.is_empty() {
@@ -1263,18 +4152,23 @@ fn on_move_image_title(
     let window = windows.single().unwrap();
 
     for (id, mut node, mut visibility) in &mut text_query {
-        let (cell_offset, _) = get_cell_rect(id.0, num_images, &layout_state.layout, window, config.misc.grid_width);
+        let (mut cell_offset, _) =
+            get_cell_rect(id.0, num_images, &layout_state.layout, window, config.misc.grid_width);
+        // The second image of the Wipe layout starts after the divider, in Blend its title goes below
+        if id.0 == compare_state.second && id.0 != layout_state.index {
+            match layout_state.layout {
+                GridLayout::Wipe if compare_state.vertical => cell_offset.x += compare_state.divider * window.width(),
+                GridLayout::Wipe => cell_offset.y += compare_state.divider * window.height(),
+                GridLayout::Blend => cell_offset.y += config.text.font_size * 1.5,
+                _ => {}
+            }
+        }
         node.top = Val::Px(cell_offset.y + 2.);
         node.left = Val::Px(cell_offset.x + 5.);
-        *visibility = match layout_state.layout {
-            GridLayout::Stack => {
-                if id.0 == layout_state.index {
-                    Visibility::Visible
-                } else {
-                    Visibility::Hidden
-                }
-            }
-            _ => Visibility::Visible,
+        *visibility = if is_cell_visible(id.0, &layout_state, &compare_state) {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
         };
     }
 }
@@ -1295,10 +4189,15 @@ fn change_image_title_style(
     }
 }
 
+// Place every cursor on the image pixel under the mouse.
+// The hovered position is mapped through each image transform so zoomed, panned or rotated cells agree.
+#[allow(clippy::type_complexity)]
 fn on_move_cursor(
     windows: Query<&Window>,
-    mut cursor_query: Query<(&Id, &mut Transform), With<MyCursor>>,
+    mut cursor_query: Query<(&Id, &mut Transform, &mut Visibility), (With<MyCursor>, Without<MyImage>)>,
+    image_query: Query<(&Id, &Transform, &ImageView), With<MyImage>>,
     layout_state: Res<GridLayoutState>,
+    compare_state: Res<CompareState>,
     config: Res<Config>,
 ) {
     let num_images = cursor_query.iter().count();
@@ -1307,16 +4206,66 @@ fn on_move_cursor(
     let Some(cursor_position) = window.cursor_position() else {
         return;
     };
-    for (id, mut transform) in &mut cursor_query {
+    let to_world = |point: Vec2| Vec2::new(point.x - window.width() / 2., window.height() / 2. - point.y);
+    let cell_area = |id: usize| {
         let (cell_offset, cell_size) =
-            get_cell_rect(id.0, num_images, &layout_state.layout, window, config.misc.grid_width);
-        let new_y = cell_offset.y + f32::rem_euclid(cursor_position.y, cell_size.y);
-        let new_x = cell_offset.x + f32::rem_euclid(cursor_position.x, cell_size.x);
-        transform.translation = Vec3::new(
-            -window.width() / 2. + new_x,
-            window.height() / 2. - new_y,
-            transform.translation.z,
-        );
+            get_cell_rect(id, num_images, &layout_state.layout, window, config.misc.grid_width);
+        Rect::from_corners(cell_offset, cell_offset + cell_size)
+    };
+
+    // Image under the mouse, in Wipe the side of the divider decides, in Blend it's the bottom image
+    let hovered_id = match layout_state.layout {
+        GridLayout::Stack | GridLayout::Blend => Some(layout_state.index),
+        GridLayout::Wipe => {
+            let before = if compare_state.vertical {
+                cursor_position.x < compare_state.divider * window.width()
+            } else {
+                cursor_position.y < compare_state.divider * window.height()
+            };
+            let hovered = if before {
+                layout_state.index
+            } else {
+                compare_state.second
+            };
+            Some(hovered)
+        }
+        _ => image_query
+            .iter()
+            .map(|(id, ..)| id.0)
+            .find(|id| cell_area(*id).contains(cursor_position)),
+    };
+    let texel = hovered_id.and_then(|hovered_id| {
+        let (_, transform, sprite) = image_query.iter().find(|(id, ..)| id.0 == hovered_id)?;
+        Some(world_to_texture(transform, sprite.rect?, to_world(cursor_position)))
+    });
+
+    for (id, mut transform, mut visibility) in &mut cursor_query {
+        let image = image_query.iter().find(|(image_id, ..)| image_id.0 == id.0);
+        let position = match (texel, image) {
+            (Some(texel), Some((_, image_transform, ImageView { rect: Some(crop), .. }))) => {
+                texture_to_world(image_transform, *crop, texel)
+            }
+            // Image not ready yet, keep the same offset within the cell
+            _ => {
+                let (cell_offset, cell_size) =
+                    get_cell_rect(id.0, num_images, &layout_state.layout, window, config.misc.grid_width);
+                let new_y = cell_offset.y + f32::rem_euclid(cursor_position.y, cell_size.y);
+                let new_x = cell_offset.x + f32::rem_euclid(cursor_position.x, cell_size.x);
+                to_world(Vec2::new(new_x, new_y))
+            }
+        };
+        transform.translation = position.extend(transform.translation.z);
+
+        // The pixel may be out of the cell when images are zoomed or panned differently
+        let in_cell = cell_area(id.0).contains(Vec2::new(
+            position.x + window.width() / 2.,
+            window.height() / 2. - position.y,
+        ));
+        *visibility = if in_cell && is_cell_visible(id.0, &layout_state, &compare_state) {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
     }
 }
 
@@ -1330,19 +4279,15 @@ fn on_reset_visibility(
     mut reset_evr: MessageReader<ResetVisibilityEvent>,
     mut visibility_query: Query<(&Id, &mut Visibility)>,
     layout_state: Res<GridLayoutState>,
-) {
-    for _ in reset_evr.read() {
-        for (i, mut visibility) in &mut visibility_query {
-            *visibility = match layout_state.layout {
-                GridLayout::Stack => {
-                    if i.0 == layout_state.index {
-                        Visibility::Visible
-                    } else {
-                        Visibility::Hidden
-                    }
-                }
-                _ => Visibility::Visible,
-            }
+    compare_state: Res<CompareState>,
+) {
+    for _ in reset_evr.read() {
+        for (i, mut visibility) in &mut visibility_query {
+            *visibility = if is_cell_visible(i.0, &layout_state, &compare_state) {
+                Visibility::Visible
+            } else {
+                Visibility::Hidden
+            };
         }
     }
 }
@@ -1359,7 +4304,9 @@ fn key_change_layout(
             GridLayout::Grid => GridLayout::Stack,
             GridLayout::Stack => GridLayout::Vertical,
             GridLayout::Vertical => GridLayout::Horizontal,
-            GridLayout::Horizontal => GridLayout::Grid,
+            GridLayout::Horizontal => GridLayout::Wipe,
+            GridLayout::Wipe => GridLayout::Blend,
+            GridLayout::Blend => GridLayout::Grid,
         };
         reset_vix_evw.write(ResetVisibilityEvent);
         move_image_evw.write(MoveImageEvent);
@@ -1391,6 +4338,9 @@ fn change_layout_on_click(
             GridLayout::Stack => GridLayout::Grid,
             GridLayout::Vertical => GridLayout::Horizontal,
             GridLayout::Horizontal => GridLayout::Vertical,
+            // Clicks in the Wipe layout are used to grab the divider, and drags in both comparisons
+            // move the images; they are switched with the selector of the bottom bar
+            GridLayout::Wipe | GridLayout::Blend => return,
         };
         reset_vix_evw.write(ResetVisibilityEvent);
         move_image_evw.write(MoveImageEvent);
@@ -1402,6 +4352,7 @@ fn change_top_image(
     mut move_image_evw: MessageWriter<MoveImageEvent>,
     mut visibility_query: Query<(&Id, &mut Visibility)>,
     mut layout_state: ResMut<GridLayoutState>,
+    compare_state: Res<CompareState>,
 ) {
     let ctrl_pressed = keys.pressed(KeyCode::ControlLeft) || keys.pressed(KeyCode::ControlRight);
     if ctrl_pressed {
@@ -1434,21 +4385,91 @@ fn change_top_image(
     layout_state.index -= 1;
 
     for (i, mut visibility) in &mut visibility_query {
-        *visibility = match layout_state.layout {
-            GridLayout::Stack => {
-                if i.0 == layout_state.index {
-                    Visibility::Visible
-                } else {
-                    Visibility::Hidden
-                }
-            }
-            _ => Visibility::Visible,
+        *visibility = if is_cell_visible(i.0, &layout_state, &compare_state) {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
         };
     }
 
     move_image_evw.write(MoveImageEvent);
 }
 
+fn key_move_divider(
+    keys: Res<ButtonInput<KeyCode>>,
+    layout_state: Res<GridLayoutState>,
+    mut compare_state: ResMut<CompareState>,
+    mut move_image_evw: MessageWriter<MoveImageEvent>,
+) {
+    if layout_state.layout != GridLayout::Wipe {
+        return;
+    }
+    let shift_pressed = keys.pressed(KeyCode::ShiftLeft) || keys.pressed(KeyCode::ShiftRight);
+    let step = if shift_pressed { 0.1 } else { 0.01 };
+    let direction = if keys.just_pressed(KeyCode::BracketLeft) {
+        -1.
+    } else if keys.just_pressed(KeyCode::BracketRight) {
+        1.
+    } else {
+        return;
+    };
+    compare_state.divider = (compare_state.divider + direction * step).clamp(0., 1.);
+    move_image_evw.write(MoveImageEvent);
+}
+
+fn key_step_frame(keys: Res<ButtonInput<KeyCode>>, mut playback: ResMut<PlaybackState>) {
+    if playback.frame_count == 0 {
+        return;
+    }
+    let shift_pressed = keys.pressed(KeyCode::ShiftLeft) || keys.pressed(KeyCode::ShiftRight);
+    let step = if shift_pressed { 10 } else { 1 };
+    let direction = if keys.just_pressed(KeyCode::ArrowLeft) {
+        -1
+    } else if keys.just_pressed(KeyCode::ArrowRight) {
+        1
+    } else {
+        return;
+    };
+    let frame_count = playback.frame_count;
+    playback.playback.playing = false;
+    playback.playback.step(direction * step, frame_count);
+}
+
+fn key_change_opacity(
+    keys: Res<ButtonInput<KeyCode>>,
+    layout_state: Res<GridLayoutState>,
+    mut compare_state: ResMut<CompareState>,
+    mut move_image_evw: MessageWriter<MoveImageEvent>,
+) {
+    if layout_state.layout != GridLayout::Blend {
+        return;
+    }
+    let shift_pressed = keys.pressed(KeyCode::ShiftLeft) || keys.pressed(KeyCode::ShiftRight);
+    let step = if shift_pressed { 0.25 } else { 0.05 };
+    let direction = if keys.just_pressed(KeyCode::Minus) {
+        -1.
+    } else if keys.just_pressed(KeyCode::Equal) {
+        1.
+    } else {
+        return;
+    };
+    compare_state.opacity = (compare_state.opacity + direction * step).clamp(0., 1.);
+    move_image_evw.write(MoveImageEvent);
+}
+
+fn key_switch_channel(
+    keys: Res<ButtonInput<KeyCode>>,
+    config: Res<Config>,
+    mut channel_state: ResMut<ChannelState>,
+    mut refresh_display_evw: MessageWriter<RefreshDisplayEvent>,
+) {
+    if keys.just_pressed(config.shortcut.switch_channel) {
+        let shift_pressed = keys.pressed(KeyCode::ShiftLeft) || keys.pressed(KeyCode::ShiftRight);
+        channel_state.0 = channel_state.0.cycle(if shift_pressed { -1 } else { 1 });
+        refresh_display_evw.write(RefreshDisplayEvent);
+    }
+}
+
 fn change_global_rotation(
     config: Res<Config>,
     keys: Res<ButtonInput<KeyCode>>,
@@ -1615,16 +4636,12 @@ fn get_cell_rect(
             let cell_size = Vec2::new(window.width(), step.y.abs());
             (index as f32 * step, cell_size)
         }
-        GridLayout::Stack => {
+        GridLayout::Stack | GridLayout::Wipe | GridLayout::Blend => {
             let cell_size = Vec2::new(window.width(), window.height());
             (Vec2::ZERO, cell_size)
         }
         GridLayout::Grid => {
-            let grid_width = if grid_width == 0 {
-                (num_images as f32).sqrt().ceil()
-            } else {
-                grid_width as f32
-            };
+            let grid_width = grid_columns(num_images, grid_width) as f32;
             let grid_height = (num_images as f32 / grid_width).ceil();
             let step = Vec2::new(window.width() / grid_width, window.height() / grid_height);
             let cell_size = step.abs();
@@ -1636,6 +4653,20 @@ fn get_cell_rect(
     (cell_tl, cell_size)
 }
 
+// Columns of the Grid layout, the square root of the image count when the grid width isn't set
+fn grid_columns(num_images: usize, grid_width: i32) -> usize {
+    if grid_width > 0 {
+        grid_width as usize
+    } else {
+        (num_images as f32).sqrt().ceil() as usize
+    }
+}
+
+// Shortcuts are ignored while a text field of the UI has the keyboard focus
+fn keyboard_available(egui_wants_input: Res<EguiWantsInput>) -> bool {
+    !egui_wants_input.wants_keyboard_input()
+}
+
 fn toggle_help(keys: Res<ButtonInput<KeyCode>>, mut ui_state: ResMut<UiState>) {
     if keys.just_pressed(KeyCode::KeyH) {
         ui_state.visible = !ui_state.visible;
@@ -1659,6 +4690,8 @@ fn toggle_cursor(
     cursor_query: Query<Entity, With<MyCursor>>,
     mut cursor_state: ResMut<MultiCursorEnabled>,
     image_query: Query<&Id, With<MyImage>>,
+    font_query: Query<&FontHandle>,
+    config: Res<Config>,
 ) {
     for _ev in toggle_evr.read() {
         if cursor_query.iter().count() == 0 {
@@ -1709,6 +4742,19 @@ fn toggle_cursor(
                             },
                             Transform::from_translation(Vec3::new(0., -bar_size, 1.)),
                         ));
+                        // Filled by on_update_pixel_values
+                        parent.spawn((
+                            Text2d::default(),
+                            TextFont {
+                                font: font_query.single().unwrap().0.clone(),
+                                font_size: config.text.font_size * 0.8,
+                                ..default()
+                            },
+                            TextColor(config.text.font_color),
+                            Anchor::TOP_LEFT,
+                            Transform::from_translation(Vec3::new(bar_size + 5., -bar_size - 5., 1.)),
+                            MyPixelValue,
+                        ));
                     });
             }
         } else {
@@ -1745,12 +4791,13 @@ fn on_image_spawned(
     fit_to_screen_evw.write(FitToScreen);
 }
 
+#[allow(clippy::too_many_arguments)]
 fn fit_to_screen(
     mut fit_to_screen_evr: MessageReader<FitToScreen>,
     windows: Query<&Window>,
     assets: Res<Assets<Image>>,
     mut global_scale: ResMut<GlobalScale>,
-    mut sprite_query: Query<(&Id, &Sprite, &mut Scale, &mut Position), With<MyImage>>,
+    mut sprite_query: Query<(&Id, &ImageView, &mut Scale, &mut Position), With<MyImage>>,
     layout_state: Res<GridLayoutState>,
     config: Res<Config>,
     mut move_image_evw: MessageWriter<MoveImageEvent>,
@@ -1811,50 +4858,65 @@ fn record_pressed_key(
     }
 }
 
-// insert a suffix to given filename
-fn insert_suffix(path: &Path, suffix: &str) -> Option<std::path::PathBuf> {
-    let parent = path.parent()?;
-    let filename = path.file_stem()?.to_str()?;
-    let extension = path.extension()?.to_str()?;
-    Some(parent.join(filename.to_owned() + suffix + "." + extension))
-}
-
+#[allow(clippy::type_complexity)]
 fn save_cropped(
     mut save_cropped_evr: MessageReader<SaveCropped>,
-    image_query: Query<(&ImagePath, &Sprite), With<MyImage>>,
+    image_query: Query<(&Id, &ImagePath, &ImageView, &SourceImage, Option<&AnimationFrames>), With<MyImage>>,
+    image_order: Res<ImageOrder>,
+    config: Res<Config>,
+    mut notice: ResMut<ExportNotice>,
 ) {
     for _ev in save_cropped_evr.read() {
-        for (path, sprite) in &image_query {
-            // Get Input image
+        let mut written = Vec::new();
+        let mut skipped = Vec::new();
+        for (id, path, sprite, source, frames) in &image_query {
+            // The image shown, the current frame for animations, in its decoded color type
             let input_path = Path::new(&path.0);
-            let Some(f_in) = File::open(&input_path).ok() else {
-                println!("Failed to open file {}", path.0);
+
+            // Get Roi from sprite
+            let Some(rect) = sprite.rect else {
+                println!("Failed to get ROI of the texture");
                 continue;
             };
-            let buf_in = BufReader::new(f_in);
-            let Some(format) = ImageFormat::from_path(&input_path).ok() else {
-                println!("Failed to deduce image format from path : {}", path.0);
+            let (min, size) = (rect.min.as_uvec2(), (rect.max - rect.min).as_uvec2());
+            let subimage = source.0.crop_imm(min.x, min.y, size.x, size.y);
+
+            // Get Output path, in the configured format and directory
+            let format = config.crop.format.output_format(input_path);
+            let extension = export::output_extension(input_path, format);
+            let Some(stem) = input_path.file_stem().and_then(|stem| stem.to_str()) else {
+                println!("Failed to create output filename");
                 continue;
             };
-
-            let mut reader = image::ImageReader::with_format(buf_in, format);
-
-            // Remove the memory limit on image size we can read
-            // This is required to process large images that would otherwise be rejected by the image crate
-            reader.no_limits();
-
-            let Some(image) = reader.decode().ok() else {
-                println!("Failed to decode image");
+            let Some(filename) = export::crop_filename(
+                &config.crop.template,
+                &export::CropName {
+                    stem,
+                    ext: &extension,
+                    x: min.x,
+                    y: min.y,
+                    w: size.x,
+                    h: size.y,
+                    index: image_order.0.iter().position(|slot| *slot == id.0).unwrap_or(id.0) + 1,
+                    frame: frames.map(|frames| frames.shown + 1),
+                },
+            ) else {
+                println!("Invalid crop file name template {}", config.crop.template);
                 continue;
             };
-            // reader don't preserve the input format and append an alpha channel
-            let image_rgb8 = image.to_rgb8();
-
-            // Get Output buffer
-            let Some(output_path) = insert_suffix(input_path, "_crop") else {
-                println!("Failed to create output filename");
+            let source_directory = input_path.parent().unwrap_or(Path::new(""));
+            let output_path = source_directory.join(&config.crop.directory).join(filename);
+            let Some(output_path) = config.crop.overwrite.resolve(output_path.clone()) else {
+                println!("Skipped existing file {}", output_path.display());
+                skipped.push(output_path.display().to_string());
                 continue;
             };
+            if let Some(parent) = output_path.parent() {
+                if let Err(e) = std::fs::create_dir_all(parent) {
+                    println!("Failed to create directory {}: {}", parent.display(), e);
+                    continue;
+                }
+            }
 
             let Some(f_out) = File::create(&output_path).ok() else {
                 println!("Failed to create file {}", &output_path.display());
@@ -1862,29 +4924,417 @@ fn save_cropped(
             };
             let mut buf_out = BufWriter::new(f_out);
 
-            // Get Roi from sprite
-            let Some(rect) = sprite.rect else {
-                println!("Failed to get ROI of the texture");
+            // Save to disk
+            if let Err(e) = export::write_image(&subimage, format, config.crop.jpeg_quality, &mut buf_out) {
+                println!("Failed to write data to file {}: {}", &output_path.display(), e);
                 continue;
             };
+            written.push(output_path.display().to_string());
+        }
+        notice.show_with_skipped("Saved crops", written, skipped);
+    }
+}
 
-            let size = rect.max - rect.min;
-            let image_view = SubImage::new(
-                &image_rgb8,
-                rect.min.x as u32,
-                rect.min.y as u32,
-                size.x as u32,
-                size.y as u32,
-            );
+// Visible crop of a cell, rendered in the background by the exports
+struct CropJob {
+    rect: Rect,
+    image: Arc<DynamicImage>,
+    settings: DisplaySettings,
+    turns: i32,
+    label: String,
+}
 
-            let subimage = image_view.to_image();
+impl CropJob {
+    // None when the cell isn't shown yet
+    fn new(
+        sprite: &ImageView,
+        source: &SourceImage,
+        applied: &AppliedDisplay,
+        turns: i32,
+        label: String,
+    ) -> Option<Self> {
+        Some(CropJob {
+            rect: sprite.rect?,
+            image: source.0.clone(),
+            settings: applied.0.clone(),
+            turns,
+            label,
+        })
+    }
 
-            // Save to disk
-            if let Err(e) = subimage.write_to(&mut buf_out, ImageFormat::Jpeg) {
-                println!("Failed to write data to file {}: {}", &output_path.display(), e);
-                continue;
-            };
+    // The crop as shown on screen, rotated like the cell
+    fn render(&self) -> export::CompositeCell {
+        let (min, size) = (self.rect.min.as_uvec2(), (self.rect.max - self.rect.min).as_uvec2());
+        let pixels = display::render_region(&self.image, &self.settings, min.x, min.y, size.x, size.y);
+        let image = match self.turns.rem_euclid(4) {
+            1 => image::imageops::rotate90(&pixels),
+            2 => image::imageops::rotate180(&pixels),
+            3 => image::imageops::rotate270(&pixels),
+            _ => pixels,
+        };
+        export::CompositeCell {
+            image,
+            label: self.label.clone(),
+        }
+    }
+}
+
+// Show the notice of the exports written in the background
+fn poll_exports(mut export_tasks: ResMut<ExportTasks>, mut notice: ResMut<ExportNotice>) {
+    export_tasks.0.retain_mut(|export| {
+        let Some(result) = check_ready(&mut export.task) else {
+            return true;
+        };
+        if let Some(files) = result {
+            notice.show(export.title, files);
+        }
+        false
+    });
+}
+
+// Title shown on the cell with this id
+fn cell_label(text_query: &Query<(&Id, &Text), With<MyText>>, id: usize) -> String {
+    text_query
+        .iter()
+        .find(|(text_id, _)| text_id.0 == id)
+        .map(|(_, text)| text.0.clone())
+        .unwrap_or_default()
+}
+
+// Path of an exported file, in the crop folder of the first image and following the overwrite policy
+fn export_path(first_path: &str, file_name: &str, config: &Config) -> Option<PathBuf> {
+    let source_directory = Path::new(first_path).parent().unwrap_or(Path::new(""));
+    let output_path = source_directory.join(&config.crop.directory).join(file_name);
+    let Some(output_path) = config.crop.overwrite.resolve(output_path.clone()) else {
+        println!("Skipped existing file {}", output_path.display());
+        return None;
+    };
+    if let Some(parent) = output_path.parent() {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            println!("Failed to create directory {}: {}", parent.display(), e);
+            return None;
+        }
+    }
+    Some(output_path)
+}
+
+// Save the visible crop of every cell, as shown and with its title, in one image laid out like the cells
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn export_composite(
+    mut export_composite_evr: MessageReader<ExportCompositeEvent>,
+    image_query: Query<(&Id, &ImagePath, &ImageView, &SourceImage, &AppliedDisplay, &Rotation), With<MyImage>>,
+    text_query: Query<(&Id, &Text), With<MyText>>,
+    layout_state: Res<GridLayoutState>,
+    global_rotation: Res<GlobalRotation>,
+    config: Res<Config>,
+    mut export_tasks: ResMut<ExportTasks>,
+) {
+    if export_composite_evr.is_empty() {
+        return;
+    }
+    export_composite_evr.clear();
+
+    let mut images: Vec<_> = image_query.iter().collect();
+    images.sort_by_key(|(id, ..)| id.0);
+    let Some((_, first_path, ..)) = images.first() else {
+        return;
+    };
+    // A cell still loading keeps its slot empty
+    let jobs: Vec<Option<CropJob>> = images
+        .iter()
+        .map(|(id, _, sprite, source, applied, rotation)| {
+            let label = cell_label(&text_query, id.0);
+            CropJob::new(sprite, source, applied, global_rotation.0 + rotation.0, label)
+        })
+        .collect();
+
+    // Same columns as on screen (see get_cell_rect), the layouts showing one or two images are exported side by side
+    let columns = match layout_state.layout {
+        GridLayout::Grid => grid_columns(images.len(), config.misc.grid_width),
+        GridLayout::Vertical => 1,
+        _ => images.len(),
+    };
+    let style = config.composite.style(columns);
+
+    let Some(output_path) = export_path(&first_path.0, "composite.png", &config) else {
+        return;
+    };
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        let cells: Vec<_> = jobs.iter().map(|job| job.as_ref().map(CropJob::render)).collect();
+        let canvas = export::composite(&cells, &style);
+        if let Err(e) = canvas.save_with_format(&output_path, ImageFormat::Png) {
+            println!("Failed to write data to file {}: {}", &output_path.display(), e);
+            return None;
+        }
+        Some(vec![output_path.display().to_string()])
+    });
+    export_tasks.0.push(ExportTask {
+        title: "Saved composite",
+        task,
+    });
+}
+
+// Save the visible crop of every image, in the order of the image list, as the frames of a looping animation
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn export_flicker(
+    mut export_flicker_evr: MessageReader<ExportFlickerEvent>,
+    image_query: Query<(&Id, &ImagePath, &ImageView, &SourceImage, &AppliedDisplay, &Rotation), With<MyImage>>,
+    text_query: Query<(&Id, &Text), With<MyText>>,
+    image_order: Res<ImageOrder>,
+    global_rotation: Res<GlobalRotation>,
+    config: Res<Config>,
+    mut export_tasks: ResMut<ExportTasks>,
+) {
+    if export_flicker_evr.is_empty() {
+        return;
+    }
+    export_flicker_evr.clear();
+
+    let mut first_path = None;
+    let mut jobs = Vec::new();
+    for &image_id in &image_order.0 {
+        let Some((id, path, sprite, source, applied, rotation)) = image_query.iter().find(|(id, ..)| id.0 == image_id)
+        else {
+            continue;
+        };
+        let label = if config.flicker.captions {
+            cell_label(&text_query, id.0)
+        } else {
+            String::new()
+        };
+        let Some(job) = CropJob::new(sprite, source, applied, global_rotation.0 + rotation.0, label) else {
+            continue;
+        };
+        first_path.get_or_insert(path.0.clone());
+        jobs.push(job);
+    }
+    let Some(first_path) = first_path else {
+        return;
+    };
+
+    let mut style = config.composite.style(1);
+    if !config.flicker.captions {
+        style.label.size = 0.;
+    }
+    let format = config.flicker.format;
+    let frame_duration_ms = config.flicker.frame_duration_ms;
+    let file_name = format!("flicker.{}", format.extension());
+    let Some(output_path) = export_path(&first_path, &file_name, &config) else {
+        return;
+    };
+    // Rendering the crops and quantizing the GIF colors take a while, the UI keeps running meanwhile
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        let cells: Vec<_> = jobs.iter().map(CropJob::render).collect();
+        let frames = export::flicker_frames(&cells, &style);
+        let result = File::create(&output_path)
+            .map_err(image::ImageError::IoError)
+            .and_then(|file| export::write_animation(&frames, format, frame_duration_ms, BufWriter::new(file)));
+        if let Err(e) = result {
+            println!("Failed to write data to file {}: {}", &output_path.display(), e);
+            return None;
+        }
+        Some(vec![output_path.display().to_string()])
+    });
+    export_tasks.0.push(ExportTask {
+        title: "Saved flicker",
+        task,
+    });
+}
+
+// Save the images, their order, the layout, the zoom, pan and rotation, the compared images, the frame
+// and the review state to the session file chosen in ui_session_dialog
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn save_session(
+    mut save_session_evr: MessageReader<SaveSessionEvent>,
+    image_query: Query<
+        (
+            &Id,
+            &ImagePath,
+            &Scale,
+            &Position,
+            &Rotation,
+            Option<&Layers>,
+            Option<&Sequence>,
+        ),
+        With<MyImage>,
+    >,
+    image_order: Res<ImageOrder>,
+    layout_state: Res<GridLayoutState>,
+    global_scale: Res<GlobalScale>,
+    global_rotation: Res<GlobalRotation>,
+    review_state: Res<ReviewState>,
+    compare_state: Res<CompareState>,
+    playback: Res<PlaybackState>,
+    mut notice: ResMut<ExportNotice>,
+) {
+    let Some(SaveSessionEvent(output_path)) = save_session_evr.read().last() else {
+        return;
+    };
+
+    let mut images = Vec::new();
+    for &image_id in &image_order.0 {
+        let Some((_, path, scale, position, rotation, layers, sequence)) =
+            image_query.iter().find(|(id, ..)| id.0 == image_id)
+        else {
+            continue;
+        };
+        images.push(session::SessionImage {
+            // Sequences are opened again from their folder or pattern
+            path: sequence.map_or(&path.0, |sequence| &sequence.source).clone(),
+            layer: layers.map_or(0, |layers| layers.selected),
+            scale: scale.0,
+            position: position.0.to_array(),
+            rotation: rotation.0,
+        });
+    }
+    if images.is_empty() {
+        return;
+    }
+    let radix = review_state.radixes.get(review_state.current_index).cloned();
+    let review = review_state.enabled.then(|| session::SessionReview {
+        directory: review_state.directory.clone(),
+        patterns: review_state.cell_patterns.clone(),
+        radix: radix.unwrap_or_default(),
+    });
+    let session = session::Session {
+        layout: layout_state.layout,
+        index: layout_state.index,
+        scale: global_scale.0,
+        rotation: global_rotation.0,
+        frame: playback.playback.frame,
+        images,
+        review,
+        compare: Some(session::SessionCompare {
+            second: compare_state.second,
+            divider: compare_state.divider,
+            vertical: compare_state.vertical,
+            opacity: compare_state.opacity,
+        }),
+    };
+
+    // The extension is needed to open the file again, e.g. by dropping it
+    let mut output_path = PathBuf::from(output_path);
+    if !session::is_session(&output_path.to_string_lossy()) {
+        output_path.as_mut_os_string().push(format!(".{}", session::EXTENSION));
+    }
+    if let Some(parent) = output_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            println!("Failed to create directory {}: {}", parent.display(), e);
+            return;
+        }
+    }
+    if let Err(e) = session.write(&output_path) {
+        println!("Failed to write data to file {}: {}", &output_path.display(), e);
+        return;
+    }
+    notice.show("Saved session", vec![output_path.display().to_string()]);
+}
+
+// Replace the images with the ones of a session and restore its layout, rotation and review state.
+// The zoom and pan are restored by apply_session once the images are loaded.
+#[allow(clippy::too_many_arguments)]
+fn on_open_session(
+    mut open_session_evr: MessageReader<OpenSessionEvent>,
+    mut is_new_batch: ResMut<NewImageBatch>,
+    mut load_image_evw: MessageWriter<LoadNewImageEvent>,
+    mut reset_visibility_evw: MessageWriter<ResetVisibilityEvent>,
+    mut layout_state: ResMut<GridLayoutState>,
+    mut global_rotation: ResMut<GlobalRotation>,
+    mut review_state: ResMut<ReviewState>,
+    mut compare_state: ResMut<CompareState>,
+    mut pending_session: ResMut<PendingSession>,
+) {
+    for ev in open_session_evr.read() {
+        let Some(session) = session::Session::read(Path::new(&ev.0)) else {
+            continue;
+        };
+        if session.images.is_empty() {
+            println!("Session without images: {}", ev.0);
+            continue;
+        }
+
+        is_new_batch.0 = true;
+        for (index, image) in session.images.iter().enumerate() {
+            load_image_evw.write(LoadNewImageEvent {
+                path: image.path.clone(),
+                index,
+                layer: image.layer,
+            });
+        }
+        layout_state.layout = session.layout;
+        layout_state.index = session.index.min(session.images.len() - 1);
+        global_rotation.0 = session.rotation;
+        if let Some(compare) = &session.compare {
+            compare_state.divider = compare.divider;
+            compare_state.vertical = compare.vertical;
+            compare_state.opacity = compare.opacity;
+        }
+
+        *review_state = ReviewState::default();
+        if let Some(review) = &session.review {
+            let directory = PathBuf::from(&review.directory);
+            review_state.enabled = true;
+            review_state.radixes = review::scan_radixes(&directory, &review.patterns);
+            let current_index = review_state.radixes.iter().position(|r| r == &review.radix);
+            review_state.current_index = current_index.unwrap_or(0);
+            review_state.directory = review.directory.clone();
+            review_state.editable_patterns = review.patterns.iter().map(|cp| cp.regex_str.clone()).collect();
+            review_state.cell_patterns = review.patterns.clone();
+        }
+        reset_visibility_evw.write(ResetVisibilityEvent);
+        pending_session.0 = Some(session);
+    }
+}
+
+// Restore the zoom and pan of the session after each fit to screen, until all its images are loaded.
+// Images are matched by their load slot since a failed load renumbers the Ids of the next ones.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn apply_session(
+    mut fit_to_screen_evr: MessageReader<FitToScreen>,
+    mut pending_session: ResMut<PendingSession>,
+    is_new_batch: Res<NewImageBatch>,
+    mut global_scale: ResMut<GlobalScale>,
+    mut sprite_query: Query<(&Id, &LoadSlot, &ImageView, &mut Scale, &mut Position, &mut Rotation), With<MyImage>>,
+    mut layout_state: ResMut<GridLayoutState>,
+    mut compare_state: ResMut<CompareState>,
+    mut playback: ResMut<PlaybackState>,
+    mut move_image_evw: MessageWriter<MoveImageEvent>,
+) {
+    if fit_to_screen_evr.is_empty() {
+        return;
+    }
+    fit_to_screen_evr.clear();
+    // The images of the previous batch are still there until the session images are spawned
+    let Some(session) = &pending_session.0 else {
+        return;
+    };
+    if is_new_batch.0 {
+        return;
+    }
+
+    global_scale.0 = session.scale;
+    let mut all_loaded = true;
+    for (id, slot, sprite, mut scale, mut position, mut rotation) in &mut sprite_query {
+        all_loaded &= sprite.image != Handle::default();
+        if slot.0 == session.index {
+            layout_state.index = id.0;
         }
+        if session.compare.as_ref().is_some_and(|compare| slot.0 == compare.second) {
+            compare_state.second = id.0;
+        }
+        let Some(image) = session.images.get(slot.0) else {
+            continue;
+        };
+        scale.0 = image.scale;
+        position.0 = Vec2::from_array(image.position);
+        rotation.0 = image.rotation;
+    }
+    move_image_evw.write(MoveImageEvent);
+    if all_loaded {
+        // Animations are loaded too, the saved frame is shown paused
+        playback.playback.playing = false;
+        playback.playback.seek(session.frame);
+        pending_session.0 = None;
     }
 }
 
@@ -1915,28 +5365,17 @@ fn save_settings(mut save_settings_evr: MessageReader<SaveSettingsEvent>, config
     }
 }
 
+// The shader interpolates the texels, only the materials change
 fn change_sampler(
     mut change_sampler_evr: MessageReader<ChangeSamplerEvent>,
-    config: Res<Config>,
-    sprite_query: Query<&Sprite, With<MyImage>>,
-    mut images: ResMut<Assets<Image>>,
+    mut refresh_display_evw: MessageWriter<RefreshDisplayEvent>,
 ) {
     if change_sampler_evr.is_empty() {
         return;
     }
     change_sampler_evr.clear();
 
-    let new_sampler = match config.misc.sampler_mode {
-        SamplerMode::Nearest => ImageSampler::Descriptor(ImageSamplerDescriptor::nearest()),
-        SamplerMode::Bilinear => ImageSampler::Descriptor(ImageSamplerDescriptor::linear()),
-    };
-
-    for sprite in &sprite_query {
-        let Some(image) = images.get_mut(&sprite.image) else {
-            continue;
-        };
-        image.sampler = new_sampler.clone();
-    }
+    refresh_display_evw.write(RefreshDisplayEvent);
 }
 
 fn scroll_events(
@@ -1987,17 +5426,37 @@ fn mouse_button_input(
     mut mouse_state: ResMut<MouseState>,
     global_scale: Res<GlobalScale>,
     mut image_query: Query<(&mut Position, &Scale), With<MyImage>>,
+    layout_state: Res<GridLayoutState>,
+    mut compare_state: ResMut<CompareState>,
 ) {
     if buttons.just_pressed(MouseButton::Left) {
         let window = windows.single().unwrap();
         let Some(cursor_position) = window.cursor_position() else {
             return;
         };
+
+        // Grab the wipe divider instead of panning when clicking close to it
+        if layout_state.layout == GridLayout::Wipe {
+            let distance = if compare_state.vertical {
+                cursor_position.x - compare_state.divider * window.width()
+            } else {
+                cursor_position.y - compare_state.divider * window.height()
+            };
+            if distance.abs() < 8. {
+                compare_state.dragging = true;
+                return;
+            }
+        }
+
         mouse_state.pressed = true;
         mouse_state.origin = cursor_position;
         mouse_state.delta = Vec2::ZERO;
     }
     if buttons.just_released(MouseButton::Left) {
+        if compare_state.dragging {
+            compare_state.dragging = false;
+            return;
+        }
         mouse_state.pressed = false;
 
         let window = windows.single().unwrap();
@@ -2025,19 +5484,32 @@ fn cursor_move(
     mut cursor_evr: MessageReader<CursorMoved>,
     mut move_image_evw: MessageWriter<MoveImageEvent>,
     mut mouse_state: ResMut<MouseState>,
+    windows: Query<&Window>,
+    mut compare_state: ResMut<CompareState>,
 ) {
     for ev in cursor_evr.read() {
-        if mouse_state.pressed {
+        if compare_state.dragging {
+            let window = windows.single().unwrap();
+            compare_state.divider = if compare_state.vertical {
+                ev.position.x / window.width()
+            } else {
+                ev.position.y / window.height()
+            }
+            .clamp(0., 1.);
+            move_image_evw.write(MoveImageEvent);
+        } else if mouse_state.pressed {
             mouse_state.delta = ev.position - mouse_state.origin;
             move_image_evw.write(MoveImageEvent);
         }
     }
 }
 
+#[allow(clippy::explicit_counter_loop)]
 fn file_drop(
     mut dnd_evr: MessageReader<FileDragAndDrop>,
     mut is_new_batch: ResMut<NewImageBatch>,
     mut load_image_evw: MessageWriter<LoadNewImageEvent>,
+    mut open_session_evw: MessageWriter<OpenSessionEvent>,
     add_mode: Res<AddMode>,
     sprite_query: Query<&Id, With<MyImage>>,
 ) {
@@ -2057,6 +5529,11 @@ fn file_drop(
             images_filename.push(String::from(image_absolute));
         }
     }
+    // A dropped session replaces everything, whatever the add mode
+    if let Some(session) = images_filename.iter().find(|path| session::is_session(path)) {
+        open_session_evw.write(OpenSessionEvent(session.clone()));
+        return;
+    }
     if some_file_dropped {
         let mut count: usize = if add_mode.0 {
             sprite_query.iter().count()
@@ -2068,6 +5545,7 @@ fn file_drop(
             load_image_evw.write(LoadNewImageEvent {
                 path: filename,
                 index: count,
+                layer: 0,
             });
             count += 1;
         }
@@ -2143,6 +5621,7 @@ mod macos_dock_drop {
 fn poll_dock_drop_queue(
     mut is_new_batch: ResMut<NewImageBatch>,
     mut load_image_evw: MessageWriter<LoadNewImageEvent>,
+    mut open_session_evw: MessageWriter<OpenSessionEvent>,
     add_mode: Res<AddMode>,
     sprite_query: Query<&Id, With<MyImage>>,
 ) {
@@ -2156,6 +5635,11 @@ fn poll_dock_drop_queue(
     let paths: Vec<String> = queue.drain(..).collect();
     drop(queue);
 
+    if let Some(session) = paths.iter().find(|path| session::is_session(path)) {
+        open_session_evw.write(OpenSessionEvent(session.clone()));
+        return;
+    }
+
     let count: usize = if add_mode.0 { sprite_query.iter().count() } else { 0 };
     if !add_mode.0 {
         is_new_batch.0 = true;
@@ -2164,6 +5648,7 @@ fn poll_dock_drop_queue(
         load_image_evw.write(LoadNewImageEvent {
             path,
             index: count + index,
+            layer: 0,
         });
     }
 }
@@ -2191,7 +5676,7 @@ fn on_navigate_review(
         is_new_batch.0 = true;
         for (index, file) in files.into_iter().enumerate() {
             let Some(path) = file else { continue };
-            load_image_evw.write(LoadNewImageEvent { path, index });
+            load_image_evw.write(LoadNewImageEvent { path, index, layer: 0 });
         }
     }
 }
@@ -2273,7 +5758,7 @@ fn on_refresh_review(
             is_new_batch.0 = true;
             for (index, file) in files.into_iter().enumerate() {
                 let Some(path) = file else { continue };
-                load_image_evw.write(LoadNewImageEvent { path, index });
+                load_image_evw.write(LoadNewImageEvent { path, index, layer: 0 });
             }
         }
     }
@@ -2331,19 +5816,31 @@ fn check_all_images_exist(images: &[String]) -> Result<Vec<String>> {
     let mut images_absolute = Vec::new();
     for image_filename in images {
         let input_path = Path::new(&image_filename);
-        if !input_path.exists() {
+        // Folders and frame patterns (frame_####.png) are opened as image sequences,
+        // existing files are opened as is whatever their name
+        let is_sequence = !input_path.is_file() && sequence::resolve(image_filename).is_some();
+        if !input_path.exists() && !is_sequence {
             return Err(Box::new(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
                 format!("Image not found: {}", image_filename),
             )));
         }
-        if !input_path.is_file() {
+        if !input_path.is_file() && !is_sequence {
             return Err(Box::new(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
-                format!("Provided Path is not a file: {}", image_filename),
+                format!("Provided Path is not a file or an image sequence: {}", image_filename),
             )));
         }
-        let resolved_path = canonicalize(input_path)?;
+        let resolved_path = if input_path.exists() {
+            canonicalize(input_path)?
+        } else {
+            // A pattern isn't a file, only its directory is resolved
+            let directory = match input_path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            canonicalize(directory)?.join(input_path.file_name().unwrap_or_default())
+        };
         let Some(image_absolute) = resolved_path.as_path().to_str() else {
             return Err(Box::new(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
@@ -0,0 +1,192 @@
+use bevy::asset::{embedded_asset, embedded_path, AssetPath};
+use bevy::prelude::*;
+use bevy::render::render_resource::{AsBindGroup, ShaderType};
+use bevy::shader::ShaderRef;
+use bevy::sprite_render::{AlphaMode2d, Material2d, Material2dPlugin};
+
+use crate::colorspace::{ColorSpace, ImageSpace};
+use crate::display::{ChannelView, Colormap, DisplaySettings, ToneMapping};
+
+// Transfer id of the ICC profiles in the shader, after the ones of colorspace::Transfer
+const TRANSFER_ICC: u32 = 4;
+
+pub struct DisplayMaterialPlugin;
+
+impl Plugin for DisplayMaterialPlugin {
+    fn build(&self, app: &mut App) {
+        embedded_asset!(app, "display.wgsl");
+        app.add_plugins(Material2dPlugin::<DisplayMaterial>::default());
+    }
+}
+
+// Draws the stored values of an image with the display transform applied by the shader,
+// so a display setting change only updates the uniforms
+#[derive(Asset, TypePath, AsBindGroup, Clone, Default)]
+pub struct DisplayMaterial {
+    #[uniform(0)]
+    pub params: DisplayParams,
+    // Float images are Rgba32Float, which isn't filterable everywhere: the shader loads the texels
+    #[texture(1, sample_type = "float", filterable = false)]
+    pub image: Handle<Image>,
+    #[texture(2, sample_type = "float", filterable = false)]
+    pub reference: Option<Handle<Image>>, // stored values of the reference of the difference mode
+    #[texture(3, dimension = "3d")]
+    #[sampler(4)]
+    pub icc_lut: Option<Handle<Image>>, // ICC profile conversion, see ImageSpace::lut
+}
+
+impl Material2d for DisplayMaterial {
+    fn fragment_shader() -> ShaderRef {
+        ShaderRef::Path(AssetPath::from_path_buf(embedded_path!("display.wgsl")).with_source("embedded"))
+    }
+
+    fn alpha_mode(&self) -> AlphaMode2d {
+        AlphaMode2d::Blend
+    }
+}
+
+// Uniforms of display.wgsl, the fields must stay in the same order as the shader struct
+#[derive(ShaderType, Debug, Clone, Copy, PartialEq)]
+pub struct DisplayParams {
+    pub to_working: Mat3,     // linear RGB of the color space -> linear sRGB
+    pub levels: Vec2,         // stored values stretched to [0, 1], (0, 1) when disabled
+    pub colormap_range: Vec2, // in stored units
+    pub mapping: UVec4,       // source channel of each displayed channel
+    pub reference_axes: Mat2, // display::ReferenceMapping, texels of the image -> texels of the reference
+    pub reference_offset: Vec2,
+    pub exposure: f32,
+    pub offset: f32,
+    pub gamma: f32,
+    pub tonemapping: u32,
+    pub tone_identity: u32, // values are shown as is, without clamping
+    pub transfer: u32,      // colorspace::Transfer, or TRANSFER_ICC
+    pub channels: u32,      // 0: RGB, 1: single, 2: luma, 3: swizzle
+    pub colormap: u32,      // 0: none, then display::Colormap + 1
+    pub ignore_alpha: u32,
+    pub highlight: u32,
+    pub linear: u32, // float image, the clipping problems are values outside [0, 1]
+    pub stored_unit: f32,
+    pub difference: u32, // 0: none, 1: color, 2: heat-map
+    pub amplification: f32,
+    pub opacity: f32,
+    pub bilinear: u32, // interpolate the texels, otherwise the nearest one is shown
+}
+
+impl Default for DisplayParams {
+    fn default() -> Self {
+        DisplayParams::new(&DisplaySettings::default(), false, 255.)
+    }
+}
+
+impl DisplayParams {
+    // `linear` and `stored_unit` describe the stored values of the image (display::is_linear, display::stored_unit)
+    pub fn new(settings: &DisplaySettings, linear: bool, stored_unit: f32) -> DisplayParams {
+        let color_space = settings
+            .color_space
+            .clone()
+            .unwrap_or(ImageSpace::Known(ColorSpace::default_for(linear)));
+        let (transfer, to_working) = match color_space {
+            ImageSpace::Known(space) => (
+                space.transfer() as u32,
+                Mat3::from_cols_array_2d(&space.to_srgb_matrix()).transpose(),
+            ),
+            ImageSpace::Icc { .. } => (TRANSFER_ICC, Mat3::IDENTITY),
+        };
+        let (channels, mapping) = match settings.channels {
+            ChannelView::Rgb => (0, UVec4::new(0, 1, 2, 3)),
+            ChannelView::Single(channel) => (1, UVec4::splat(channel as u32)),
+            ChannelView::Luma => (2, UVec4::new(0, 1, 2, 3)),
+            ChannelView::Swizzle([r, g, b]) => (3, UVec4::new(r as u32, g as u32, b as u32, 3)),
+        };
+        let (colormap, colormap_range) = match settings.colormap {
+            Some(colormap) => {
+                let id = Colormap::ALL.iter().position(|c| *c == colormap.colormap).unwrap_or(0) + 1;
+                (id as u32, Vec2::new(colormap.min, colormap.max))
+            }
+            None => (0, Vec2::new(0., 1.)),
+        };
+        let (low, high) = settings.levels.unwrap_or((0., 1.));
+        let difference = match &settings.difference {
+            Some(difference) if difference.heatmap => 2,
+            Some(_) => 1,
+            None => 0,
+        };
+        let reference = settings
+            .difference
+            .as_ref()
+            .map(|difference| difference.mapping)
+            .unwrap_or_default();
+        DisplayParams {
+            to_working,
+            levels: Vec2::new(low, high),
+            colormap_range,
+            reference_axes: Mat2::from_cols_array_2d(&reference.axes),
+            reference_offset: Vec2::from_array(reference.offset),
+            mapping,
+            exposure: settings.tone.exposure,
+            offset: settings.tone.offset,
+            gamma: settings.tone.gamma,
+            tonemapping: match settings.tone.tonemapping {
+                ToneMapping::Clamp => 0,
+                ToneMapping::Reinhard => 1,
+                ToneMapping::Aces => 2,
+                ToneMapping::Filmic => 3,
+            },
+            tone_identity: settings.tone.is_identity() as u32,
+            transfer,
+            channels,
+            colormap,
+            ignore_alpha: settings.ignore_alpha as u32,
+            highlight: settings.highlight as u32,
+            linear: linear as u32,
+            stored_unit,
+            difference,
+            amplification: settings
+                .difference
+                .as_ref()
+                .map_or(1., |difference| difference.amplification),
+            opacity: 1.,
+            bilinear: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::ColormapSettings;
+
+    #[test]
+    fn native_images_are_only_decoded() {
+        let params = DisplayParams::new(&DisplaySettings::default(), false, 255.);
+        assert_eq!(params.transfer, 1);
+        assert!(params.to_working.abs_diff_eq(Mat3::IDENTITY, 1e-6));
+        assert_eq!(params.levels, Vec2::new(0., 1.));
+        assert_eq!(params.tone_identity, 1);
+
+        let params = DisplayParams::new(&DisplaySettings::default(), true, 1.);
+        assert_eq!(params.transfer, 0);
+        assert_eq!(params.linear, 1);
+    }
+
+    #[test]
+    fn settings_are_packed_for_the_shader() {
+        let settings = DisplaySettings {
+            channels: ChannelView::Swizzle([2, 1, 0]),
+            colormap: Some(ColormapSettings {
+                colormap: Colormap::Labels,
+                min: 0.,
+                max: 255.,
+            }),
+            color_space: Some(ImageSpace::Known(ColorSpace::DisplayP3)),
+            ..Default::default()
+        };
+        let params = DisplayParams::new(&settings, false, 255.);
+        assert_eq!(params.channels, 3);
+        assert_eq!(params.mapping, UVec4::new(2, 1, 0, 3));
+        assert_eq!(params.colormap, 4);
+        // The shader multiplies column vectors, the first column is the contribution of red
+        let red = params.to_working * Vec3::X;
+        assert!((red - Vec3::new(1.2249, -0.0420, -0.0197)).abs().max_element() < 1e-3);
+    }
+}
@@ -0,0 +1,149 @@
+use std::io::{BufRead, Seek};
+
+use exif::{Exif, In, Tag, Value};
+
+// EXIF fields of an image as text: the usual photo settings first, then every field of the main image
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    pub summary: Vec<(&'static str, String)>,
+    pub fields: Vec<(String, String)>,
+}
+
+// None when the file has no EXIF data (or its container isn't supported)
+pub fn read_from<R: BufRead + Seek>(mut reader: R) -> Option<Metadata> {
+    let exif = exif::Reader::new().read_from_container(&mut reader).ok()?;
+    Some(from_exif(&exif))
+}
+
+// Longest value kept in the full list, binary blobs are useless as text
+const MAX_VALUE_LENGTH: usize = 80;
+
+pub fn from_exif(exif: &Exif) -> Metadata {
+    let mut summary = Vec::new();
+    let camera = match (text(exif, Tag::Make), text(exif, Tag::Model)) {
+        // The model often starts with the make already
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{make} {model}")),
+        (make, model) => make.or(model),
+    };
+    let rows = [
+        ("Camera", camera),
+        ("Lens", text(exif, Tag::LensModel)),
+        ("Exposure", text(exif, Tag::ExposureTime)),
+        ("Aperture", text(exif, Tag::FNumber)),
+        ("ISO", text(exif, Tag::PhotographicSensitivity)),
+        ("Focal length", text(exif, Tag::FocalLength)),
+        (
+            "Taken",
+            text(exif, Tag::DateTimeOriginal).or_else(|| text(exif, Tag::DateTime)),
+        ),
+    ];
+    for (name, value) in rows {
+        if let Some(value) = value {
+            summary.push((name, value));
+        }
+    }
+
+    let fields = exif
+        .fields()
+        .filter(|field| field.ifd_num == In::PRIMARY && field.tag != Tag::MakerNote)
+        .map(|field| {
+            let mut value = field.display_value().with_unit(exif).to_string();
+            if value.len() > MAX_VALUE_LENGTH {
+                let end = (0..=MAX_VALUE_LENGTH)
+                    .rev()
+                    .find(|i| value.is_char_boundary(*i))
+                    .unwrap_or(0);
+                value.truncate(end);
+                value.push('\u{2026}');
+            }
+            (field.tag.to_string(), value)
+        })
+        .collect();
+    Metadata { summary, fields }
+}
+
+// Value of a field of the main image, ASCII values without their quotes
+fn text(exif: &Exif, tag: Tag) -> Option<String> {
+    let field = exif.get_field(tag, In::PRIMARY)?;
+    let value = field.display_value().with_unit(exif).to_string();
+    let value = match field.value {
+        Value::Ascii(_) => value.trim_matches(['"', ' ']).to_string(),
+        _ => value,
+    };
+    (!value.is_empty()).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use exif::experimental::Writer;
+    use exif::{Field, Rational};
+
+    use super::*;
+
+    fn field(tag: Tag, value: Value) -> Field {
+        Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value,
+        }
+    }
+
+    fn exif(fields: &[Field]) -> Exif {
+        let mut writer = Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        let mut bytes = Cursor::new(Vec::new());
+        writer.write(&mut bytes, false).unwrap();
+        exif::Reader::new().read_raw(bytes.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn photo_settings() {
+        let exif = exif(&[
+            field(Tag::Make, Value::Ascii(vec![b"Canon".to_vec()])),
+            field(Tag::Model, Value::Ascii(vec![b"Canon EOS R5".to_vec()])),
+            field(
+                Tag::ExposureTime,
+                Value::Rational(vec![Rational { num: 1, denom: 250 }]),
+            ),
+            field(Tag::FNumber, Value::Rational(vec![Rational { num: 28, denom: 10 }])),
+            field(Tag::PhotographicSensitivity, Value::Short(vec![400])),
+            field(Tag::FocalLength, Value::Rational(vec![Rational { num: 50, denom: 1 }])),
+            field(
+                Tag::DateTimeOriginal,
+                Value::Ascii(vec![b"2024:05:01 10:20:30".to_vec()]),
+            ),
+        ]);
+        let metadata = from_exif(&exif);
+        let summary: Vec<_> = metadata
+            .summary
+            .iter()
+            .map(|(name, value)| (*name, value.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("Camera", "Canon EOS R5"),
+                ("Exposure", "1/250 s"),
+                ("Aperture", "f/2.8"),
+                ("ISO", "400"),
+                ("Focal length", "50 mm"),
+                ("Taken", "2024-05-01 10:20:30"),
+            ]
+        );
+        assert_eq!(metadata.fields.len(), 7);
+    }
+
+    #[test]
+    fn make_is_prepended_to_model() {
+        let exif = exif(&[
+            field(Tag::Make, Value::Ascii(vec![b"Apple".to_vec()])),
+            field(Tag::Model, Value::Ascii(vec![b"iPhone 15".to_vec()])),
+        ]);
+        assert_eq!(from_exif(&exif).summary, [("Camera", "Apple iPhone 15".to_string())]);
+    }
+}
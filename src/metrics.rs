@@ -0,0 +1,251 @@
+use image::{DynamicImage, Rgba32FImage};
+
+// Area of an image in pixel coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    pub fn full(image: &DynamicImage) -> Self {
+        Region {
+            x: 0,
+            y: 0,
+            width: image.width(),
+            height: image.height(),
+        }
+    }
+
+    // Keep the part of the region inside a width x height image
+    fn clamp(&self, width: u32, height: u32) -> Region {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+        Region {
+            x,
+            y,
+            width: self.width.min(width - x),
+            height: self.height.min(height - y),
+        }
+    }
+}
+
+// Quality metrics of an image against a reference.
+// Integer images are normalized to [0, 1], float images are compared as is, PSNR uses a peak value of 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metrics {
+    pub mse: f64,
+    pub psnr: f64,
+    pub ssim: f64,
+}
+
+// One line of the metrics table
+#[derive(Debug, Clone)]
+pub struct MetricsRow {
+    pub name: String,
+    // None when the region doesn't overlap the reference
+    pub metrics: Option<Metrics>,
+    // The images don't have the same size, only the overlapping area is compared
+    pub size_mismatch: bool,
+}
+
+// Compare the region of `image` with the same region of `reference`.
+// RGB channels are used for MSE and PSNR, SSIM is computed on the luma.
+pub fn compare(image: &DynamicImage, reference: &DynamicImage, region: Region) -> Option<Metrics> {
+    let region = region
+        .clamp(image.width(), image.height())
+        .clamp(reference.width(), reference.height());
+    if region.width == 0 || region.height == 0 {
+        return None;
+    }
+    let a = crop_rgba(image, region);
+    let b = crop_rgba(reference, region);
+
+    let mut squared_error = 0.;
+    for (pa, pb) in a.pixels().zip(b.pixels()) {
+        for c in 0..3 {
+            let d = (pa.0[c] - pb.0[c]) as f64;
+            squared_error += d * d;
+        }
+    }
+    let mse = squared_error / (region.width as f64 * region.height as f64 * 3.);
+    let psnr = if mse == 0. {
+        f64::INFINITY
+    } else {
+        10. * (1. / mse).log10()
+    };
+    let ssim = ssim(&luma(&a), &luma(&b), region.width as usize, region.height as usize);
+    Some(Metrics { mse, psnr, ssim })
+}
+
+fn crop_rgba(image: &DynamicImage, region: Region) -> Rgba32FImage {
+    image
+        .crop_imm(region.x, region.y, region.width, region.height)
+        .to_rgba32f()
+}
+
+fn luma(image: &Rgba32FImage) -> Vec<f64> {
+    image
+        .pixels()
+        .map(|p| 0.2126 * p.0[0] as f64 + 0.7152 * p.0[1] as f64 + 0.0722 * p.0[2] as f64)
+        .collect()
+}
+
+// Summed-area table with an extra row and column of zeros
+fn integral(values: impl Iterator<Item = f64>, width: usize, height: usize) -> Vec<f64> {
+    let mut table = vec![0.; (width + 1) * (height + 1)];
+    for (i, value) in values.enumerate() {
+        let (x, y) = (i % width, i / width);
+        table[(y + 1) * (width + 1) + x + 1] =
+            value + table[y * (width + 1) + x + 1] + table[(y + 1) * (width + 1) + x] - table[y * (width + 1) + x];
+    }
+    table
+}
+
+// Mean SSIM over every 7x7 window (smaller if the image is smaller), with the usual constants for a range of 1
+fn ssim(a: &[f64], b: &[f64], width: usize, height: usize) -> f64 {
+    let (c1, c2) = (0.01f64.powi(2), 0.03f64.powi(2));
+    let window = 7.min(width).min(height);
+    let sum_a = integral(a.iter().copied(), width, height);
+    let sum_b = integral(b.iter().copied(), width, height);
+    let sum_aa = integral(a.iter().map(|v| v * v), width, height);
+    let sum_bb = integral(b.iter().map(|v| v * v), width, height);
+    let sum_ab = integral(a.iter().zip(b).map(|(u, v)| u * v), width, height);
+    let area = |table: &[f64], x: usize, y: usize| {
+        let (x1, y1) = (x + window, y + window);
+        let stride = width + 1;
+        table[y1 * stride + x1] - table[y * stride + x1] - table[y1 * stride + x] + table[y * stride + x]
+    };
+
+    let n = (window * window) as f64;
+    let mut total = 0.;
+    let mut count = 0.;
+    for y in 0..=height - window {
+        for x in 0..=width - window {
+            let mean_a = area(&sum_a, x, y) / n;
+            let mean_b = area(&sum_b, x, y) / n;
+            let var_a = area(&sum_aa, x, y) / n - mean_a * mean_a;
+            let var_b = area(&sum_bb, x, y) / n - mean_b * mean_b;
+            let covariance = area(&sum_ab, x, y) / n - mean_a * mean_b;
+            total += ((2. * mean_a * mean_b + c1) * (2. * covariance + c2))
+                / ((mean_a * mean_a + mean_b * mean_b + c1) * (var_a + var_b + c2));
+            count += 1.;
+        }
+    }
+    total / count
+}
+
+// Table as CSV, missing metrics are left empty
+pub fn to_csv(rows: &[MetricsRow]) -> String {
+    let mut csv = String::from("image,mse,psnr,ssim\n");
+    for row in rows {
+        let name = row.name.replace('"', "\"\"");
+        match &row.metrics {
+            Some(m) => csv.push_str(&format!("\"{name}\",{},{},{}\n", m.mse, m.psnr, m.ssim)),
+            None => csv.push_str(&format!("\"{name}\",,,\n")),
+        }
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma, Rgb, RgbImage};
+
+    use super::*;
+
+    fn gray(width: u32, height: u32, value: u8) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_pixel(width, height, Luma([value])))
+    }
+
+    fn noise(width: u32, height: u32, seed: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            let v = ((x * 31 + y * 17 + seed).wrapping_mul(2654435761) >> 24) as u8;
+            Rgb([v, v / 2, 255 - v])
+        }))
+    }
+
+    #[test]
+    fn identical_images() {
+        let image = noise(16, 16, 1);
+        let m = compare(&image, &image, Region::full(&image)).unwrap();
+        assert_eq!(m.mse, 0.);
+        assert!(m.psnr.is_infinite());
+        assert!((m.ssim - 1.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn constant_offset() {
+        let a = gray(8, 8, 0);
+        let b = gray(8, 8, 51); // 0.2
+        let m = compare(&a, &b, Region::full(&a)).unwrap();
+        assert!((m.mse - 0.04).abs() < 1e-6);
+        assert!((m.psnr - 13.979).abs() < 1e-3);
+        assert!(m.ssim < 1.);
+    }
+
+    #[test]
+    fn ssim_decreases_with_noise() {
+        let a = noise(32, 32, 1);
+        let b = noise(32, 32, 2);
+        let m = compare(&a, &b, Region::full(&a)).unwrap();
+        assert!(m.ssim < 0.9, "{}", m.ssim);
+    }
+
+    #[test]
+    fn region_only() {
+        let mut a = RgbImage::from_pixel(8, 8, Rgb([0, 0, 0]));
+        a.put_pixel(7, 7, Rgb([255, 255, 255]));
+        let a = DynamicImage::ImageRgb8(a);
+        let b = DynamicImage::ImageRgb8(RgbImage::from_pixel(8, 8, Rgb([0, 0, 0])));
+        let region = Region {
+            x: 0,
+            y: 0,
+            width: 4,
+            height: 4,
+        };
+        assert_eq!(compare(&a, &b, region).unwrap().mse, 0.);
+        assert!(compare(&a, &b, Region::full(&a)).unwrap().mse > 0.);
+    }
+
+    #[test]
+    fn different_sizes_use_overlap() {
+        let a = gray(10, 4, 20);
+        let b = gray(4, 10, 20);
+        let m = compare(&a, &b, Region::full(&a)).unwrap();
+        assert_eq!(m.mse, 0.);
+        let outside = Region {
+            x: 5,
+            y: 0,
+            width: 5,
+            height: 4,
+        };
+        assert!(compare(&a, &b, outside).is_none());
+    }
+
+    #[test]
+    fn csv_output() {
+        let rows = vec![
+            MetricsRow {
+                name: "2 b.png".into(),
+                metrics: Some(Metrics {
+                    mse: 0.5,
+                    psnr: 3.,
+                    ssim: 0.25,
+                }),
+                size_mismatch: false,
+            },
+            MetricsRow {
+                name: "3 \"c\".png".into(),
+                metrics: None,
+                size_mismatch: true,
+            },
+        ];
+        assert_eq!(
+            to_csv(&rows),
+            "image,mse,psnr,ssim\n\"2 b.png\",0.5,3,0.25\n\"3 \"\"c\"\".png\",,,\n"
+        );
+    }
+}
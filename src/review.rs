@@ -2,9 +2,10 @@ use std::collections::BTreeSet;
 use std::path::Path;
 
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 // A cell's pattern: the full tail after radix, and regex.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct CellPattern {
     pub tail: String,
     pub regex_str: String,
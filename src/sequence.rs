@@ -0,0 +1,158 @@
+use std::cmp::Ordering;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use image::ImageFormat;
+use regex::Regex;
+
+// Files of an image sequence bound to a cell, in frame order. The path is either a directory
+// (every image in it) or a pattern where the frame number is written `####` or `%04d`
+// (e.g. `render/frame_####.png`). None for a single image or when nothing matches, existing
+// files are images even when their name looks like a pattern.
+pub fn resolve(path: &str) -> Option<Vec<String>> {
+    let path = Path::new(path);
+    if path.is_file() {
+        return None;
+    }
+    let (directory, regex) = if path.is_dir() {
+        (path, None)
+    } else {
+        let regex = pattern_regex(path.file_name()?.to_str()?)?;
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        (directory, Some(regex))
+    };
+
+    let mut names: Vec<String> = std::fs::read_dir(directory)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| ImageFormat::from_path(name).is_ok())
+        .filter(|name| regex.as_ref().is_none_or(|regex| regex.is_match(name)))
+        .collect();
+    if names.is_empty() {
+        return None;
+    }
+    names.sort_by(|a, b| natural_cmp(a, b));
+    let paths = names
+        .iter()
+        .filter_map(|name| directory.join(name).to_str().map(String::from))
+        .collect();
+    Some(paths)
+}
+
+// Compiled once, natural_cmp runs for every comparison of a sort
+static PLACEHOLDER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"#+|%0?\d*d").unwrap());
+static NUMBER_CHUNK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\d+|\D+").unwrap());
+
+// Regex matching the file names of a pattern, None when there is no frame number placeholder
+fn pattern_regex(filename: &str) -> Option<Regex> {
+    let found = PLACEHOLDER.find(filename)?;
+    let regex = format!(
+        "^{}\\d+{}$",
+        regex::escape(&filename[..found.start()]),
+        regex::escape(&filename[found.end()..])
+    );
+    Regex::new(&regex).ok()
+}
+
+// Compare file names with their numbers by value: frame_2 comes before frame_10
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut chunks_a = NUMBER_CHUNK.find_iter(a).map(|m| m.as_str());
+    let mut chunks_b = NUMBER_CHUNK.find_iter(b).map(|m| m.as_str());
+    loop {
+        let ordering = match (chunks_a.next(), chunks_b.next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => match (x.parse::<u64>(), y.parse::<u64>()) {
+                (Ok(x), Ok(y)) => x.cmp(&y),
+                _ => x.cmp(y),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+// Frames to keep decoded around `frame` in loading order: the frame itself, the next ones
+// (wrapping around as playback loops), then the previous ones
+pub fn preload_order(frame: usize, len: usize, ahead: usize, behind: usize) -> Vec<usize> {
+    let mut order = Vec::new();
+    if len == 0 {
+        return order;
+    }
+    let frame = frame.min(len - 1);
+    let offsets = (0..=ahead as isize).chain((1..=behind as isize).map(|offset| -offset));
+    for offset in offsets {
+        let index = (frame as isize + offset).rem_euclid(len as isize) as usize;
+        if !order.contains(&index) {
+            order.push(index);
+        }
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+
+    #[test]
+    fn numbers_are_compared_by_value() {
+        let mut names = vec!["frame_10.png", "frame_2.png", "frame_1.png", "frame_1b.png"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["frame_1.png", "frame_1b.png", "frame_2.png", "frame_10.png"]);
+        assert_eq!(natural_cmp("a_0001.png", "a_0001.png"), Ordering::Equal);
+    }
+
+    #[test]
+    fn placeholders() {
+        let regex = pattern_regex("frame_####.png").unwrap();
+        assert!(regex.is_match("frame_0012.png"));
+        assert!(!regex.is_match("frame_0012.exr"));
+        assert!(!regex.is_match("frame_.png"));
+        assert!(pattern_regex("frame_%04d.png").unwrap().is_match("frame_0240.png"));
+        assert!(pattern_regex("shot.v2_%d.png").unwrap().is_match("shot.v2_7.png"));
+        assert!(!pattern_regex("shot.v2_%d.png").unwrap().is_match("shotXv2_7.png"));
+        assert!(pattern_regex("frame_0001.png").is_none());
+    }
+
+    #[test]
+    fn directory_and_pattern_sequences() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["f_10.png", "f_9.png", "g_1.png", "g_#1.png", "notes.txt"] {
+            File::create(dir.path().join(name)).unwrap();
+        }
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        let name = |path: &String| Path::new(path).file_name().unwrap().to_str().unwrap().to_string();
+
+        let all = resolve(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(
+            all.iter().map(name).collect::<Vec<_>>(),
+            ["f_9.png", "f_10.png", "g_1.png", "g_#1.png"]
+        );
+
+        let pattern = dir.path().join("f_##.png");
+        let frames = resolve(pattern.to_str().unwrap()).unwrap();
+        assert_eq!(frames.iter().map(name).collect::<Vec<_>>(), ["f_9.png", "f_10.png"]);
+
+        assert!(resolve(dir.path().join("h_#.png").to_str().unwrap()).is_none());
+        assert!(resolve(dir.path().join("f_9.png").to_str().unwrap()).is_none());
+        // An existing file named like a pattern is a single image
+        assert!(resolve(dir.path().join("g_#1.png").to_str().unwrap()).is_none());
+    }
+
+    #[test]
+    fn preload_wraps_around() {
+        assert_eq!(preload_order(0, 10, 3, 1), [0, 1, 2, 3, 9]);
+        assert_eq!(preload_order(8, 10, 3, 2), [8, 9, 0, 1, 7, 6]);
+        assert_eq!(preload_order(1, 2, 3, 2), [1, 0]);
+        assert!(preload_order(0, 0, 3, 2).is_empty());
+    }
+}
@@ -0,0 +1,206 @@
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::review::CellPattern;
+use crate::GridLayout;
+
+// Extension of the session files, also used to recognize them when dropped on the window
+pub const EXTENSION: &str = "ivs";
+
+// Viewing state saved in a session file (TOML). Paths are written relative to the session file
+// when they share a folder with it, so a folder holding the images and the session can be moved.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Session {
+    pub layout: GridLayout,
+    pub index: usize, // top image of the Stack layout
+    pub scale: f32,
+    pub rotation: i32,
+    #[serde(default)]
+    pub frame: usize, // frame of the animations and sequences
+    pub images: Vec<SessionImage>, // in the order of the image list
+    pub review: Option<SessionReview>,
+    #[serde(default)]
+    pub compare: Option<SessionCompare>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionImage {
+    pub path: String, // image file, folder or frame pattern of a sequence
+    #[serde(default)]
+    pub layer: usize,
+    pub scale: f32,
+    pub position: [f32; 2],
+    pub rotation: i32,
+}
+
+// Wipe and Blend layouts
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionCompare {
+    pub second: usize, // index of the second image in `images`
+    pub divider: f32,
+    pub vertical: bool,
+    pub opacity: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionReview {
+    pub directory: String,
+    pub patterns: Vec<CellPattern>,
+    pub radix: String,
+}
+
+impl Session {
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let base = path.parent().unwrap_or(Path::new(""));
+        let session = self.with_paths(|p| relative_path(p, base));
+        let text = toml::to_string(&session).map_err(std::io::Error::other)?;
+        std::fs::write(path, text)
+    }
+
+    pub fn read(path: &Path) -> Option<Session> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => {
+                println!("Failed to read session {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        let session: Session = match toml::from_str(&text) {
+            Ok(session) => session,
+            Err(e) => {
+                println!("Invalid session {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        let base = path.parent().unwrap_or(Path::new(""));
+        Some(session.with_paths(|p| resolve_path(p, base)))
+    }
+
+    fn with_paths(&self, convert: impl Fn(&str) -> String) -> Session {
+        let mut session = self.clone();
+        for image in &mut session.images {
+            image.path = convert(&image.path);
+        }
+        if let Some(review) = &mut session.review {
+            review.directory = convert(&review.directory);
+        }
+        session
+    }
+}
+
+pub fn is_session(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension == EXTENSION)
+}
+
+// `path` relative to the folder `base`, or unchanged when they only share the root
+fn relative_path(path: &str, base: &Path) -> String {
+    let path = Path::new(path);
+    let path_components: Vec<Component> = path.components().collect();
+    let base_components: Vec<Component> = base.components().collect();
+    let common = path_components
+        .iter()
+        .zip(&base_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let shares_folder = path_components[..common]
+        .iter()
+        .any(|component| matches!(component, Component::Normal(_)));
+    if !path.is_absolute() || !shares_folder {
+        return path.to_string_lossy().to_string();
+    }
+    let mut relative = PathBuf::new();
+    for _ in common..base_components.len() {
+        relative.push("..");
+    }
+    relative.extend(&path_components[common..]);
+    if relative.as_os_str().is_empty() {
+        return ".".to_string();
+    }
+    relative.to_string_lossy().to_string()
+}
+
+// Absolute path of a path written in a session file
+fn resolve_path(path: &str, base: &Path) -> String {
+    let mut resolved = PathBuf::new();
+    for component in base.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if resolved.file_name().is_some() => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    resolved.to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(path: &str) -> SessionImage {
+        SessionImage {
+            path: path.to_string(),
+            layer: 0,
+            scale: 1.5,
+            position: [10., -4.],
+            rotation: 1,
+        }
+    }
+
+    #[test]
+    fn paths_are_relative_to_the_session() {
+        let base = Path::new("/data/shots/session");
+        assert_eq!(relative_path("/data/shots/session/a.png", base), "a.png");
+        assert_eq!(
+            relative_path("/data/shots/b/frame_####.png", base),
+            "../b/frame_####.png"
+        );
+        assert_eq!(relative_path("/other/c.png", base), "/other/c.png");
+        assert_eq!(relative_path("/data/shots/session", base), ".");
+        assert_eq!(resolve_path(".", base), "/data/shots/session");
+        assert_eq!(
+            resolve_path("../b/frame_####.png", base),
+            "/data/shots/b/frame_####.png"
+        );
+        assert_eq!(resolve_path("/other/c.png", base), "/other/c.png");
+    }
+
+    #[test]
+    fn session_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_string_lossy().to_string();
+        let session = Session {
+            layout: GridLayout::Stack,
+            index: 1,
+            scale: 0.25,
+            rotation: 3,
+            frame: 12,
+            images: vec![image(&format!("{folder}/a.png")), image("/other/b.exr")],
+            review: Some(SessionReview {
+                directory: folder.clone(),
+                patterns: vec![CellPattern {
+                    tail: "_diffuse.png".to_string(),
+                    regex_str: r"^(.+)_diffuse\.png$".to_string(),
+                }],
+                radix: "shot_001".to_string(),
+            }),
+            compare: Some(SessionCompare {
+                second: 1,
+                divider: 0.3,
+                vertical: false,
+                opacity: 0.75,
+            }),
+        };
+        let path = dir.path().join("compare.ivs");
+        session.write(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.contains("path = \"a.png\""));
+        assert_eq!(Session::read(&path), Some(session));
+        assert!(is_session(&path.to_string_lossy()));
+        assert!(!is_session(&format!("{folder}/a.png")));
+    }
+}